@@ -0,0 +1,185 @@
+//! Runs the fuzz targets from `fuzz/targets.rs` on randomly mutated input, for when cargo-fuzz
+//! isn't available.
+//!
+//! ```text
+//! cargo run --release --example fuzz -- [target|all] [iterations] [seed]
+//! ```
+//!
+//! Inputs start out as documents from the toml-test corpus and are mutated by splicing in
+//! tokens, deleting and duplicating ranges, and crossing over with other documents. Any input
+//! that makes a target panic is printed and the run stops.
+
+#[path = "../fuzz/targets.rs"]
+mod targets;
+
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+/// xorshift64*, which is plenty for generating test input and is reproducible from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Fragments of TOML syntax, and things that tend to break it.
+#[rustfmt::skip]
+const TOKENS: &[&[u8]] = &[
+    b"[", b"]", b"[[", b"]]", b"{", b"}", b"=", b".", b",", b"\"", b"'", b"\"\"\"", b"'''",
+    b"\\", b"\\u", b"\\U0010ffff", b"#", b"\n", b"\r\n", b"\r", b"\0", b" ", b"\t", b"true",
+    b"false", b"inf", b"-nan", b"0x", b"0o7", b"0b", b"1979-05-27", b"T", b"07:32:00", b".999",
+    b"Z", b"+01:00", b"+", b"-", b"_", b"e", b"E", b"\xc3\xa9", b"\x01", b"\x7f", b"\xff", b"1.0",
+    b"9223372036854775808", b"a", b"a.b", b"key = ", b"[[a]]\n", b"[a]\n", b"x = {y = 1}",
+    b"[[[[[[[[", b"]]]]]]]]", b"{a={b={c=", b"}}}",
+];
+
+/// Loads every document in the toml-test corpus.
+fn corpus() -> Vec<Vec<u8>> {
+    fn walk(dir: &Path, out: &mut Vec<Vec<u8>>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                walk(&path, out);
+            } else if path.extension().is_some_and(|e| e == "toml") {
+                if let Ok(data) = fs::read(&path) {
+                    out.push(data);
+                }
+            }
+        }
+    }
+
+    let mut out = vec![Vec::new()];
+    walk(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/toml-test"),
+        &mut out,
+    );
+    out.sort();
+    out
+}
+
+fn mutate(rng: &mut Rng, corpus: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = rng.pick(corpus).clone();
+    for _ in 0..1 + rng.below(8) {
+        let at = rng.below(data.len() + 1);
+        match rng.below(5) {
+            0 | 1 => {
+                let token = rng.pick(TOKENS);
+                data.splice(at..at, token.iter().copied());
+            }
+            2 => {
+                let end = (at + rng.below(16)).min(data.len());
+                data.drain(at..end);
+            }
+            3 => {
+                let end = (at + rng.below(32)).min(data.len());
+                let copy = data[at..end].to_vec();
+                let to = rng.below(data.len() + 1);
+                data.splice(to..to, copy);
+            }
+            _ => {
+                let other = rng.pick(corpus);
+                let from = rng.below(other.len() + 1);
+                let end = (from + rng.below(64)).min(other.len());
+                data.splice(at..at, other[from..end].iter().copied());
+            }
+        }
+    }
+    data
+}
+
+/// Runs `target` on `iterations` inputs, returning the first one that panics.
+fn run(target: targets::Target, iterations: u64, seed: u64) -> Result<(), Vec<u8>> {
+    let corpus = corpus();
+    let mut rng = Rng::new(seed);
+    for _ in 0..iterations {
+        let data = mutate(&mut rng, &corpus);
+        if panic::catch_unwind(|| target(&data)).is_err() {
+            return Err(data);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let name = args.first().map(String::as_str).unwrap_or("all");
+    let iterations: u64 = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(100_000);
+    let seed: u64 = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(0);
+
+    let targets: Vec<_> = targets::TARGETS
+        .iter()
+        .filter(|(n, _)| name == "all" || *n == name)
+        .collect();
+    if targets.is_empty() {
+        eprintln!("unknown target `{}`", name);
+        std::process::exit(2);
+    }
+
+    for (name, target) in targets {
+        println!("{}: {} iterations from seed {}", name, iterations, seed);
+        if let Err(data) = run(*target, iterations, seed) {
+            println!("{}: panicked on {:?}", name, String::from_utf8_lossy(&data));
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_survive_random_input() {
+        for (name, target) in targets::TARGETS {
+            if let Err(data) = run(*target, 2_000, 1) {
+                panic!("{} panicked on {:?}", name, String::from_utf8_lossy(&data));
+            }
+        }
+    }
+
+    #[test]
+    fn targets_survive_hostile_input() {
+        let deep = |open: &str, n: usize| open.repeat(n).into_bytes();
+        let inputs = [
+            deep("[", 100_000),
+            [b"a = ".as_slice(), &deep("[", 100_000)].concat(),
+            [b"a = ".as_slice(), &deep("{b=", 100_000)].concat(),
+            deep("a.", 100_000),
+            [b"[".as_slice(), &deep("a.", 100_000), b"a]"].concat(),
+            b"a = '''".to_vec(),
+            b"a = \"\"\"\\".to_vec(),
+            b"a = 1979-05-27T".to_vec(),
+            b"a = 0x".to_vec(),
+            b"a = +".to_vec(),
+            b"\xff".to_vec(),
+        ];
+        for data in &inputs {
+            for (_, target) in targets::TARGETS {
+                target(data);
+            }
+        }
+    }
+}
@@ -1 +1,771 @@
-fn main() {}
+//! The tickler: a grammar-based random TOML generator.
+//!
+//! Every generated document is valid, and comes with the symbols `Lex` is expected to produce
+//! and the table `parse` is expected to return. Documents can also be mutated in ways that are
+//! guaranteed to make them invalid, or scrambled at random to make sure nothing panics.
+//!
+//! ```text
+//! cargo run --example tickler -- [iterations] [seed]
+//! cargo run --example tickler -- --print [seed]
+//! ```
+//!
+//! The same checks run as tests with `cargo test --example tickler`.
+
+use std::panic;
+use toml_rust::{Date, Datetime, DocumentMut, Lex, Offset, Sym, Table, Time, Value};
+
+/// xorshift64*, which is plenty for generating test input and is reproducible from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// A generated document along with what it should lex and parse to.
+struct Document {
+    text: String,
+    syms: Vec<Sym>,
+    table: Table,
+}
+
+struct Generator {
+    rng: Rng,
+    text: String,
+    syms: Vec<Sym>,
+    newline: &'static str,
+    next_id: usize,
+    depth: usize,
+}
+
+impl Generator {
+    fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let newline = if rng.chance(20) { "\r\n" } else { "\n" };
+        Self {
+            rng,
+            text: String::new(),
+            syms: Vec::new(),
+            newline,
+            next_id: 0,
+            depth: 0,
+        }
+    }
+
+    fn fresh(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn emit(&mut self, s: &str) {
+        self.text.push_str(s);
+    }
+
+    fn sym(&mut self, s: &str, sym: Sym) {
+        self.text.push_str(s);
+        self.syms.push(sym);
+    }
+
+    fn ws(&mut self) {
+        let ws = *self.rng.pick(&["", "", " ", " ", "\t", "  "]);
+        self.emit(ws);
+    }
+
+    fn newline(&mut self) {
+        let newline = self.newline;
+        self.emit(newline);
+    }
+
+    /// Ends a line, possibly with a comment and some blank lines.
+    fn end_line(&mut self) {
+        if self.rng.chance(20) {
+            self.ws();
+            let comment = *self
+                .rng
+                .pick(&["#", "# comment", "#\tä 🐸 [x] = 1", "## #"]);
+            self.emit(comment);
+        }
+        self.newline();
+        while self.rng.chance(15) {
+            self.ws();
+            self.newline();
+        }
+    }
+
+    fn key(&mut self) -> String {
+        let id = self.fresh();
+        match self.rng.below(5) {
+            0 => {
+                self.sym(&format!("\"q {}\\t\\u00e9\"", id), Sym::String);
+                format!("q {}\té", id)
+            }
+            1 => {
+                self.sym(&format!("'l.{}'", id), Sym::String);
+                format!("l.{}", id)
+            }
+            _ => {
+                let prefix = *self.rng.pick(&["", "k", "Key_", "x-", "_", "-"]);
+                let key = format!("{}{}", prefix, id);
+                self.sym(&key, Sym::Key);
+                key
+            }
+        }
+    }
+
+    /// Writes `key = value`, or `key.key = value`, and inserts it into `table`.
+    fn keyval(&mut self, table: &mut Table) {
+        let key = self.key();
+        let inner = if self.rng.chance(25) {
+            self.ws();
+            self.emit(".");
+            self.ws();
+            Some(self.key())
+        } else {
+            None
+        };
+        self.ws();
+        self.sym("=", Sym::Assign);
+        self.ws();
+        let value = self.value();
+
+        let value = match inner {
+            Some(inner) => Value::Table(Table::from([(inner, value)])),
+            None => value,
+        };
+        table.insert(key, value);
+    }
+
+    fn value(&mut self) -> Value {
+        let kinds = if self.depth > 2 { 5 } else { 7 };
+        match self.rng.below(kinds) {
+            0 => self.string(),
+            1 => self.integer(),
+            2 => self.float(),
+            3 => {
+                let b = self.rng.chance(50);
+                self.sym(if b { "true" } else { "false" }, Sym::Bool);
+                Value::Boolean(b)
+            }
+            4 => self.datetime(),
+            5 => self.array(),
+            _ => self.inline_table(),
+        }
+    }
+
+    /// Joins randomly picked `(encoded, decoded)` pieces, never picking the same quote-like
+    /// piece twice in a row so the result can't contain a closing delimiter.
+    fn pieces(&mut self, pieces: &[(&str, &str)], quote: &str) -> (String, String) {
+        let mut encoded = String::new();
+        let mut decoded = String::new();
+        let mut last = "";
+        for _ in 0..self.rng.below(6) {
+            let (e, d) = *self.rng.pick(pieces);
+            if e == quote && last == quote {
+                continue;
+            }
+            encoded.push_str(e);
+            decoded.push_str(d);
+            last = e;
+        }
+        (encoded, decoded)
+    }
+
+    /// Picks whether a multiline string starts with a newline. A newline straight after the
+    /// opening delimiter is trimmed, whether it's the one picked here or the content's own.
+    fn first_newline(&mut self, encoded: &str, decoded: String) -> (&'static str, String) {
+        let first = if self.rng.chance(50) { "\n" } else { "" };
+        match first.is_empty() && encoded.starts_with('\n') {
+            true => (first, decoded[1..].to_string()),
+            false => (first, decoded),
+        }
+    }
+
+    fn string(&mut self) -> Value {
+        let (text, value) = match self.rng.below(4) {
+            0 => {
+                let (e, d) = self.pieces(
+                    &[
+                        ("abc", "abc"),
+                        (" ", " "),
+                        ("é🐸", "é🐸"),
+                        ("'", "'"),
+                        ("\\n", "\n"),
+                        ("\\t", "\t"),
+                        ("\\\"", "\""),
+                        ("\\\\", "\\"),
+                        ("\\b\\f\\r", "\u{8}\u{c}\r"),
+                        ("\\u00E9", "é"),
+                        ("\\U0001F600", "😀"),
+                    ],
+                    "",
+                );
+                (format!("\"{}\"", e), d)
+            }
+            1 => {
+                let (e, d) = self.pieces(
+                    &[
+                        ("abc", "abc"),
+                        ("\\", "\\"),
+                        ("\"", "\""),
+                        ("\t", "\t"),
+                        ("é", "é"),
+                    ],
+                    "",
+                );
+                (format!("'{}'", e), d)
+            }
+            2 => {
+                let (e, d) = self.pieces(
+                    &[
+                        ("abc", "abc"),
+                        ("\n", "\n"),
+                        ("\"", "\""),
+                        ("\\\"\\\"\\\"", "\"\"\""),
+                        ("\\\n   z", "z"),
+                        ("\\n", "\n"),
+                    ],
+                    "\"",
+                );
+                let (first, d) = self.first_newline(&e, d);
+                (format!("\"\"\"{}{}\"\"\"", first, e), d)
+            }
+            _ => {
+                let (e, d) = self.pieces(
+                    &[
+                        ("abc", "abc"),
+                        ("\n", "\n"),
+                        ("'", "'"),
+                        ("\\n", "\\n"),
+                        ("\"\"\"", "\"\"\""),
+                    ],
+                    "'",
+                );
+                let (first, d) = self.first_newline(&e, d);
+                (format!("'''{}{}'''", first, e), d)
+            }
+        };
+        self.sym(&text, Sym::String);
+        Value::String(value)
+    }
+
+    /// Formats `digits`, randomly separating them with underscores.
+    fn underscored(&mut self, digits: &str) -> String {
+        let mut out = String::new();
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && self.rng.chance(15) {
+                out.push('_');
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    fn integer(&mut self) -> Value {
+        let value = match self.rng.below(8) {
+            0 => i64::MIN,
+            1 => i64::MAX,
+            2 => 0,
+            _ => (self.rng.next() >> self.rng.below(64)) as i64 * self.rng.pick(&[1, -1]),
+        };
+        let text = match self.rng.below(6) {
+            0 if value >= 0 => format!("0x{}", self.underscored(&format!("{:x}", value))),
+            1 if value >= 0 => format!("0o{}", self.underscored(&format!("{:o}", value))),
+            2 if value >= 0 => format!("0b{}", self.underscored(&format!("{:b}", value))),
+            _ => {
+                let digits = self.underscored(&value.unsigned_abs().to_string());
+                let sign = match value < 0 {
+                    true => "-",
+                    false => *self.rng.pick(&["", "", "+"]),
+                };
+                format!("{}{}", sign, digits)
+            }
+        };
+        self.sym(&text, Sym::Integer);
+        Value::Integer(value)
+    }
+
+    fn float(&mut self) -> Value {
+        let sign = *self.rng.pick(&["", "+", "-"]);
+        let text = match self.rng.below(6) {
+            0 => format!("{}inf", sign),
+            1 => format!("{}nan", sign),
+            _ => {
+                let int = self.rng.below(100_000).to_string();
+                let mut text = format!("{}{}", sign, self.underscored(&int));
+                let frac = self.rng.chance(70);
+                if frac {
+                    let frac = self.rng.below(1_000_000).to_string();
+                    text.push('.');
+                    text.push_str(&self.underscored(&frac));
+                }
+                if !frac || self.rng.chance(30) {
+                    let e = *self.rng.pick(&["e", "E"]);
+                    let exp_sign = *self.rng.pick(&["", "+", "-"]);
+                    text.push_str(&format!("{}{}{}", e, exp_sign, self.rng.below(300)));
+                }
+                text
+            }
+        };
+        self.sym(&text, Sym::Float);
+        let value = match text.trim_start_matches(['+', '-']) {
+            "inf" if sign == "-" => f64::NEG_INFINITY,
+            "inf" => f64::INFINITY,
+            "nan" => f64::NAN,
+            _ => text.replace('_', "").parse().unwrap(),
+        };
+        Value::Float(value)
+    }
+
+    fn datetime(&mut self) -> Value {
+        let date = Date {
+            year: 1000 + self.rng.below(2000) as u16,
+            month: 1 + self.rng.below(12) as u8,
+            day: 1 + self.rng.below(28) as u8,
+        };
+        let nanosecond = match self.rng.below(3) {
+            0 => 0,
+            1 => self.rng.below(1000) as u32 * 1_000_000,
+            _ => self.rng.below(1_000_000_000) as u32,
+        };
+        let time = Time {
+            hour: self.rng.below(24) as u8,
+            minute: self.rng.below(60) as u8,
+            second: self.rng.below(60) as u8,
+            nanosecond,
+        };
+        let offset = match self.rng.below(3) {
+            0 => Offset::Z,
+            _ => Offset::Custom {
+                minutes: self.rng.below(24 * 60) as i16 * self.rng.pick(&[1, -1]),
+            },
+        };
+
+        let (date, time, offset) = match self.rng.below(4) {
+            0 => (Some(date), Some(time), Some(offset)),
+            1 => (Some(date), Some(time), None),
+            2 => (Some(date), None, None),
+            _ => (None, Some(time), None),
+        };
+        let datetime = Datetime { date, time, offset };
+
+        // Display always uses `T` and `Z`, so vary the spelling of those here.
+        let mut text = datetime.to_string();
+        if date.is_some() && time.is_some() {
+            let sep = *self.rng.pick(&["T", "t", " "]);
+            text.replace_range(10..11, sep);
+        }
+        if offset == Some(Offset::Z) && self.rng.chance(50) {
+            text = text.replace('Z', "z");
+        }
+        self.sym(&text, Sym::DateTime);
+        Value::Datetime(datetime)
+    }
+
+    /// Whitespace, newlines and comments, as allowed between array elements.
+    fn array_ws(&mut self) {
+        self.ws();
+        while self.rng.chance(20) {
+            if self.rng.chance(50) {
+                self.emit("# in array");
+            }
+            self.newline();
+            self.ws();
+        }
+    }
+
+    fn array(&mut self) -> Value {
+        self.depth += 1;
+        self.sym("[", Sym::Array);
+        self.array_ws();
+        let mut items = Vec::new();
+        for i in 0..self.rng.below(5) {
+            if i > 0 {
+                self.array_ws();
+                self.emit(",");
+                self.array_ws();
+            }
+            items.push(self.value());
+        }
+        if !items.is_empty() && self.rng.chance(30) {
+            self.array_ws();
+            self.emit(",");
+        }
+        self.array_ws();
+        self.sym("]", Sym::ArrayEnd);
+        self.depth -= 1;
+        Value::Array(items)
+    }
+
+    fn inline_table(&mut self) -> Value {
+        self.depth += 1;
+        self.sym("{", Sym::InlineTable);
+        self.ws();
+        let mut table = Table::new();
+        for i in 0..self.rng.below(4) {
+            if i > 0 {
+                self.emit(",");
+                self.ws();
+            }
+            self.keyval(&mut table);
+            self.ws();
+        }
+        self.sym("}", Sym::InlineTableEnd);
+        self.depth -= 1;
+        Value::Table(table)
+    }
+
+    /// Writes the key/value lines of a table body.
+    fn body(&mut self) -> Table {
+        let mut table = Table::new();
+        for _ in 0..self.rng.below(4) {
+            self.ws();
+            self.keyval(&mut table);
+            self.end_line();
+        }
+        table
+    }
+
+    /// Writes a `[header]` or `[[header]]` line, returning the keys it names.
+    fn header(&mut self, keys: Option<&[String]>, is_array: bool) -> Vec<String> {
+        let (open, close, sym) = match is_array {
+            true => ("[[", "]]", Sym::ArrayOfTable),
+            false => ("[", "]", Sym::Table),
+        };
+        self.sym(open, sym);
+        self.ws();
+        let keys = match keys {
+            // Repeated array headers reuse the keys as they were first written.
+            Some(keys) => {
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        self.emit(".");
+                    }
+                    let quoted = format!("{:?}", key);
+                    let bare = !key.is_empty()
+                        && key
+                            .bytes()
+                            .all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-');
+                    match bare {
+                        true => self.sym(key, Sym::Key),
+                        false => self.sym(&quoted.replace("\\u{e9}", "é"), Sym::String),
+                    }
+                }
+                keys.to_vec()
+            }
+            None => {
+                let mut keys = vec![self.key()];
+                if self.rng.chance(30) {
+                    self.ws();
+                    self.emit(".");
+                    self.ws();
+                    keys.push(self.key());
+                }
+                keys
+            }
+        };
+        self.ws();
+        self.emit(close);
+        self.end_line();
+        self.syms.push(Sym::TableEnd);
+        keys
+    }
+
+    fn document(mut self) -> Document {
+        let mut root = self.body();
+
+        for _ in 0..self.rng.below(5) {
+            let is_array = self.rng.chance(40);
+            let keys = self.header(None, is_array);
+            let value = if is_array {
+                let mut items = vec![Value::Table(self.body())];
+                for _ in 0..self.rng.below(3) {
+                    self.header(Some(&keys), true);
+                    items.push(Value::Table(self.body()));
+                }
+                Value::Array(items)
+            } else {
+                Value::Table(self.body())
+            };
+
+            let (last, parents) = keys.split_last().unwrap();
+            let mut table = &mut root;
+            for key in parents {
+                table = match table
+                    .entry(key.clone())
+                    .or_insert_with(|| Value::Table(Table::new()))
+                {
+                    Value::Table(t) => t,
+                    _ => unreachable!(),
+                };
+            }
+            table.insert(last.clone(), value);
+        }
+
+        self.syms.push(Sym::Eof);
+        Document {
+            text: self.text,
+            syms: self.syms,
+            table: root,
+        }
+    }
+}
+
+fn generate(seed: u64) -> Document {
+    Generator::new(seed).document()
+}
+
+/// Appends or splices in something that makes any document invalid.
+fn break_document(doc: &Document, seed: u64) -> String {
+    let mut rng = Rng::new(seed);
+    let mut text = doc.text.clone();
+    match rng.below(9) {
+        0 => text.push_str("\n= 1\n"),
+        1 => text.push_str("\nbroken = 01\n"),
+        2 => text.push_str("\n[unclosed\n"),
+        3 => text.push_str("\nbroken = \"\u{1}\"\n"),
+        4 => text.insert_str(0, "dup = 1\ndup = 2\n"),
+        5 => text.push_str("\n[dup]\n[dup]\n"),
+        6 => text.push_str("\nbroken = [1 2]\n"),
+        7 => text.push_str("\nbroken = { a = 1,\n}\n"),
+        _ => match text.find(['"', '\'']) {
+            // The first quote in a document always opens a string.
+            Some(index) => text.truncate(index + 1),
+            None => text.push_str("\nbroken = \"\n"),
+        },
+    }
+    text
+}
+
+/// Scrambles a document at random. The result may or may not be valid.
+fn scramble(doc: &Document, seed: u64) -> String {
+    let mut rng = Rng::new(seed);
+    let mut chars: Vec<char> = doc.text.chars().collect();
+    for _ in 0..1 + rng.below(4) {
+        let index = rng.below(chars.len() + 1);
+        let c = *rng.pick(&[
+            '\0', '\u{1}', '\r', '\n', ' ', '#', '"', '\'', '=', '.', ',', '[', ']', '{', '}',
+            '\\', '0', 'e', '_', '+', '-', ':', 'T', 'é',
+        ]);
+        match rng.below(3) {
+            0 => chars.insert(index, c),
+            1 if index < chars.len() => {
+                chars.remove(index);
+            }
+            _ if index < chars.len() => chars[index] = c,
+            _ => chars.push(c),
+        }
+    }
+    chars.into_iter().collect()
+}
+
+/// Compares values, treating NaN as equal to itself.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(x), Value::Float(y)) => x == y || (x.is_nan() && y.is_nan()),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Table(a), Value::Table(b)) => same_table(a, b),
+        _ => a == b,
+    }
+}
+
+fn same_table(a: &Table, b: &Table) -> bool {
+    a.len() == b.len() && a.iter().all(|(k, v)| b.get(k).is_some_and(|w| same(v, w)))
+}
+
+/// Runs the lexer, parser and validator, reporting a panic or any disagreement between them as
+/// an error.
+fn check_no_panic(text: &str) -> Result<(), String> {
+    let (lexed, parsed, validated) = panic::catch_unwind(|| {
+        let mut lex = Lex::new(text);
+        let lexed = lex.scan().is_ok();
+        let parsed = toml_rust::parse(text).map(drop);
+        (lexed, parsed, toml_rust::validate(text))
+    })
+    .map_err(|_| "panicked".to_string())?;
+
+    if parsed.is_ok() && !lexed {
+        return Err("parsed a document the lexer rejected".to_string());
+    }
+    if validated != parsed {
+        return Err(format!(
+            "validate returned {:?} but parse returned {:?}",
+            validated, parsed
+        ));
+    }
+    Ok(())
+}
+
+/// Removes and replaces each top-level key of an editable copy of the document, checking that
+/// only that key changes.
+fn check_edits(text: &str, table: &Table) -> Result<(), String> {
+    let doc = DocumentMut::parse(text).map_err(|e| format!("DocumentMut failed: {}", e))?;
+    if doc.as_str() != text {
+        return Err("DocumentMut changed the text".to_string());
+    }
+    for key in table.keys() {
+        let mut expected = table.clone();
+        expected.remove(key);
+        let mut edited = doc.clone();
+        edited
+            .remove(key)
+            .map_err(|e| format!("removing {:?} failed: {}", key, e))?;
+        if !same_table(edited.as_table(), &expected) {
+            return Err(format!("removing {:?} produced\n{}", key, edited));
+        }
+
+        expected.insert(key.clone(), Value::Integer(1));
+        let mut edited = doc.clone();
+        edited
+            .insert(key, 1)
+            .map_err(|e| format!("replacing {:?} failed: {}", key, e))?;
+        if !same_table(edited.as_table(), &expected) {
+            return Err(format!("replacing {:?} produced\n{}", key, edited));
+        }
+    }
+    Ok(())
+}
+
+fn check_valid(doc: &Document) -> Result<(), String> {
+    check_no_panic(&doc.text)?;
+
+    let mut lex = Lex::new(&doc.text);
+    lex.scan().map_err(|e| format!("lex failed: {}", e))?;
+    let syms: Vec<Sym> = lex.symbols.iter().map(|s| s.sym()).collect();
+    if syms != doc.syms {
+        return Err(format!(
+            "lexed to\n  {:?}\nexpected\n  {:?}",
+            syms, doc.syms
+        ));
+    }
+
+    let table = toml_rust::parse(&doc.text).map_err(|e| format!("parse failed: {}", e))?;
+    if !same_table(&table, &doc.table) {
+        return Err(format!(
+            "parsed to\n  {:?}\nexpected\n  {:?}",
+            table, doc.table
+        ));
+    }
+
+    check_edits(&doc.text, &table)?;
+
+    let text = toml_rust::to_string(&table).map_err(|e| format!("serialize failed: {}", e))?;
+    match toml_rust::parse(&text) {
+        Ok(reparsed) if same_table(&reparsed, &table) => Ok(()),
+        Ok(reparsed) => Err(format!(
+            "round trip through\n{}\nproduced\n  {:?}",
+            text, reparsed
+        )),
+        Err(e) => Err(format!("reparse failed: {}\n{}", e, text)),
+    }
+}
+
+fn check_invalid(text: &str) -> Result<(), String> {
+    check_no_panic(text)?;
+    match toml_rust::parse(text) {
+        Ok(_) => Err("invalid document was accepted".to_string()),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Runs every check for the documents generated from `seed`.
+fn tickle(seed: u64) -> Result<(), String> {
+    let doc = generate(seed);
+    let context = |what: &str, text: &str, e: String| {
+        format!("seed {}: {}: {}\n--- document ---\n{}", seed, what, e, text)
+    };
+
+    check_valid(&doc).map_err(|e| context("valid", &doc.text, e))?;
+
+    let broken = break_document(&doc, seed);
+    check_invalid(&broken).map_err(|e| context("invalid", &broken, e))?;
+
+    let scrambled = scramble(&doc, seed);
+    check_no_panic(&scrambled).map_err(|e| context("scrambled", &scrambled, e))?;
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(String::as_str) == Some("--print") {
+        let seed = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+        print!("{}", generate(seed).text);
+        return;
+    }
+
+    let iterations: u64 = args.first().and_then(|s| s.parse().ok()).unwrap_or(10_000);
+    let start: u64 = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+
+    // Panics are reported as failures, so keep the default hook from spamming stderr.
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut failures = 0;
+    for seed in start..start + iterations {
+        if let Err(e) = tickle(seed) {
+            failures += 1;
+            println!("{}\n", e);
+        }
+    }
+    println!("{} of {} seeds failed", failures, iterations);
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_documents() {
+        for seed in 0..2_000 {
+            if let Err(e) = tickle(seed) {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn generator_covers_every_sym() {
+        let mut seen = Vec::new();
+        for seed in 0..200 {
+            for sym in generate(seed).syms {
+                if !seen.contains(&sym) {
+                    seen.push(sym);
+                }
+            }
+        }
+        assert_eq!(seen.len(), 15, "only generated {:?}", seen);
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        assert_eq!(generate(42).text, generate(42).text);
+        assert_ne!(generate(42).text, generate(43).text);
+    }
+}
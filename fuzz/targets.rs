@@ -0,0 +1,75 @@
+//! Fuzz targets shared by the cargo-fuzz harness in this directory and by
+//! `examples/fuzz.rs`. Each target takes arbitrary bytes and must return without panicking,
+//! whatever the input.
+
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use toml_rust::{Datetime, Lex, Table, Value};
+
+pub type Target = fn(&[u8]);
+
+/// Every target, by name.
+#[allow(dead_code)]
+pub const TARGETS: &[(&str, Target)] = &[("lex", lex), ("parse", parse), ("de", de)];
+
+pub fn lex(data: &[u8]) {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let mut lex = Lex::new(text);
+        if lex.scan().is_ok() {
+            for symbol in &lex.symbols {
+                let span = symbol.span();
+                assert!(span.lo() <= span.hi() && span.hi() <= text.len() + 1);
+            }
+        }
+    }
+}
+
+pub fn parse(data: &[u8]) {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let parsed = toml_rust::parse(text);
+        assert_eq!(
+            toml_rust::validate(text),
+            parsed.as_ref().map(drop).map_err(Clone::clone)
+        );
+        if let Ok(table) = parsed {
+            // Anything that parses must serialize, and read back the same.
+            let text = toml_rust::to_string(&table).expect("serialize");
+            let reparsed = toml_rust::parse(&text).expect("reparse");
+            assert_eq!(
+                toml_rust::tagged::table_to_json(&reparsed),
+                toml_rust::tagged::table_to_json(&table)
+            );
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+enum Kind {
+    Unit,
+    Newtype(i32),
+    Tuple(u8, String),
+    Struct { a: Option<f32> },
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+struct Config {
+    name: Option<String>,
+    port: Option<u16>,
+    ratio: Option<f64>,
+    enabled: Option<bool>,
+    when: Option<Datetime>,
+    tags: Option<Vec<String>>,
+    pair: Option<(i8, char)>,
+    kinds: Option<Vec<Kind>>,
+    nested: Option<HashMap<String, Config>>,
+    any: Option<Value>,
+}
+
+pub fn de(data: &[u8]) {
+    let _ = toml_rust::from_reader::<_, Table>(data);
+    let _ = toml_rust::from_reader::<_, Value>(data);
+    let _ = toml_rust::from_reader::<_, Config>(data);
+    let _ = toml_rust::from_reader::<_, HashMap<String, Kind>>(data);
+}
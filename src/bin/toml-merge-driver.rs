@@ -0,0 +1,99 @@
+//! A git merge driver for TOML files.
+//!
+//! ```text
+//! toml-merge-driver [--identify PATH=KEY]... <BASE> <OURS> <THEIRS>
+//! ```
+//!
+//! Merges the changes from `BASE` to `THEIRS` into `OURS` and writes the result to `OURS`, as git
+//! expects. If some values conflict, they're written with conflict markers and it exits with 1.
+//! Files that aren't valid TOML are left to `git merge-file`. To use it, add to `.gitconfig`:
+//!
+//! ```text
+//! [merge "toml"]
+//!     name = TOML merge driver
+//!     driver = toml-merge-driver %O %A %B
+//! ```
+//!
+//! and to `.gitattributes`:
+//!
+//! ```text
+//! *.toml merge=toml
+//! ```
+
+use std::fs;
+use std::process::{Command, ExitCode};
+use toml_rust::three_way::ThreeWay;
+
+const USAGE: &str = "usage: toml-merge-driver [--identify PATH=KEY]... <BASE> <OURS> <THEIRS>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            // Anything above 128 makes git give up on the merge.
+            ExitCode::from(255)
+        }
+    }
+}
+
+fn run(mut args: &[String]) -> Result<ExitCode, String> {
+    if let [flag] = args {
+        if flag == "-h" || flag == "--help" {
+            println!("{}", USAGE);
+            return Ok(ExitCode::SUCCESS);
+        }
+    }
+    let mut three_way = ThreeWay::new();
+    while let [flag, spec, rest @ ..] = args {
+        if flag != "--identify" {
+            break;
+        }
+        let (path, key) = spec
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected PATH=KEY, found `{}`", spec))?;
+        let path = path.parse().map_err(|e| format!("<path>: {}", e))?;
+        three_way.identify(path, key);
+        args = rest;
+    }
+    let [base, ours, theirs] = args else {
+        return Err(USAGE.to_string());
+    };
+    let read = |path: &String| fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e));
+    let merged = match three_way.run(&read(base)?, &read(ours)?, &read(theirs)?) {
+        Ok(merged) => merged,
+        Err(e) => {
+            eprintln!("toml-merge-driver: {}; merging by line", e);
+            return merge_file(base, ours, theirs);
+        }
+    };
+    for conflict in merged.conflicts() {
+        eprintln!("toml-merge-driver: conflict at `{}`", conflict.path());
+    }
+    let text = if merged.is_clean() {
+        merged.document().as_str().to_string()
+    } else {
+        merged.with_markers()
+    };
+    fs::write(ours, text).map_err(|e| format!("{}: {}", ours, e))?;
+    Ok(if merged.is_clean() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+/// Falls back to git's own line-based merge, which writes to `ours` as well.
+fn merge_file(base: &str, ours: &str, theirs: &str) -> Result<ExitCode, String> {
+    let status = Command::new("git")
+        .args(["merge-file", "-L", "ours", "-L", "base", "-L", "theirs"])
+        .args([ours, base, theirs])
+        .status()
+        .map_err(|e| format!("git merge-file: {}", e))?;
+    Ok(match status.code() {
+        Some(0) => ExitCode::SUCCESS,
+        Some(code) if (1..128).contains(&code) => ExitCode::from(1),
+        _ => return Err("git merge-file failed".to_string()),
+    })
+}
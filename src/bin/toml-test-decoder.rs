@@ -0,0 +1,23 @@
+//! Reads a TOML document on stdin and writes it as toml-test tagged JSON on stdout.
+
+use std::io::{self, Read};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut input = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut input) {
+        eprintln!("error: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    match toml_rust::parse(&input) {
+        Ok(table) => {
+            println!("{}", toml_rust::tagged::table_to_json(&table));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Reads toml-test tagged JSON on stdin and writes it as a TOML document on stdout.
+
+use std::io::{self, Read};
+use std::process::ExitCode;
+
+fn encode(input: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let json: serde_json::Value = serde_json::from_str(input)?;
+    let table = toml_rust::tagged::table_from_json(&json)?;
+    Ok(toml_rust::to_string(&table)?)
+}
+
+fn main() -> ExitCode {
+    let mut input = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut input) {
+        eprintln!("error: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    match encode(&input) {
+        Ok(output) => {
+            print!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
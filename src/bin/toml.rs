@@ -0,0 +1,208 @@
+//! Command line tools for working with TOML documents.
+//!
+//! ```text
+//! toml query <QUERY> [FILE]
+//! toml diff [--identify PATH=KEY]... <OLD> <NEW>
+//! toml codegen [--name NAME] <FILE>...
+//! toml export [--prefix PREFIX] [--separator SEP] [--nested json|indices|error] [--table PATH]
+//!             [FILE]
+//! ```
+//!
+//! Documents are read from `FILE`, or from stdin if it's left out or `-`. `--identify` matches
+//! the items of the arrays at `PATH` by their key `KEY` instead of by position. `codegen` prints
+//! Rust types for the sample files, with the struct for whole documents named `NAME`. `export`
+//! prints the values of the document, or of the table at `PATH`, as `KEY=value` lines for a
+//! shell, with arrays written as `--nested` says.
+
+use std::fs;
+use std::io::{self, Read};
+use std::process::ExitCode;
+use toml_rust::codegen::Codegen;
+use toml_rust::diff::{self, Diff};
+use toml_rust::export::{Export, Nested};
+use toml_rust::query::Query;
+
+const USAGE: &str = "usage: toml query <QUERY> [FILE]
+       toml diff [--identify PATH=KEY]... <OLD> <NEW>
+       toml codegen [--name NAME] <FILE>...
+       toml export [--prefix PREFIX] [--separator SEP] [--nested json|indices|error]
+                   [--table PATH] [FILE]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("query") => query(&args[1..]),
+        Some("diff") => diff(&args[1..]),
+        Some("codegen") => codegen(&args[1..]),
+        Some("export") => export(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Reads the document named by `path`, returning it along with a name to report it by.
+fn read_input(path: Option<&str>) -> Result<(String, String), String> {
+    match path {
+        None | Some("-") => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("<stdin>: {}", e))?;
+            Ok(("<stdin>".to_string(), text))
+        }
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            Ok((path.to_string(), text))
+        }
+    }
+}
+
+/// Formats an error in `text` as `name:line:col: message`, if it has a position.
+fn located(name: &str, text: &str, e: toml_rust::Error) -> String {
+    match e.pos() {
+        Some(pos) => {
+            let (line, col) = toml_rust::Span::new(pos, pos).line_col(text);
+            format!("{}:{}:{}: {}", name, line, col, e)
+        }
+        None => format!("{}: {}", name, e),
+    }
+}
+
+/// Prints each value the query selects as `name:line:col: path = value`.
+fn query(args: &[String]) -> Result<(), String> {
+    let (query, path) = match args {
+        [query] => (query, None),
+        [query, path] => (query, Some(path.as_str())),
+        _ => return Err(USAGE.to_string()),
+    };
+    let query = Query::parse(query).map_err(|e| located("<query>", query, e))?;
+    let (name, text) = read_input(path)?;
+    let matches = query.run(&text).map_err(|e| located(&name, &text, e))?;
+    for m in matches {
+        let (line, col) = m.line_col(&text);
+        let path = m.path().to_string();
+        let path = if path.is_empty() { "." } else { &path };
+        println!("{}:{}:{}: {} = {}", name, line, col, path, m.value());
+    }
+    Ok(())
+}
+
+/// Prints the changes from one document to another, one per line.
+fn diff(mut args: &[String]) -> Result<(), String> {
+    let mut diff = Diff::new();
+    while let [flag, spec, rest @ ..] = args {
+        if flag != "--identify" {
+            break;
+        }
+        let (path, key) = spec
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected PATH=KEY, found `{}`", spec))?;
+        let path = path.parse().map_err(|e| located("<path>", path, e))?;
+        diff.identify(path, key);
+        args = rest;
+    }
+    let [old, new] = args else {
+        return Err(USAGE.to_string());
+    };
+    let (old_name, old) = read_input(Some(old))?;
+    let (new_name, new) = read_input(Some(new))?;
+    let changes = diff.run(&old, &new).map_err(|e| match e {
+        toml_rust::Error::Source { name, error } if name == "old" => {
+            located(&old_name, &old, *error)
+        }
+        toml_rust::Error::Source { error, .. } => located(&new_name, &new, *error),
+        e => e.to_string(),
+    })?;
+    print!("{}", diff::report(&changes, &old, &new));
+    Ok(())
+}
+
+/// Prints Rust types that the sample files deserialize into.
+fn codegen(mut args: &[String]) -> Result<(), String> {
+    let mut codegen = Codegen::new();
+    if let [flag, name, rest @ ..] = args {
+        if flag == "--name" {
+            codegen.name(name);
+            args = rest;
+        }
+    }
+    if args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let samples = args
+        .iter()
+        .map(|path| read_input(Some(path)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let code = codegen
+        .generate(samples.iter().map(|(_, text)| text.as_str()))
+        .map_err(|e| {
+            // Samples are named by their number, counting from 1.
+            let sample = match &e {
+                toml_rust::Error::Source { name, .. } => name
+                    .strip_prefix("sample ")
+                    .and_then(|i| i.parse::<usize>().ok())
+                    .and_then(|i| samples.get(i.checked_sub(1)?)),
+                _ => None,
+            };
+            match (sample, e) {
+                (Some((name, text)), toml_rust::Error::Source { error, .. }) => {
+                    located(name, text, *error)
+                }
+                (_, e) => e.to_string(),
+            }
+        })?;
+    print!("{}", code);
+    Ok(())
+}
+
+/// Prints the values of a document as `KEY=value` lines.
+fn export(mut args: &[String]) -> Result<(), String> {
+    let mut export = Export::new();
+    let mut table_path = None;
+    while let [flag, arg, rest @ ..] = args {
+        match flag.as_str() {
+            "--prefix" => export.prefix(arg),
+            "--separator" => export.separator(arg),
+            "--nested" => export.nested(match arg.as_str() {
+                "json" => Nested::Json,
+                "indices" => Nested::Indices,
+                "error" => Nested::Error,
+                _ => return Err(format!("expected json, indices or error, found `{}`", arg)),
+            }),
+            "--table" => {
+                let path: toml_rust::Path = arg.parse().map_err(|e| located("<path>", arg, e))?;
+                table_path = Some(path);
+            }
+            _ => break,
+        }
+        args = rest;
+    }
+    let path = match args {
+        [] => None,
+        [path] => Some(path.as_str()),
+        _ => return Err(USAGE.to_string()),
+    };
+    let (name, text) = read_input(path)?;
+    let table = toml_rust::parse(&text).map_err(|e| located(&name, &text, e))?;
+    let table = match &table_path {
+        None => &table,
+        Some(path) => match path.get_in(&table) {
+            Some(toml_rust::Value::Table(table)) => table,
+            Some(_) => return Err(format!("`{}` is not a table", path)),
+            None => return Err(format!("`{}` is missing", path)),
+        },
+    };
+    let lines = export.render(table).map_err(|e| e.to_string())?;
+    print!("{}", lines);
+    Ok(())
+}
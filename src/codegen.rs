@@ -0,0 +1,356 @@
+//! Generation of Rust types with serde derives from sample documents.
+//!
+//! Tables become structs, named after their keys, and arrays become `Vec`s, so arrays of tables
+//! become `Vec`s of a struct named after the singular of their key. Date-times are `Datetime`s,
+//! and places where several types of value are found are `Value`s. When several samples are
+//! given, keys missing from some of them are `Option`s.
+//!
+//! Keys become snake case field names, with `#[serde(rename)]` when that changes them. Struct
+//! names are made unique by numbering them.
+//!
+//! ```
+//! use toml_rust::codegen::Codegen;
+//!
+//! let code = Codegen::new().generate(["[[servers]]\nname = 'a'\ntls = false\n"]).unwrap();
+//! assert_eq!(code, "\
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+//! pub struct Config {
+//!     pub servers: Vec<Server>,
+//! }
+//!
+//! #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+//! pub struct Server {
+//!     pub name: String,
+//!     pub tls: bool,
+//! }
+//! ");
+//! ```
+
+use crate::error::Result;
+use crate::parse;
+use crate::shape::Shape;
+use crate::value::Value;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Generates the types for a set of samples.
+#[derive(Clone, Debug)]
+pub struct Codegen {
+    name: String,
+}
+
+impl Default for Codegen {
+    fn default() -> Self {
+        Codegen {
+            name: "Config".to_string(),
+        }
+    }
+}
+
+impl Codegen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the name of the struct for whole documents. The default is `Config`.
+    pub fn name(&mut self, name: impl Into<String>) {
+        self.name = name.into();
+    }
+
+    /// Returns Rust code declaring types that `samples` deserialize into, the type of whole
+    /// documents first. Errors in the samples are reported as in the source `sample N`, counting
+    /// from 1.
+    pub fn generate<'a>(&self, samples: impl IntoIterator<Item = &'a str>) -> Result<String> {
+        let mut shape = Shape::default();
+        for (i, sample) in samples.into_iter().enumerate() {
+            let table =
+                parse::parse(sample).map_err(|e| e.in_source(format!("sample {}", i + 1)))?;
+            shape.add(&Value::Table(table));
+        }
+        let mut types = Types::new();
+        types.declare(&self.name, &shape);
+
+        let mut code = "use serde::{Deserialize, Serialize};\n".to_string();
+        for declaration in types.declarations {
+            code.push('\n');
+            code.push_str(&declaration);
+        }
+        Ok(code)
+    }
+}
+
+struct Types {
+    names: HashSet<String>,
+    declarations: Vec<String>,
+}
+
+impl Types {
+    /// Starts with the names the generated code uses taken, so no struct shadows them.
+    fn new() -> Self {
+        Types {
+            names: USED_NAMES.iter().map(|name| name.to_string()).collect(),
+            declarations: Vec::new(),
+        }
+    }
+
+    /// Declares a struct for the tables `shape` describes, returning its name.
+    fn declare(&mut self, name: &str, shape: &Shape) -> String {
+        let mut unique = name.to_string();
+        for n in 2.. {
+            if self.names.insert(unique.clone()) {
+                break;
+            }
+            unique = format!("{}{}", name, n);
+        }
+        // Reserve the place first, so that structs come before the ones they contain.
+        let index = self.declarations.len();
+        self.declarations.push(String::new());
+
+        let mut code = String::new();
+        code.push_str("#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]\n");
+        writeln!(code, "pub struct {} {{", unique).unwrap();
+        let mut fields = HashSet::new();
+        for (key, (count, value)) in &shape.properties {
+            let mut ty = self.type_of(key, value);
+            if *count < shape.tables {
+                ty = format!("Option<{}>", ty);
+            }
+            let field = field_name(key);
+            let mut unique = field.clone();
+            for n in 2.. {
+                if fields.insert(unique.clone()) {
+                    break;
+                }
+                unique = format!("{}_{}", field, n);
+            }
+            if unique.trim_start_matches("r#") != key {
+                // Debug formatting escapes strings the way Rust literals do.
+                writeln!(code, "    #[serde(rename = {:?})]", key).unwrap();
+            }
+            writeln!(code, "    pub {}: {},", unique, ty).unwrap();
+        }
+        code.push_str("}\n");
+        self.declarations[index] = code;
+        unique
+    }
+
+    /// Returns the type of the values at the key `key` that `shape` describes.
+    fn type_of(&mut self, key: &str, shape: &Shape) -> String {
+        let mut types = Vec::new();
+        if shape.strings {
+            types.push("String".to_string());
+        }
+        if !shape.datetimes.is_empty() {
+            types.push("toml_rust::Datetime".to_string());
+        }
+        match (shape.integers, shape.floats) {
+            (true, false) => types.push("i64".to_string()),
+            (_, true) => types.push("f64".to_string()),
+            _ => {}
+        }
+        if shape.booleans {
+            types.push("bool".to_string());
+        }
+        if shape.arrays {
+            types.push(match &shape.items {
+                Some(items) => format!("Vec<{}>", self.type_of(&singular(key), items)),
+                None => "Vec<toml_rust::Value>".to_string(),
+            });
+        }
+        if shape.tables > 0 {
+            types.push(String::new());
+        }
+        match types.as_mut_slice() {
+            [ty] if ty.is_empty() => self.declare(&type_name(key), shape),
+            [ty] => std::mem::take(ty),
+            _ => "toml_rust::Value".to_string(),
+        }
+    }
+}
+
+/// Returns `key` as a field name in snake case, which may be a raw identifier.
+fn field_name(key: &str) -> String {
+    let mut name = String::new();
+    let mut prev = None::<char>;
+    for c in key.chars() {
+        if c.is_ascii_uppercase()
+            && prev.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+        {
+            name.push('_');
+        }
+        name.push(if c.is_ascii_alphanumeric() {
+            c.to_ascii_lowercase()
+        } else {
+            '_'
+        });
+        prev = Some(c);
+    }
+    match name.as_str() {
+        "" | "_" => "field".to_string(),
+        "self" | "super" | "crate" => format!("{}_", name),
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{}", name),
+        _ if KEYWORDS.contains(&name.as_str()) => format!("r#{}", name),
+        _ => name,
+    }
+}
+
+/// Returns `key` as a type name in upper camel case.
+fn type_name(key: &str) -> String {
+    let mut name = String::new();
+    for part in key.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            name.push(first.to_ascii_uppercase());
+            name.extend(chars);
+        }
+    }
+    match name.as_str() {
+        "" => "Table".to_string(),
+        "Self" => "Self_".to_string(),
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => format!("T{}", name),
+        _ => name,
+    }
+}
+
+/// Returns the singular of an English plural, roughly, for the items of an array.
+fn singular(key: &str) -> String {
+    if let Some(stem) = key.strip_suffix("ies").filter(|s| !s.is_empty()) {
+        format!("{}y", stem)
+    } else if let Some(stem) = key
+        .strip_suffix('s')
+        .filter(|s| !s.is_empty() && !s.ends_with('s'))
+    {
+        stem.to_string()
+    } else {
+        format!("{}_item", key)
+    }
+}
+
+/// Types and traits the generated code names without a path.
+const USED_NAMES: &[&str] = &[
+    "Box",
+    "Deserialize",
+    "Option",
+    "Result",
+    "Serialize",
+    "String",
+    "Vec",
+];
+
+/// Keywords that can be raw identifiers.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codegen_success() {
+        let samples = [
+            r#"
+title = "a"
+"Content-Type" = "text/plain"
+type = 1
+when = 1979-05-27T07:32:00Z
+mixed = 1
+empty = []
+
+[owner]
+userName = "me"
+
+[[servers]]
+port = 80
+ips = [["10.0.0.1"]]
+
+[[servers]]
+port = 80.5
+"#,
+            r#"
+title = "b"
+type = 2
+when = 1979-05-27T07:32:00Z
+mixed = "x"
+empty = []
+"2fa" = true
+
+[[servers]]
+port = 1
+owner = { name = "x" }
+"#,
+        ];
+        let mut codegen = Codegen::new();
+        codegen.name("App");
+        assert_eq!(
+            codegen.generate(samples).unwrap(),
+            r#"use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct App {
+    #[serde(rename = "2fa")]
+    pub _2fa: Option<bool>,
+    #[serde(rename = "Content-Type")]
+    pub content_type: Option<String>,
+    pub empty: Vec<toml_rust::Value>,
+    pub mixed: toml_rust::Value,
+    pub owner: Option<Owner>,
+    pub servers: Vec<Server>,
+    pub title: String,
+    pub r#type: i64,
+    pub when: toml_rust::Datetime,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Owner {
+    #[serde(rename = "userName")]
+    pub user_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Server {
+    pub ips: Option<Vec<Vec<String>>>,
+    pub owner: Option<Owner2>,
+    pub port: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Owner2 {
+    pub name: String,
+}
+"#
+        );
+
+        let code = Codegen::new()
+            .generate(["\"x\\u0001\\b\\\"\" = 1"])
+            .unwrap();
+        assert!(
+            code.contains("    #[serde(rename = \"x\\u{1}\\u{8}\\\"\")]\n    pub x___: i64,\n"),
+            "{}",
+            code
+        );
+        let code = Codegen::new()
+            .generate(["[option]\na = 1\n[vec]\n", "[vec]\n"])
+            .unwrap();
+        assert!(
+            code.contains("    pub option: Option<Option2>,\n    pub vec: Vec2,\n"),
+            "{}",
+            code
+        );
+        assert!(code.contains("pub struct Option2 {"), "{}", code);
+        assert!(code.contains("pub struct Vec2 {"), "{}", code);
+        assert_eq!(field_name("a-b"), "a_b");
+        assert_eq!(field_name("self"), "self_");
+        assert_eq!(type_name("my-table"), "MyTable");
+        assert_eq!(singular("entries"), "entry");
+        assert_eq!(singular("class"), "class_item");
+        let err = Codegen::new().generate(["a = "]).unwrap_err();
+        assert_eq!(err.to_string(), "sample 1: Unexpected { pos: 4 }");
+    }
+}
@@ -0,0 +1,319 @@
+//! Loading a base file along with a directory of fragments, as in `/etc/app/conf.d/*.toml`.
+//!
+//! Every `.toml` file in the directory is merged over the base file, in lexical order of file
+//! names, so packages and administrators can drop in fragments like `10-defaults.toml` and
+//! `50-local.toml`. Hidden files are skipped. The result is a `Merged`, whose origins name the
+//! fragment that defined each key.
+
+use crate::error::{Error, Result};
+use crate::lex::Span;
+use crate::merge::{ArrayStrategy, Merge, Merged};
+use crate::parse::{self, Node, NodeKind};
+use crate::path::Path;
+use crate::value::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{self as fs_path, PathBuf};
+
+/// Loads a directory of fragments.
+#[derive(Clone, Debug, Default)]
+pub struct ConfDir {
+    base: Option<PathBuf>,
+    reject_conflicts: bool,
+}
+
+impl ConfDir {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the file the fragments are merged over.
+    pub fn base(&mut self, path: impl Into<PathBuf>) {
+        self.base = Some(path.into());
+    }
+
+    /// Makes it an error for two fragments to set the same key to different values, rather than
+    /// the later one winning. Tables don't conflict, as they're merged, and neither do arrays
+    /// that aren't replaced. The base file may be overridden either way.
+    pub fn reject_conflicts(&mut self, reject: bool) {
+        self.reject_conflicts = reject;
+    }
+
+    /// Loads the base file and the fragments in `dir`, merging them the default way.
+    pub fn load(&self, dir: impl AsRef<fs_path::Path>) -> Result<Merged> {
+        let mut merge = Merge::new();
+        self.load_into(&mut merge, dir)?;
+        merge.finish()
+    }
+
+    /// Adds the base file and the fragments in `dir` to `merge` as layers. This way, strategies
+    /// for arrays can be set on `merge`. A missing directory has no fragments.
+    pub fn load_into(&self, merge: &mut Merge, dir: impl AsRef<fs_path::Path>) -> Result<()> {
+        let read = |path: &fs_path::Path| {
+            let name = path.display().to_string();
+            match fs::read_to_string(path) {
+                Ok(text) => Ok((name, text)),
+                Err(e) => Err(Error::Message(format!("{}: {}", name, e))),
+            }
+        };
+        if let Some(base) = &self.base {
+            let (name, text) = read(base)?;
+            merge.add(name, text)?;
+        }
+
+        let mut seen = BTreeMap::new();
+        for path in fragments(dir.as_ref())? {
+            let (name, text) = read(&path)?;
+            if self.reject_conflicts {
+                let root = parse::parse_nodes(&text).map_err(|e| e.in_source(&name))?;
+                let root = Node {
+                    span: Span::new(0, text.len()),
+                    kind: NodeKind::Table(root),
+                };
+                let fragment = Fragment {
+                    merge,
+                    name: &name,
+                    text: &text,
+                };
+                fragment.check(&mut Path::new(), &mut Path::new(), root, &mut seen)?;
+            }
+            merge.add(name, text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the `.toml` files in `dir`, in lexical order.
+fn fragments(dir: &fs_path::Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::Message(format!("{}: {}", dir.display(), e))),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::Message(format!("{}: {}", dir.display(), e)))?;
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        if let Ok(name) = entry.file_name().into_string() {
+            if name.ends_with(".toml") && !name.starts_with('.') {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    Ok(names.into_iter().map(|name| dir.join(name)).collect())
+}
+
+/// A value set by a fragment, for finding conflicts.
+struct Set {
+    name: String,
+    line_col: (usize, usize),
+    value: Setting,
+}
+
+/// What a fragment set a path to. Tables and arrays that aren't replaced have no value, as the
+/// keys of tables are compared one by one and the items of such arrays are all kept.
+#[derive(PartialEq)]
+enum Setting {
+    Table,
+    Array,
+    Value(Value),
+}
+
+/// A fragment being checked for conflicts with those before it.
+struct Fragment<'a> {
+    merge: &'a Merge,
+    name: &'a str,
+    text: &'a str,
+}
+
+impl Fragment<'_> {
+    /// Records the values `node` sets, failing if an earlier fragment set one differently, or
+    /// put a table where `node` has a value or the other way around. `keys` is `path` without
+    /// indices.
+    fn check(
+        &self,
+        path: &mut Path,
+        keys: &mut Path,
+        node: Node,
+        seen: &mut BTreeMap<Path, Set>,
+    ) -> Result<()> {
+        let span = node.span;
+        let value = match node.kind {
+            NodeKind::Table(table) => {
+                if !path.segments().is_empty() {
+                    self.record(path, span, Setting::Table, seen)?;
+                }
+                for (key, entry) in table.entries {
+                    path.push(key.as_str());
+                    keys.push(key.as_str());
+                    self.check(path, keys, entry.node, seen)?;
+                    path.pop();
+                    keys.pop();
+                }
+                return Ok(());
+            }
+            NodeKind::Array(..) if *self.merge.strategy(keys) != ArrayStrategy::Replace => {
+                Setting::Array
+            }
+            kind => Setting::Value(Node { span, kind }.into_value()),
+        };
+        self.record(path, span, value, seen)
+    }
+
+    /// Records that the fragment sets `path` to `value`.
+    fn record(
+        &self,
+        path: &Path,
+        span: Span,
+        value: Setting,
+        seen: &mut BTreeMap<Path, Set>,
+    ) -> Result<()> {
+        if let Some(set) = seen.get(path) {
+            if set.value != value {
+                let (line, col) = set.line_col;
+                let message = format!("`{}` is also set by {}:{}:{}", path, set.name, line, col);
+                return Err(Error::Invalid { message, span }.in_source(self.name));
+            }
+            if !matches!(value, Setting::Value(_)) {
+                return Ok(());
+            }
+        }
+        let set = Set {
+            name: self.name.to_string(),
+            line_col: span.line_col(self.text),
+            value,
+        };
+        seen.insert(path.clone(), set);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn origin(merged: &Merged, dir: &TempDir, path: &str) -> String {
+        let origin = merged.origin(&path.parse().unwrap()).unwrap();
+        let name = fs_path::Path::new(origin.name());
+        name.strip_prefix(&dir.0).unwrap().display().to_string()
+    }
+
+    #[test]
+    fn conf_d_success() {
+        let dir = TempDir::new(
+            "conf-d-success",
+            &[
+                (
+                    "app.toml",
+                    "port = 80\nhosts = ['a']\n[log]\nlevel = 'info'\n",
+                ),
+                (
+                    "conf.d/50-local.toml",
+                    "port = 8080\n[log]\nfile = 'x.log'\n",
+                ),
+                ("conf.d/10-defaults.toml", "port = 81\nhosts = ['b']\n"),
+                ("conf.d/20-same.toml", "hosts = ['b']\n"),
+                ("conf.d/.hidden.toml", "port = 1\n"),
+                ("conf.d/README", "port = 2\n"),
+            ],
+        );
+        let mut conf_d = ConfDir::new();
+        conf_d.base(dir.0.join("app.toml"));
+        // With arrays appended, only `port` is set differently by two fragments.
+        conf_d.reject_conflicts(true);
+        let mut merge = Merge::new();
+        merge.arrays(ArrayStrategy::Append);
+        let message = conf_d
+            .load_into(&mut merge, dir.0.join("conf.d"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            message.ends_with("10-defaults.toml:1:8 at 7"),
+            "{}",
+            message
+        );
+
+        let mut merge = Merge::new();
+        merge.arrays(ArrayStrategy::Append);
+        conf_d.reject_conflicts(false);
+        conf_d.load_into(&mut merge, dir.0.join("conf.d")).unwrap();
+        let merged = merge.finish().unwrap();
+        let table = Value::Table(merged.table().clone());
+        assert_eq!(
+            table.to_string(),
+            "{ hosts = [\"a\", \"b\", \"b\"], log = { file = \"x.log\", level = \"info\" }, port = 8080 }"
+        );
+        assert_eq!(origin(&merged, &dir, "port"), "conf.d/50-local.toml");
+        assert_eq!(origin(&merged, &dir, "log.level"), "app.toml");
+        assert_eq!(origin(&merged, &dir, "hosts[2]"), "conf.d/20-same.toml");
+
+        let merged = ConfDir::new().load(dir.0.join("missing")).unwrap();
+        assert!(merged.table().is_empty());
+    }
+
+    #[test]
+    fn conf_d_fail() {
+        let dir = TempDir::new(
+            "conf-d-fail",
+            &[
+                ("base.toml", "port = 1\n"),
+                ("conf.d/a.toml", "port = 2\n[t]\nx = [1]\n"),
+                ("conf.d/b.toml", "port = 2\n[t]\ny = 1\nx = [2]\n"),
+            ],
+        );
+        let mut conf_d = ConfDir::new();
+        conf_d.base(dir.0.join("base.toml"));
+        conf_d.reject_conflicts(true);
+        let (name, error) = match conf_d.load(dir.0.join("conf.d")).unwrap_err() {
+            Error::Source { name, error } => (name, error),
+            e => panic!("{:?}", e),
+        };
+        assert!(name.ends_with("b.toml"), "{}", name);
+        let message = error
+            .to_string()
+            .replace(&format!("{}/", dir.0.display()), "");
+        assert_eq!(message, "`t.x` is also set by conf.d/a.toml:3:5 at 23");
+
+        conf_d.base(dir.0.join("nope.toml"));
+        let message = conf_d.load(dir.0.join("conf.d")).unwrap_err().to_string();
+        assert!(message.contains("nope.toml: "), "{}", message);
+
+        // A table where an earlier fragment has a value conflicts with it, and the other way,
+        // even when the value is an array whose items would all be kept.
+        for (a, b, expected) in [
+            ("t = 1\n", "t.x = 2\n", "`t` is also set by a.toml:1:5 at 0"),
+            (
+                "t = { x = 1 }\n",
+                "t = 5\n",
+                "`t` is also set by a.toml:1:5 at 4",
+            ),
+            (
+                "t = [1]\n",
+                "[t]\nx = 1\n",
+                "`t` is also set by a.toml:1:5 at 1",
+            ),
+            (
+                "t = { x = 1 }\n",
+                "t = [1]\n",
+                "`t` is also set by a.toml:1:5 at 4",
+            ),
+        ] {
+            let dir = TempDir::new("conf-d-fail-types", &[("a.toml", a), ("b.toml", b)]);
+            let mut conf_d = ConfDir::new();
+            conf_d.reject_conflicts(true);
+            let mut merge = Merge::new();
+            merge.arrays(ArrayStrategy::Append);
+            let message = conf_d
+                .load_into(&mut merge, &dir.0)
+                .unwrap_err()
+                .to_string();
+            let message = message.replace(&format!("{}/", dir.0.display()), "");
+            assert_eq!(message, format!("b.toml: {}", expected));
+        }
+    }
+}
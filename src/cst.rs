@@ -0,0 +1,846 @@
+//! A lossless concrete syntax tree, in which every byte of a document belongs to exactly one
+//! token.
+//!
+//! Tokens are the leaves of the tree: whitespace, newlines, comments, key segments, scalar
+//! values and punctuation. Nodes group them into tables, headers, key/value pairs, dotted keys,
+//! arrays and inline tables. Concatenating the tokens in order gives back the original text, so
+//! tools that rewrite documents can work on the tree and leave everything else untouched.
+//!
+//! ```
+//! use toml_rust::cst::{self, Kind};
+//!
+//! let tree = cst::parse("[server]\nport = 80 # http\n").unwrap();
+//! let token = tree.node_at(17).unwrap();
+//! assert_eq!((token.kind(), token.text()), (Kind::Integer, "80"));
+//! let key_value = token.parent().unwrap();
+//! assert_eq!(key_value.kind(), Kind::KeyValue);
+//! assert_eq!(key_value.key().unwrap().text(), "port");
+//! assert_eq!(key_value.next_sibling().unwrap().kind(), Kind::Whitespace);
+//! ```
+
+use crate::error::{Error, Result};
+use crate::lex::{self, Span};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// The whole document: root key/value pairs, tables and the trivia between them.
+    Document,
+    /// A header and the key/value pairs below it, up to the end of the last one's line. Blank
+    /// lines and comments after that belong to the document.
+    Table,
+    /// `[key]` or `[[key]]`.
+    TableHeader,
+    /// `key = value`, in a table or an inline table.
+    KeyValue,
+    /// A key of more than one segment, including the dots and whitespace between them.
+    DottedKey,
+    Array,
+    InlineTable,
+
+    Whitespace,
+    /// `\n` or `\r\n`.
+    Newline,
+    /// From `#` up to the end of the line.
+    Comment,
+    /// An unquoted key segment.
+    BareKey,
+    /// Any kind of string, including its quotes.
+    String,
+    Integer,
+    Float,
+    Bool,
+    Datetime,
+    LeftBracket,
+    RightBracket,
+    /// `[[` opening an array of tables header.
+    DoubleLeftBracket,
+    /// `]]` closing an array of tables header.
+    DoubleRightBracket,
+    LeftBrace,
+    RightBrace,
+    Equals,
+    Comma,
+    Dot,
+}
+
+impl Kind {
+    /// Nodes that group other nodes, as opposed to tokens.
+    pub fn is_node(self) -> bool {
+        matches!(
+            self,
+            Kind::Document
+                | Kind::Table
+                | Kind::TableHeader
+                | Kind::KeyValue
+                | Kind::DottedKey
+                | Kind::Array
+                | Kind::InlineTable
+        )
+    }
+
+    /// Whitespace, newlines and comments.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, Kind::Whitespace | Kind::Newline | Kind::Comment)
+    }
+
+    /// A value written in place, as opposed to a table defined by a header.
+    pub fn is_value(self) -> bool {
+        matches!(
+            self,
+            Kind::String
+                | Kind::Integer
+                | Kind::Float
+                | Kind::Bool
+                | Kind::Datetime
+                | Kind::Array
+                | Kind::InlineTable
+        )
+    }
+
+    /// A single segment of a key.
+    pub fn is_key_segment(self) -> bool {
+        matches!(self, Kind::BareKey | Kind::String)
+    }
+}
+
+pub(crate) type NodeId = usize;
+
+#[derive(Clone, Debug)]
+struct NodeData {
+    kind: Kind,
+    span: Span,
+    parent: Option<NodeId>,
+    /// Position among the parent's children.
+    index: usize,
+    children: Vec<NodeId>,
+}
+
+/// Parses `text` into a syntax tree.
+pub fn parse(text: &str) -> Result<Tree> {
+    crate::validate(text)?;
+    Tree::new(text)
+}
+
+/// A syntax tree along with the text it covers. Nodes are stored in an arena and referred to
+/// by id internally; `Node` is a handle for navigating them.
+#[derive(Clone, Debug)]
+pub struct Tree {
+    text: String,
+    nodes: Vec<NodeData>,
+    root: NodeId,
+}
+
+impl Tree {
+    /// Builds the tree for `text`, which must be a valid document.
+    pub(crate) fn new(text: &str) -> Result<Self> {
+        let mut builder = Builder {
+            text: text.as_bytes(),
+            pos: 0,
+            nodes: Vec::new(),
+        };
+        match builder.document() {
+            Some(root) if builder.pos == text.len() => Ok(Tree {
+                text: text.to_string(),
+                nodes: builder.nodes,
+                root,
+            }),
+            _ => Err(Error::Lex(lex::Error::Unexpected { pos: builder.pos })),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The `Document` node.
+    pub fn root(&self) -> Node<'_> {
+        self.node(self.root)
+    }
+
+    /// Returns the token containing the byte at `offset`, or `None` past the end of the text.
+    /// Its ancestors are the nodes containing the offset.
+    pub fn node_at(&self, offset: usize) -> Option<Node<'_>> {
+        let mut id = self.root;
+        loop {
+            let children = self.children(id);
+            if children.is_empty() {
+                return self.span(id).hi().gt(&offset).then(|| self.node(id));
+            }
+            // Children are in order and cover their parent without gaps.
+            let i = children.partition_point(|&c| self.span(c).hi() <= offset);
+            id = *children.get(i)?;
+        }
+    }
+
+    fn node(&self, id: NodeId) -> Node<'_> {
+        Node { tree: self, id }
+    }
+
+    pub(crate) fn root_id(&self) -> NodeId {
+        self.root
+    }
+
+    pub(crate) fn kind(&self, id: NodeId) -> Kind {
+        self.nodes[id].kind
+    }
+
+    pub(crate) fn span(&self, id: NodeId) -> Span {
+        self.nodes[id].span
+    }
+
+    pub(crate) fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id].children
+    }
+
+    /// The first child of `id` of the given kind.
+    pub(crate) fn child(&self, id: NodeId, kind: Kind) -> Option<NodeId> {
+        self.children(id)
+            .iter()
+            .copied()
+            .find(|&child| self.kind(child) == kind)
+    }
+
+    /// The key of a `KeyValue` or `TableHeader`, which is a single segment or a `DottedKey`.
+    pub(crate) fn key(&self, id: NodeId) -> Option<NodeId> {
+        self.children(id)
+            .iter()
+            .copied()
+            .find(|&child| self.kind(child).is_key_segment() || self.kind(child) == Kind::DottedKey)
+    }
+
+    /// The segments of the key of a `KeyValue` or `TableHeader`.
+    pub(crate) fn key_segments(&self, id: NodeId) -> Vec<NodeId> {
+        match self.key(id) {
+            Some(key) if self.kind(key) == Kind::DottedKey => self
+                .children(key)
+                .iter()
+                .copied()
+                .filter(|&child| self.kind(child).is_key_segment())
+                .collect(),
+            Some(key) => vec![key],
+            None => Vec::new(),
+        }
+    }
+
+    /// The value of a `KeyValue`.
+    pub(crate) fn value(&self, id: NodeId) -> Option<NodeId> {
+        let children = self.children(id);
+        let assign = children
+            .iter()
+            .position(|&c| self.kind(c) == Kind::Equals)?;
+        children[assign..]
+            .iter()
+            .copied()
+            .find(|&child| self.kind(child).is_value())
+    }
+
+    /// The values in an `Array`.
+    pub(crate) fn items(&self, id: NodeId) -> Vec<NodeId> {
+        self.children(id)
+            .iter()
+            .copied()
+            .filter(|&child| self.kind(child).is_value())
+            .collect()
+    }
+
+    /// The `KeyValue` children of a table, inline table or the document.
+    pub(crate) fn key_values(&self, id: NodeId) -> Vec<NodeId> {
+        self.children(id)
+            .iter()
+            .copied()
+            .filter(|&child| self.kind(child) == Kind::KeyValue)
+            .collect()
+    }
+}
+
+/// A node or token in a `Tree`.
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    tree: &'a Tree,
+    id: NodeId,
+}
+
+impl<'a> Node<'a> {
+    pub fn kind(&self) -> Kind {
+        self.tree.kind(self.id)
+    }
+
+    pub fn span(&self) -> Span {
+        self.tree.span(self.id)
+    }
+
+    /// The text the node covers, including any trivia inside it.
+    pub fn text(&self) -> &'a str {
+        let span = self.span();
+        &self.tree.text[span.lo()..span.hi()]
+    }
+
+    pub fn is_token(&self) -> bool {
+        !self.kind().is_node()
+    }
+
+    pub fn parent(&self) -> Option<Node<'a>> {
+        let parent = self.tree.nodes[self.id].parent?;
+        Some(self.tree.node(parent))
+    }
+
+    /// The parent, its parent and so on up to the `Document`.
+    pub fn ancestors(&self) -> impl Iterator<Item = Node<'a>> {
+        std::iter::successors(self.parent(), Node::parent)
+    }
+
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = Node<'a>> + ExactSizeIterator {
+        let tree = self.tree;
+        tree.children(self.id).iter().map(move |&id| tree.node(id))
+    }
+
+    pub fn first_child(&self) -> Option<Node<'a>> {
+        self.children().next()
+    }
+
+    pub fn last_child(&self) -> Option<Node<'a>> {
+        self.children().next_back()
+    }
+
+    /// The child at `index`.
+    pub fn child(&self, index: usize) -> Option<Node<'a>> {
+        let id = *self.tree.children(self.id).get(index)?;
+        Some(self.tree.node(id))
+    }
+
+    /// Position among the parent's children.
+    pub fn index(&self) -> usize {
+        self.tree.nodes[self.id].index
+    }
+
+    pub fn next_sibling(&self) -> Option<Node<'a>> {
+        self.parent()?.child(self.index() + 1)
+    }
+
+    pub fn prev_sibling(&self) -> Option<Node<'a>> {
+        self.parent()?.child(self.index().checked_sub(1)?)
+    }
+
+    /// The node and everything below it, in document order.
+    pub fn descendants(&self) -> impl Iterator<Item = Node<'a>> {
+        let mut stack = vec![*self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children().rev());
+            Some(node)
+        })
+    }
+
+    /// The tokens under the node, in document order. Their text adds up to the node's.
+    pub fn tokens(&self) -> impl Iterator<Item = Node<'a>> {
+        self.descendants().filter(Node::is_token)
+    }
+
+    /// The key of a `KeyValue` or `TableHeader`: a single segment or a `DottedKey`.
+    pub fn key(&self) -> Option<Node<'a>> {
+        match self.kind() {
+            Kind::KeyValue | Kind::TableHeader => Some(self.tree.node(self.tree.key(self.id)?)),
+            _ => None,
+        }
+    }
+
+    /// The value of a `KeyValue`.
+    pub fn value(&self) -> Option<Node<'a>> {
+        match self.kind() {
+            Kind::KeyValue => Some(self.tree.node(self.tree.value(self.id)?)),
+            _ => None,
+        }
+    }
+
+    /// The header of a `Table`.
+    pub fn header(&self) -> Option<Node<'a>> {
+        match self.kind() {
+            Kind::Table => Some(self.tree.node(self.tree.child(self.id, Kind::TableHeader)?)),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Node<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.tree, other.tree) && self.id == other.id
+    }
+}
+
+impl Eq for Node<'_> {}
+
+impl fmt::Debug for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?}@{}..{}",
+            self.kind(),
+            self.span().lo(),
+            self.span().hi()
+        )
+    }
+}
+
+struct Builder<'a> {
+    text: &'a [u8],
+    pos: usize,
+    nodes: Vec<NodeData>,
+}
+
+impl Builder<'_> {
+    fn current(&self) -> u8 {
+        self.at(0)
+    }
+
+    fn at(&self, offset: usize) -> u8 {
+        *self.text.get(self.pos + offset).unwrap_or(&0)
+    }
+
+    fn starts_with(&self, s: &[u8]) -> bool {
+        self.text
+            .get(self.pos..)
+            .is_some_and(|rest| rest.starts_with(s))
+    }
+
+    fn push(&mut self, kind: Kind, span: Span, children: Vec<NodeId>) -> NodeId {
+        let id = self.nodes.len();
+        for (index, &child) in children.iter().enumerate() {
+            self.nodes[child].parent = Some(id);
+            self.nodes[child].index = index;
+        }
+        self.nodes.push(NodeData {
+            kind,
+            span,
+            parent: None,
+            index: 0,
+            children,
+        });
+        id
+    }
+
+    /// Adds a token covering the next `len` bytes.
+    fn token(&mut self, kind: Kind, len: usize) -> NodeId {
+        let lo = self.pos;
+        self.pos = (lo + len).min(self.text.len());
+        self.push(kind, Span::new(lo, self.pos), Vec::new())
+    }
+
+    /// Adds a node spanning its children.
+    fn node(&mut self, kind: Kind, children: Vec<NodeId>) -> NodeId {
+        let lo = children
+            .first()
+            .map_or(self.pos, |&c| self.nodes[c].span.lo());
+        let hi = children
+            .last()
+            .map_or(self.pos, |&c| self.nodes[c].span.hi());
+        self.push(kind, Span::new(lo, hi), children)
+    }
+
+    fn expect(&mut self, c: u8, kind: Kind, out: &mut Vec<NodeId>) -> Option<()> {
+        if self.current() != c {
+            return None;
+        }
+        out.push(self.token(kind, 1));
+        Some(())
+    }
+
+    fn whitespace(&mut self, out: &mut Vec<NodeId>) {
+        let len = self.run(|c| c == b' ' || c == b'\t');
+        if len > 0 {
+            out.push(self.token(Kind::Whitespace, len));
+        }
+    }
+
+    /// Whitespace, a comment and a newline, any of which may be missing.
+    fn line_end(&mut self, out: &mut Vec<NodeId>) {
+        self.whitespace(out);
+        if self.current() == b'#' {
+            let len = self.run(|c| c != b'\n' && c != b'\r');
+            out.push(self.token(Kind::Comment, len));
+        }
+        self.newline(out);
+    }
+
+    fn newline(&mut self, out: &mut Vec<NodeId>) -> bool {
+        let len = match (self.current(), self.at(1)) {
+            (b'\n', _) => 1,
+            (b'\r', b'\n') => 2,
+            _ => return false,
+        };
+        out.push(self.token(Kind::Newline, len));
+        true
+    }
+
+    /// Any mix of whitespace, comments and newlines.
+    fn trivia(&mut self, out: &mut Vec<NodeId>) {
+        loop {
+            let start = self.pos;
+            self.line_end(out);
+            if self.pos == start {
+                break;
+            }
+        }
+    }
+
+    fn run(&self, f: impl Fn(u8) -> bool) -> usize {
+        let rest = self.text.get(self.pos..).unwrap_or(&[]);
+        rest.iter().position(|&c| !f(c)).unwrap_or(rest.len())
+    }
+
+    fn document(&mut self) -> Option<NodeId> {
+        let mut children = Vec::new();
+        loop {
+            self.trivia(&mut children);
+            match self.current() {
+                0 if self.pos >= self.text.len() => break,
+                b'[' => {
+                    let (table, trailing) = self.table()?;
+                    children.push(table);
+                    children.extend(trailing);
+                }
+                _ => {
+                    children.push(self.key_value()?);
+                    self.line_end(&mut children);
+                }
+            }
+        }
+        Some(self.node(Kind::Document, children))
+    }
+
+    /// A table section. The trivia following its last line is returned separately, since it
+    /// belongs to the document rather than the table.
+    fn table(&mut self) -> Option<(NodeId, Vec<NodeId>)> {
+        let mut children = vec![self.header()?];
+        self.line_end(&mut children);
+        let mut trailing = Vec::new();
+        loop {
+            self.trivia(&mut trailing);
+            match self.current() {
+                b'[' => break,
+                0 if self.pos >= self.text.len() => break,
+                _ => {
+                    children.append(&mut trailing);
+                    children.push(self.key_value()?);
+                    self.line_end(&mut children);
+                }
+            }
+        }
+        Some((self.node(Kind::Table, children), trailing))
+    }
+
+    fn header(&mut self) -> Option<NodeId> {
+        let mut children = Vec::new();
+        let is_array = self.starts_with(b"[[");
+        if is_array {
+            children.push(self.token(Kind::DoubleLeftBracket, 2));
+        } else {
+            self.expect(b'[', Kind::LeftBracket, &mut children)?;
+        }
+        self.whitespace(&mut children);
+        children.push(self.key()?);
+        self.whitespace(&mut children);
+        if is_array {
+            if !self.starts_with(b"]]") {
+                return None;
+            }
+            children.push(self.token(Kind::DoubleRightBracket, 2));
+        } else {
+            self.expect(b']', Kind::RightBracket, &mut children)?;
+        }
+        Some(self.node(Kind::TableHeader, children))
+    }
+
+    fn key_value(&mut self) -> Option<NodeId> {
+        let mut children = vec![self.key()?];
+        self.whitespace(&mut children);
+        self.expect(b'=', Kind::Equals, &mut children)?;
+        self.whitespace(&mut children);
+        children.push(self.value()?);
+        Some(self.node(Kind::KeyValue, children))
+    }
+
+    /// A single key segment, or a `DottedKey` if it's followed by a dot.
+    fn key(&mut self) -> Option<NodeId> {
+        let mut children = vec![self.key_segment()?];
+        loop {
+            let spaces = self.run(|c| c == b' ' || c == b'\t');
+            if self.at(spaces) != b'.' {
+                break;
+            }
+            self.whitespace(&mut children);
+            children.push(self.token(Kind::Dot, 1));
+            self.whitespace(&mut children);
+            children.push(self.key_segment()?);
+        }
+        match children.len() {
+            1 => children.pop(),
+            _ => Some(self.node(Kind::DottedKey, children)),
+        }
+    }
+
+    fn key_segment(&mut self) -> Option<NodeId> {
+        match self.current() {
+            b'"' | b'\'' => self.string(),
+            _ => {
+                let len = self.run(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-');
+                if len == 0 {
+                    return None;
+                }
+                Some(self.token(Kind::BareKey, len))
+            }
+        }
+    }
+
+    fn value(&mut self) -> Option<NodeId> {
+        match self.current() {
+            b'"' | b'\'' => self.string(),
+            b'[' => self.array(),
+            b'{' => self.inline_table(),
+            _ => self.scalar(),
+        }
+    }
+
+    fn string(&mut self) -> Option<NodeId> {
+        let quote = self.current();
+        let triple = [quote; 3];
+        let escapes = quote == b'"';
+        let rest = self.text.get(self.pos..)?;
+
+        let len = if rest.starts_with(&triple) {
+            let mut i = 3;
+            loop {
+                match rest.get(i)? {
+                    b'\\' if escapes => i += 2,
+                    &c if c == quote && rest[i..].starts_with(&triple) => {
+                        // Up to two quotes may directly precede the closing delimiter.
+                        let quotes = rest[i..].iter().take_while(|&&c| c == quote).count();
+                        break i + quotes.min(5);
+                    }
+                    _ => i += 1,
+                }
+            }
+        } else {
+            let mut i = 1;
+            loop {
+                match rest.get(i)? {
+                    b'\\' if escapes => i += 2,
+                    &c if c == quote => break i + 1,
+                    _ => i += 1,
+                }
+            }
+        };
+        Some(self.token(Kind::String, len))
+    }
+
+    fn scalar(&mut self) -> Option<NodeId> {
+        let is_scalar = |c: u8| c.is_ascii_alphanumeric() || b"_+-.:".contains(&c);
+        let mut len = self.run(is_scalar);
+        let word = &self.text[self.pos..self.pos + len];
+
+        // A date and time may be separated by a space.
+        let is_date = len == 10 && word[4] == b'-' && word[7] == b'-';
+        if is_date
+            && self.at(len) == b' '
+            && self.at(len + 1).is_ascii_digit()
+            && self.at(len + 2).is_ascii_digit()
+            && self.at(len + 3) == b':'
+        {
+            let rest = &self.text[self.pos + len + 1..];
+            len += 1 + rest
+                .iter()
+                .position(|&c| !is_scalar(c))
+                .unwrap_or(rest.len());
+        }
+
+        let digits = word
+            .strip_prefix(b"+")
+            .or(word.strip_prefix(b"-"))
+            .unwrap_or(word);
+        let kind = match word {
+            [] => return None,
+            b"true" | b"false" => Kind::Bool,
+            _ if word.get(4) == Some(&b'-') || word.get(2) == Some(&b':') => Kind::Datetime,
+            _ if [b"0x", b"0o", b"0b"].iter().any(|p| digits.starts_with(*p)) => Kind::Integer,
+            _ if digits == b"inf" || digits == b"nan" => Kind::Float,
+            _ if word.iter().any(|c| b".eE".contains(c)) => Kind::Float,
+            _ => Kind::Integer,
+        };
+        Some(self.token(kind, len))
+    }
+
+    fn array(&mut self) -> Option<NodeId> {
+        let mut children = Vec::new();
+        self.expect(b'[', Kind::LeftBracket, &mut children)?;
+        loop {
+            self.trivia(&mut children);
+            if self.current() == b']' {
+                break;
+            }
+            children.push(self.value()?);
+            self.trivia(&mut children);
+            if self.current() == b',' {
+                children.push(self.token(Kind::Comma, 1));
+            } else if self.current() != b']' {
+                return None;
+            }
+        }
+        self.expect(b']', Kind::RightBracket, &mut children)?;
+        Some(self.node(Kind::Array, children))
+    }
+
+    fn inline_table(&mut self) -> Option<NodeId> {
+        let mut children = Vec::new();
+        self.expect(b'{', Kind::LeftBrace, &mut children)?;
+        loop {
+            self.trivia(&mut children);
+            if self.current() == b'}' {
+                break;
+            }
+            children.push(self.key_value()?);
+            self.trivia(&mut children);
+            if self.current() == b',' {
+                children.push(self.token(Kind::Comma, 1));
+            } else if self.current() != b'}' {
+                return None;
+            }
+        }
+        self.expect(b'}', Kind::RightBrace, &mut children)?;
+        Some(self.node(Kind::InlineTable, children))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that the tokens of the tree cover `text` exactly once, in order, and that the
+    /// links between nodes agree with each other.
+    fn lossless(text: &str) -> Tree {
+        let tree = parse(text).expect(text);
+        let mut pos = 0;
+        for token in tree.root().tokens() {
+            assert_eq!(token.span().lo(), pos, "{:?} in {:?}", token, text);
+            assert!(!token.text().is_empty(), "{:?} in {:?}", token, text);
+            assert_eq!(tree.node_at(pos), Some(token));
+            pos = token.span().hi();
+        }
+        assert_eq!(pos, text.len(), "{:?}", text);
+        assert_eq!(tree.node_at(pos), None);
+
+        for node in tree.root().descendants() {
+            let children: Vec<_> = node.children().collect();
+            let text: String = children.iter().map(|c| c.text()).collect();
+            assert!(children.is_empty() || text == node.text(), "{:?}", node);
+            for (i, child) in children.iter().enumerate() {
+                assert_eq!(child.parent(), Some(node));
+                assert_eq!(child.index(), i);
+                assert_eq!(child.prev_sibling(), i.checked_sub(1).map(|i| children[i]));
+                assert_eq!(child.next_sibling(), children.get(i + 1).copied());
+            }
+        }
+        tree
+    }
+
+    fn kinds<'a>(nodes: impl Iterator<Item = Node<'a>>) -> Vec<Kind> {
+        nodes.map(|n| n.kind()).filter(|k| !k.is_trivia()).collect()
+    }
+
+    #[test]
+    fn tree_success() {
+        let text =
+            "# c\na = \"x\" # t\n\"q.k\" . b = \"\"\"\nml\"\"\"\r\nc = [1, 'y',\n  # c\n]\n\n\
+                    [[t.u]] # h\nd = {e = 1, f.\"g\" = 2}\n# trailing\n\n[ s . 't' ]\n\
+                    m = \"\"\"\"x\"\"\"\"\nn = 1979-05-27 07:32:00Z\no = -inf\np = 0xbeef";
+        let tree = lossless(text);
+        let root = tree.root();
+        assert_eq!(root.kind(), Kind::Document);
+        assert_eq!(root.parent(), None);
+        assert_eq!(
+            kinds(root.children()),
+            [
+                Kind::KeyValue,
+                Kind::KeyValue,
+                Kind::KeyValue,
+                Kind::Table,
+                Kind::Table
+            ]
+        );
+
+        let tables: Vec<_> = root
+            .children()
+            .filter(|n| n.kind() == Kind::Table)
+            .collect();
+        assert_eq!(tables[0].text(), "[[t.u]] # h\nd = {e = 1, f.\"g\" = 2}\n");
+        assert_eq!(tables[0].header().unwrap().key().unwrap().text(), "t.u");
+        assert_eq!(tables[0].next_sibling().unwrap().text(), "# trailing");
+
+        let values = tables[1].children().filter_map(|n| n.value());
+        assert_eq!(
+            kinds(values),
+            [Kind::String, Kind::Datetime, Kind::Float, Kind::Integer]
+        );
+
+        let key_value = root
+            .children()
+            .filter(|n| n.kind() == Kind::KeyValue)
+            .nth(1);
+        let key = key_value.unwrap().key().unwrap();
+        assert_eq!(key.kind(), Kind::DottedKey);
+        assert_eq!(
+            kinds(key.children()),
+            [Kind::String, Kind::Dot, Kind::BareKey]
+        );
+    }
+
+    #[test]
+    fn node_at_success() {
+        let text = "a = [1, { b = 'c' }] # d\n";
+        let tree = lossless(text);
+        let at = |offset| tree.node_at(offset).unwrap();
+        assert_eq!((at(0).kind(), at(0).text()), (Kind::BareKey, "a"));
+        assert_eq!(at(1).kind(), Kind::Whitespace);
+        assert_eq!(at(15).text(), "'c'");
+        assert_eq!(
+            kinds(at(15).ancestors()),
+            [
+                Kind::KeyValue,
+                Kind::InlineTable,
+                Kind::Array,
+                Kind::KeyValue,
+                Kind::Document
+            ]
+        );
+        assert_eq!(at(22).text(), "# d");
+        assert_eq!(at(24).kind(), Kind::Newline);
+        assert_eq!(tree.node_at(25), None);
+
+        let array = at(4).parent().unwrap();
+        assert_eq!(array.first_child().unwrap().kind(), Kind::LeftBracket);
+        assert_eq!(array.last_child().unwrap().kind(), Kind::RightBracket);
+        assert_eq!(array.children().len(), 6);
+    }
+
+    #[test]
+    fn tree_covers_corpus() {
+        for text in [
+            "",
+            "\n\n# only a comment",
+            "a=1",
+            "[a]",
+            "[a]\n\n\n",
+            "a = []\nb = [ ]\nc = {}\nd = [[], [{}]]",
+            "a = \"\\\"\"\nb = '\\'\nc = '''''x'''''",
+            "a = 1979-05-27T07:32:00.999+05:30\nb = 07:32:00\nc = 1979-05-27",
+            "a = +1_000\nb = 1e-3\nc = true\nd = nan",
+        ] {
+            lossless(text);
+        }
+    }
+
+    #[test]
+    fn parse_fail() {
+        assert!(parse("a = ").is_err());
+        assert!(parse("[a]\n[a]").is_err());
+    }
+}
@@ -0,0 +1,394 @@
+use serde::{de, ser};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// Name of the newtype struct used to pass a `Datetime` through serde without losing its type.
+pub(crate) const NAME: &str = "$__toml_private_Datetime";
+pub(crate) const FIELD: &str = "$__toml_private_datetime";
+
+/// A TOML date-time value.
+///
+/// Depending on which components are present this is an offset date-time, a local date-time,
+/// a local date or a local time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Datetime {
+    pub date: Option<Date>,
+    pub time: Option<Time>,
+    pub offset: Option<Offset>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Time {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Offset {
+    /// UTC, written as `Z`.
+    Z,
+    /// A fixed offset from UTC, in minutes.
+    Custom { minutes: i16 },
+}
+
+/// Kinds of date-time defined by the TOML specification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatetimeKind {
+    OffsetDatetime,
+    LocalDatetime,
+    LocalDate,
+    LocalTime,
+}
+
+impl Datetime {
+    pub fn kind(&self) -> DatetimeKind {
+        match (self.date, self.time, self.offset) {
+            (Some(_), Some(_), Some(_)) => DatetimeKind::OffsetDatetime,
+            (Some(_), Some(_), None) => DatetimeKind::LocalDatetime,
+            (Some(_), None, _) => DatetimeKind::LocalDate,
+            (None, _, _) => DatetimeKind::LocalTime,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatetimeParseError {
+    _priv: (),
+}
+
+impl Display for DatetimeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "failed to parse datetime".fmt(f)
+    }
+}
+
+impl std::error::Error for DatetimeParseError {}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)?;
+        if self.nanosecond != 0 {
+            let fraction = format!("{:09}", self.nanosecond);
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Offset::Z => "Z".fmt(f),
+            Offset::Custom { minutes } => {
+                let sign = if minutes < 0 { '-' } else { '+' };
+                let minutes = minutes.unsigned_abs();
+                write!(f, "{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+            }
+        }
+    }
+}
+
+impl Display for Datetime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(date) = &self.date {
+            date.fmt(f)?;
+        }
+        if let Some(time) = &self.time {
+            if self.date.is_some() {
+                "T".fmt(f)?;
+            }
+            time.fmt(f)?;
+        }
+        if let Some(offset) = &self.offset {
+            offset.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// A tiny cursor over the bytes of a date-time literal.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.index).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), DatetimeParseError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(DatetimeParseError { _priv: () })
+        }
+    }
+
+    /// Reads exactly `n` ascii digits as a decimal number.
+    fn digits(&mut self, n: usize) -> Result<u32, DatetimeParseError> {
+        let mut value = 0;
+        for _ in 0..n {
+            match self.peek() {
+                Some(c @ b'0'..=b'9') => {
+                    value = value * 10 + (c - b'0') as u32;
+                    self.index += 1;
+                }
+                _ => return Err(DatetimeParseError { _priv: () }),
+            }
+        }
+        Ok(value)
+    }
+
+    fn is_date(&self) -> bool {
+        self.bytes.get(4) == Some(&b'-')
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn parse_date(cursor: &mut Cursor) -> Result<Date, DatetimeParseError> {
+    let err = DatetimeParseError { _priv: () };
+    let year = cursor.digits(4)? as u16;
+    cursor.expect(b'-')?;
+    let month = cursor.digits(2)? as u8;
+    cursor.expect(b'-')?;
+    let day = cursor.digits(2)? as u8;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(err);
+    }
+    Ok(Date { year, month, day })
+}
+
+fn parse_time(cursor: &mut Cursor) -> Result<Time, DatetimeParseError> {
+    let err = DatetimeParseError { _priv: () };
+    let hour = cursor.digits(2)? as u8;
+    cursor.expect(b':')?;
+    let minute = cursor.digits(2)? as u8;
+    cursor.expect(b':')?;
+    let second = cursor.digits(2)? as u8;
+
+    let mut nanosecond = 0;
+    if cursor.eat(b'.') {
+        let mut scale = 100_000_000;
+        let start = cursor.index;
+        while let Some(c @ b'0'..=b'9') = cursor.peek() {
+            // Precision beyond nanoseconds is truncated.
+            nanosecond += (c - b'0') as u32 * scale;
+            scale /= 10;
+            cursor.index += 1;
+        }
+        if cursor.index == start {
+            return Err(err);
+        }
+    }
+
+    // Leap seconds are permitted by RFC 3339.
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(err);
+    }
+    Ok(Time {
+        hour,
+        minute,
+        second,
+        nanosecond,
+    })
+}
+
+fn parse_offset(cursor: &mut Cursor) -> Result<Option<Offset>, DatetimeParseError> {
+    let err = DatetimeParseError { _priv: () };
+    let sign = match cursor.peek() {
+        Some(b'Z' | b'z') => {
+            cursor.index += 1;
+            return Ok(Some(Offset::Z));
+        }
+        Some(b'+') => 1,
+        Some(b'-') => -1,
+        _ => return Ok(None),
+    };
+    cursor.index += 1;
+    let hours = cursor.digits(2)?;
+    cursor.expect(b':')?;
+    let minutes = cursor.digits(2)?;
+    if hours > 23 || minutes > 59 {
+        return Err(err);
+    }
+    Ok(Some(Offset::Custom {
+        minutes: sign * (hours * 60 + minutes) as i16,
+    }))
+}
+
+impl FromStr for Datetime {
+    type Err = DatetimeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cursor = Cursor {
+            bytes: s.as_bytes(),
+            index: 0,
+        };
+
+        let mut datetime = Datetime {
+            date: None,
+            time: None,
+            offset: None,
+        };
+
+        if cursor.is_date() {
+            datetime.date = Some(parse_date(&mut cursor)?);
+            if let Some(b'T' | b't' | b' ') = cursor.peek() {
+                cursor.index += 1;
+                datetime.time = Some(parse_time(&mut cursor)?);
+                datetime.offset = parse_offset(&mut cursor)?;
+            }
+        } else {
+            datetime.time = Some(parse_time(&mut cursor)?);
+        }
+
+        if cursor.index != s.len() {
+            return Err(DatetimeParseError { _priv: () });
+        }
+        Ok(datetime)
+    }
+}
+
+impl ser::Serialize for Datetime {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct(NAME, 1)?;
+        s.serialize_field(FIELD, &self.to_string())?;
+        s.end()
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Datetime {
+    fn deserialize<D>(deserializer: D) -> Result<Datetime, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct DatetimeVisitor;
+
+        impl<'de> de::Visitor<'de> for DatetimeVisitor {
+            type Value = Datetime;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a TOML datetime")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Datetime, E>
+            where
+                E: de::Error,
+            {
+                s.parse().map_err(de::Error::custom)
+            }
+
+            fn visit_map<V>(self, mut visitor: V) -> Result<Datetime, V::Error>
+            where
+                V: de::MapAccess<'de>,
+            {
+                match visitor.next_key::<String>()? {
+                    Some(key) if key == FIELD => {
+                        let value: String = visitor.next_value()?;
+                        value.parse().map_err(de::Error::custom)
+                    }
+                    _ => Err(de::Error::custom("expected a datetime")),
+                }
+            }
+        }
+
+        deserializer.deserialize_struct(NAME, &[FIELD], DatetimeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(s: &str, expected: &str) {
+        let datetime: Datetime = s.parse().expect(s);
+        assert_eq!(datetime.to_string(), expected);
+    }
+
+    #[test]
+    fn parse_success() {
+        roundtrip("1979-05-27T07:32:00Z", "1979-05-27T07:32:00Z");
+        roundtrip("1979-05-27 07:32:00z", "1979-05-27T07:32:00Z");
+        roundtrip("1979-05-27T00:32:00-07:00", "1979-05-27T00:32:00-07:00");
+        roundtrip(
+            "1979-05-27T00:32:00.999999+05:30",
+            "1979-05-27T00:32:00.999999+05:30",
+        );
+        roundtrip("1979-05-27T07:32:00", "1979-05-27T07:32:00");
+        roundtrip("1979-05-27", "1979-05-27");
+        roundtrip("07:32:00.5", "07:32:00.5");
+        roundtrip("2000-02-29", "2000-02-29");
+    }
+
+    #[test]
+    fn parse_fail() {
+        for s in [
+            "",
+            "1979-05-27T",
+            "1979-13-27",
+            "1979-02-29",
+            "1979-05-32",
+            "1979-5-27",
+            "24:00:00",
+            "07:60:00",
+            "07:32",
+            "07:32:00.",
+            "1979-05-27T07:32:00+25:00",
+            "1979-05-27T07:32:00+05",
+            "1979-05-27x",
+        ] {
+            assert!(s.parse::<Datetime>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn kinds() {
+        let kind = |s: &str| s.parse::<Datetime>().unwrap().kind();
+        assert_eq!(kind("1979-05-27T07:32:00Z"), DatetimeKind::OffsetDatetime);
+        assert_eq!(kind("1979-05-27T07:32:00"), DatetimeKind::LocalDatetime);
+        assert_eq!(kind("1979-05-27"), DatetimeKind::LocalDate);
+        assert_eq!(kind("07:32:00"), DatetimeKind::LocalTime);
+    }
+}
@@ -1,635 +1,704 @@
-use crate::{Error, Result};
-use serde::{de, Deserialize};
+use crate::datetime::{self, Datetime};
+use crate::error::{Error, Result};
+use crate::lex::Span;
+use crate::parse::{self, Entry, Node, NodeKind};
+use crate::path::{Path, Segment};
+use crate::spanned;
+use serde::de::{self, Deserialize, IntoDeserializer};
+use std::cell::RefCell;
+use std::collections::btree_map;
+use std::rc::Rc;
+use std::vec;
+
+/// Deserializes a TOML document.
+pub struct Deserializer<'a> {
+    text: &'a str,
+}
 
-// Should be enough for anybody.
-const SCRATCH_LEN: usize = 64 * 1024;
+impl<'a> Deserializer<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self { text }
+    }
 
-// Keep it secret, keep it safe.
-mod private {
-    pub trait Sealed {}
+    /// Parses the document into a node covering the whole text.
+    fn root(&self) -> Result<NodeDeserializer> {
+        let table = parse::parse_nodes(self.text)?;
+        Ok(NodeDeserializer::new(Node {
+            span: Span::new(0, self.text.len()),
+            kind: NodeKind::Table(table),
+        }))
+    }
 }
 
-pub trait Read<'de>: private::Sealed {
-    fn current(&self) -> usize;
-    fn eof(&self) -> bool;
-    fn peek(&self) -> u8;
-    fn peek2(&self) -> u8;
-    fn next(&mut self) -> u8;
-    fn refill(&mut self);
+macro_rules! forward_to_root {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value>
+            where
+                V: de::Visitor<'de>,
+            {
+                self.root()?.$method($($arg,)* visitor)
+            }
+        )*
+    };
 }
 
-pub struct StrRead<'de> {
-    source: &'de str,
-    current: usize,
+impl<'de, 'a> de::Deserializer<'de> for Deserializer<'a> {
+    type Error = Error;
+
+    forward_to_root! {
+        deserialize_any()
+        deserialize_bool()
+        deserialize_i8()
+        deserialize_i16()
+        deserialize_i32()
+        deserialize_i64()
+        deserialize_u8()
+        deserialize_u16()
+        deserialize_u32()
+        deserialize_u64()
+        deserialize_f32()
+        deserialize_f64()
+        deserialize_char()
+        deserialize_str()
+        deserialize_string()
+        deserialize_bytes()
+        deserialize_byte_buf()
+        deserialize_option()
+        deserialize_unit()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_seq()
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_map()
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier()
+        deserialize_ignored_any()
+    }
 }
 
-impl<'de> StrRead<'de> {
-    pub fn new(source: &'de str) -> Self {
-        Self { source, current: 0 }
+/// The path of the value being deserialized, kept when the caller needs to know which value an
+/// error is about. After an error it's the path of the value that failed.
+#[derive(Clone, Default)]
+pub(crate) struct Track(Option<Rc<RefCell<Path>>>);
+
+impl Track {
+    /// Starts keeping the path, at the root.
+    pub fn new() -> Self {
+        Track(Some(Rc::default()))
+    }
+
+    /// Returns the path, or the root if it isn't kept.
+    pub fn path(&self) -> Path {
+        self.0
+            .as_ref()
+            .map(|path| path.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    /// Runs `f` with `segment` added to the path, leaving it there if `f` fails.
+    fn nested<T>(&self, segment: impl Into<Segment>, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let Some(path) = &self.0 else {
+            return f();
+        };
+        let len = path.borrow().len();
+        path.borrow_mut().push(segment);
+        let result = f();
+        if result.is_ok() {
+            // Values that failed within `f` but were recovered from may have left theirs.
+            let mut path = path.borrow_mut();
+            while path.len() > len {
+                path.pop();
+            }
+        }
+        result
     }
 }
 
-impl<'de> private::Sealed for StrRead<'de> {}
+/// Deserializes a single parsed value.
+pub(crate) struct NodeDeserializer {
+    node: Node,
+    track: Track,
+}
 
-impl<'de> Read<'de> for StrRead<'de> {
-    #[inline(always)]
-    fn current(&self) -> usize {
-        self.current
+impl NodeDeserializer {
+    pub fn new(node: Node) -> Self {
+        Self::tracked(node, Track::default())
     }
 
-    #[inline(always)]
-    fn eof(&self) -> bool {
-        self.current == self.source.len()
+    /// Deserializes `node`, keeping the paths of the values in it in `track`.
+    pub fn tracked(node: Node, track: Track) -> Self {
+        Self { node, track }
     }
 
-    #[inline(always)]
-    fn peek(&self) -> u8 {
-        *self.source.as_bytes().get(self.current).unwrap_or(&0)
+    fn unexpected(&self) -> de::Unexpected<'_> {
+        match &self.node.kind {
+            NodeKind::String(s) => de::Unexpected::Str(s),
+            NodeKind::Integer(i) => de::Unexpected::Signed(*i),
+            NodeKind::Float(f) => de::Unexpected::Float(*f),
+            NodeKind::Boolean(b) => de::Unexpected::Bool(*b),
+            NodeKind::Datetime(_) => de::Unexpected::Other("datetime"),
+            NodeKind::Array(..) => de::Unexpected::Seq,
+            NodeKind::Table(_) => de::Unexpected::Map,
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for NodeDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.node.kind {
+            NodeKind::String(s) => visitor.visit_string(s),
+            NodeKind::Integer(i) => visitor.visit_i64(i),
+            NodeKind::Float(f) => visitor.visit_f64(f),
+            NodeKind::Boolean(b) => visitor.visit_bool(b),
+            NodeKind::Datetime(d) => visitor.visit_map(DatetimeAccess { datetime: Some(d) }),
+            NodeKind::Array(items, _) => {
+                let len = items.len();
+                let mut seq = SeqAccess {
+                    items: items.into_iter().enumerate(),
+                    track: self.track,
+                };
+                let value = visitor.visit_seq(&mut seq)?;
+                match seq.items.len() {
+                    0 => Ok(value),
+                    remaining => Err(de::Error::invalid_length(
+                        len,
+                        &format!("{} fewer elements in array", remaining).as_str(),
+                    )),
+                }
+            }
+            NodeKind::Table(table) => visitor.visit_map(MapAccess {
+                entries: table.entries.into_iter(),
+                entry: None,
+                track: self.track,
+            }),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        // There is no null in TOML, a missing key is the only way to spell `None`.
+        visitor.visit_some(self)
     }
 
-    #[inline(always)]
-    fn peek2(&self) -> u8 {
-        *self.source.as_bytes().get(self.current + 1).unwrap_or(&0)
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
     }
 
-    #[inline(always)]
-    fn next(&mut self) -> u8 {
-        match self.source.as_bytes().get(self.current) {
-            Some(&c) => {
-                self.current += 1;
-                c
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.node.kind {
+            _ if name == spanned::NAME => {
+                let span = self.node.span;
+                visitor.visit_map(SpannedAccess::new(span, self))
             }
-            None => 0,
+            NodeKind::Datetime(_) if name == datetime::NAME => self.deserialize_any(visitor),
+            NodeKind::Datetime(_) => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+            _ => self.deserialize_any(visitor),
         }
     }
 
-    #[cold]
-    fn refill(&mut self) {
-        todo!()
+    /// Unit variants are written as strings, all other variants as a table with a single key
+    /// naming the variant.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        let mut entries = match self.node.kind {
+            NodeKind::String(s) => return visitor.visit_enum(s.into_deserializer()),
+            NodeKind::Table(table) => table.entries.into_iter(),
+            _ => return Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        };
+        match (entries.next(), entries.next()) {
+            (Some((variant, entry)), None) => visitor.visit_enum(EnumAccess {
+                variant,
+                node: entry.node,
+                track: self.track,
+            }),
+            _ => Err(de::Error::invalid_value(
+                de::Unexpected::Map,
+                &"a table with a single key naming the variant",
+            )),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map identifier ignored_any
     }
 }
 
-struct IoRead<R>
-where
-    R: std::io::Read,
-{
-    reader: R,
-    base: usize,
-    current: usize,
-    scratch: Box<[u8; SCRATCH_LEN]>,
+struct SeqAccess {
+    items: std::iter::Enumerate<vec::IntoIter<Node>>,
+    track: Track,
 }
 
-impl<R> IoRead<R>
-where
-    R: std::io::Read,
-{
-    fn new(reader: R) -> Self {
-        Self {
-            reader,
-            base: 0,
-            current: 0,
-            scratch: Box::new([0; SCRATCH_LEN]),
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.items.next() {
+            Some((i, node)) => {
+                let node = NodeDeserializer::tracked(node, self.track.clone());
+                self.track.nested(i, || seed.deserialize(node)).map(Some)
+            }
+            None => Ok(None),
         }
     }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
 }
 
-impl<R> private::Sealed for IoRead<R> where R: std::io::Read {}
+struct MapAccess {
+    entries: btree_map::IntoIter<String, Entry>,
+    /// The key and value of the entry whose key was just deserialized.
+    entry: Option<(String, Node)>,
+    track: Track,
+}
 
-impl<'de, R> Read<'de> for IoRead<R>
-where
-    R: std::io::Read,
-{
-    #[inline(always)]
-    fn current(&self) -> usize {
-        todo!()
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match self.entries.next() {
+            Some((key, entry)) => {
+                self.entry = Some((key.clone(), entry.node));
+                seed.deserialize(KeyDeserializer {
+                    key,
+                    span: entry.key_span,
+                })
+                .map(Some)
+            }
+            None => Ok(None),
+        }
     }
 
-    fn eof(&self) -> bool {
-        todo!()
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        match self.entry.take() {
+            Some((key, node)) => {
+                let node = NodeDeserializer::tracked(node, self.track.clone());
+                self.track.nested(key, || seed.deserialize(node))
+            }
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
     }
+}
+
+/// Deserializes the key of a table entry, which may be wrapped in `Spanned`.
+struct KeyDeserializer {
+    key: String,
+    span: Span,
+}
 
-    #[inline(always)]
-    fn peek(&self) -> u8 {
-        todo!()
+impl<'de> de::Deserializer<'de> for KeyDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_string(self.key)
     }
 
-    #[inline(always)]
-    fn peek2(&self) -> u8 {
-        todo!()
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
     }
 
-    #[inline(always)]
-    fn next(&mut self) -> u8 {
-        todo!()
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        if name == spanned::NAME {
+            let key = IntoDeserializer::<Error>::into_deserializer(self.key);
+            visitor.visit_map(SpannedAccess::new(self.span, key))
+        } else {
+            self.deserialize_any(visitor)
+        }
     }
 
-    #[cold]
-    fn refill(&mut self) {
-        todo!()
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        IntoDeserializer::<Error>::into_deserializer(self.key)
+            .deserialize_enum(name, variants, visitor)
     }
-}
 
-enum Token {
-    LiteralString,
-    BasicString,
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct seq tuple tuple_struct map identifier ignored_any
+    }
 }
 
-pub struct Lex<R> {
-    read: R,
-    current_line: usize,
+/// Passes a value and its span through serde as a map, see `spanned::NAME`.
+struct SpannedAccess<D> {
+    span: Span,
+    /// Index into `spanned::FIELDS` of the next entry.
+    field: usize,
+    value: Option<D>,
 }
 
-impl<'de, R> Lex<R>
-where
-    R: Read<'de>,
-{
-    pub fn new(read: R) -> Self {
+impl<D> SpannedAccess<D> {
+    fn new(span: Span, value: D) -> Self {
         Self {
-            read,
-            current_line: 0,
+            span,
+            field: 0,
+            value: Some(value),
         }
     }
+}
 
-    pub fn eof(&self) -> bool {
-        self.read.eof()
-    }
-
-    fn lex_header(&mut self) {
-        self.read.next();
-        let is_array = if self.read.peek() == b'[' {
-            self.read.next();
-            true
-        } else {
-            false
-        };
-        let start = self.read.current();
-
-        loop {
-            match self.read.next() {
-                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' | b'.' => {}
-                b'\'' => {}
-                b'"' => {}
-                b']' => {
-                    if is_array {
-                        if self.read.next() == b']' {
-                            break;
-                        } else {
-                            todo!()
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                _ => {}
-            }
+impl<'de, D> de::MapAccess<'de> for SpannedAccess<D>
+where
+    D: de::Deserializer<'de, Error = Error>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match spanned::FIELDS.get(self.field) {
+            Some(&field) => seed
+                .deserialize(IntoDeserializer::<Error>::into_deserializer(field))
+                .map(Some),
+            None => Ok(None),
         }
-
-        let finish = self.read.current();
-        println!("header (array: {}): [{}..{}]", is_array, start, finish);
     }
 
-    fn lex_key(&mut self) {
-        let start = self.read.current();
-        loop {
-            match self.read.next() {
-                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' | b'.' => {}
-                _ => break,
-            }
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        self.field += 1;
+        match self.field {
+            1 => seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.span.lo())),
+            2 => seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.span.hi())),
+            _ => match self.value.take() {
+                Some(value) => seed.deserialize(value),
+                None => Err(de::Error::custom("value is missing")),
+            },
         }
-        let end = self.read.current();
-        println!("key: [{},{}]", start, end);
     }
+}
 
-    fn lex_literal_string(&mut self) {
-        let start = self.read.current();
+/// Passes a datetime through serde as a single entry map, see `datetime::NAME`.
+struct DatetimeAccess {
+    datetime: Option<Datetime>,
+}
 
-        loop {
-            match self.read.next() {
-                b'"' => {
-                    break;
-                }
-                _ => {}
-            }
+impl<'de> de::MapAccess<'de> for DatetimeAccess {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match self.datetime {
+            Some(_) => seed
+                .deserialize(datetime::FIELD.into_deserializer())
+                .map(Some),
+            None => Ok(None),
         }
+    }
 
-        let end = self.read.current();
-
-        println!("string: [{}, {}]", start, end);
-    }
-
-    fn lex_multiline_literal_string(&mut self) {
-        let start = self.read.current();
-        println!("string: {}", start);
-    }
-
-    fn lex_basic_string(&mut self) {
-        let start = self.read.current();
-
-        loop {
-            match self.read.next() {
-                b'\\' => match self.read.peek() {
-                    b'b' | b't' | b'n' | b'f' | b'r' | b'"' | b'\\' => {
-                        self.read.next();
-                    }
-                    b'u' => {
-                        self.read.next();
-                        self.read.next();
-                        self.read.next();
-                        self.read.next();
-                    }
-                    b'U' => {
-                        self.read.next();
-                        self.read.next();
-                        self.read.next();
-                        self.read.next();
-                        self.read.next();
-                        self.read.next();
-                        self.read.next();
-                        self.read.next();
-                    }
-                    _ => todo!(),
-                },
-                b'"' => {
-                    break;
-                }
-                _ => {}
-            }
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        match self.datetime.take() {
+            Some(datetime) => seed.deserialize(datetime.to_string().into_deserializer()),
+            None => Err(de::Error::custom("value is missing")),
         }
+    }
+}
 
-        let end = self.read.current();
+struct EnumAccess {
+    variant: String,
+    node: Node,
+    track: Track,
+}
 
-        println!("ya basic: [{}, {}]", start, end);
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = Error;
+    type Variant = NodeDeserializer;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, NodeDeserializer)>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant =
+            seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.variant))?;
+        Ok((variant, NodeDeserializer::tracked(self.node, self.track)))
     }
+}
 
-    fn lex_multiline_basic_string(&mut self) {
-        let start = self.read.current();
-        println!("ya basic: {}", start);
-    }
+impl<'de> de::VariantAccess<'de> for NodeDeserializer {
+    type Error = Error;
 
-    pub fn lex(&mut self) {
-        loop {
-            match self.read.peek() {
-                b' ' | b'\t' => {
-                    self.read.next();
-                }
-                b'\n' => {
-                    self.read.next();
-                    self.current_line += 1
-                }
-                b'\r' => {
-                    self.read.next();
-                    if self.read.peek() == b'\n' {
-                        self.read.next();
-                        self.current_line += 1;
-                    } else {
-                        todo!() // error: unaccompanied carrage return. ill-eagle
-                    }
-                }
-                b'#' => loop {
-                    self.read.next();
-                    match self.read.next() {
-                        b'\n' => {
-                            self.current_line += 1;
-                            break;
-                        }
-                        b'\r' => {
-                            if self.read.peek() == b'\n' {
-                                self.read.next();
-                                self.current_line += 1;
-                                break;
-                            }
-                            todo!() // error: unaccompanied carrage return. ill-eagle
-                        }
-                        0x0..=0x8 | 0xa..=0x1f | 0x7f => {
-                            todo!() // error: ill-eagle control character in comment.
-                        }
-                        _ => {}
-                    }
-                },
-                b'=' => {
-                    self.read.next();
-                    println!("eq {}", self.read.current())
-                }
-                b'[' => {
-                    self.lex_header();
-                }
-                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => {
-                    self.lex_key();
-                }
-                b'\'' => {
-                    self.read.next();
-                    if self.read.peek() == b'\'' && self.read.peek2() == b'\'' {
-                        self.read.next();
-                        self.read.next();
-                        self.lex_multiline_literal_string();
-                    } else {
-                        self.lex_literal_string();
-                    };
-                }
-                b'"' => {
-                    self.read.next();
-                    if self.read.peek() == b'\"' && self.read.peek2() == b'\"' {
-                        self.read.next();
-                        self.read.next();
-                        self.lex_multiline_basic_string();
-                    } else {
-                        self.lex_basic_string();
-                    };
-                }
-                0 => {
-                    println!("eof: {}", self.read.current());
-                    break;
-                }
-                _ => {
-                    println!("error: {}", self.read.current());
-                    break;
-                }
-            }
+    fn unit_variant(self) -> Result<()> {
+        match &self.node.kind {
+            NodeKind::Table(table) if table.entries.is_empty() => Ok(()),
+            _ => Err(de::Error::invalid_type(self.unexpected(), &"unit variant")),
         }
     }
-}
 
-pub struct Deserializer<R> {
-    lex: Lex<R>,
-}
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
 
-impl<'de, R> Deserializer<R>
-where
-    R: Read<'de>,
-{
-    fn new(lex: Lex<R>) -> Self {
-        Self { lex }
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        de::Deserializer::deserialize_seq(self, visitor)
     }
 
-    fn end(&self) -> Result<()> {
-        Ok(())
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        de::Deserializer::deserialize_map(self, visitor)
     }
 }
 
-// impl<'de, 'a, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
-//     type Error = Error;
-
-//     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_tuple_struct<V>(
-//         self,
-//         name: &'static str,
-//         len: usize,
-//         visitor: V,
-//     ) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_struct<V>(
-//         self,
-//         name: &'static str,
-//         fields: &'static [&'static str],
-//         visitor: V,
-//     ) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_enum<V>(
-//         self,
-//         name: &'static str,
-//         variants: &'static [&'static str],
-//         visitor: V,
-//     ) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-
-//     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
-//     where
-//         V: de::Visitor<'de>,
-//     {
-//         todo!()
-//     }
-// }
-
-// fn from_trait<'de, R, T>(read: R) -> Result<T>
-// where
-//     R: Read<'de>,
-//     T: de::Deserialize<'de>,
-// {
-//     let mut de = Deserializer::new(Lex::new(read));
-//     let value = de::Deserialize::deserialize(&mut de)?;
-//     de.end()?;
-//     Ok(value)
-// }
-
-pub fn from_str<'a, T>(source: &'a str) -> Result<T>
+/// Deserializes an instance of `T` from a TOML document.
+pub fn from_str<'a, T>(text: &'a str) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    from_trait(StrRead::new(source))
+    T::deserialize(Deserializer::new(text))
 }
 
-pub fn from_reader<R, T>(reader: R) -> Result<T>
+/// Deserializes an instance of `T` from a reader producing a TOML document.
+pub fn from_reader<R, T>(mut reader: R) -> Result<T>
 where
     R: std::io::Read,
     T: de::DeserializeOwned,
 {
-    from_trait(IoRead::new(reader))
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .map_err(|e| Error::Message(e.to_string()))?;
+    from_str(&text)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::spanned::Spanned;
+    use crate::value::{Table, Value};
+    use serde_derive::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+        Pair(i8, i8),
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+        tags: Vec<String>,
+        weight: Option<f32>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        title: String,
+        updated: Datetime,
+        servers: Vec<Server>,
+        limits: HashMap<String, u64>,
+        shapes: Vec<Shape>,
+        missing: Option<bool>,
+        point: (i32, i32),
+    }
+
+    #[test]
+    fn deserialize_struct() {
+        let config: Config = from_str(
+            r#"
+            title = "example"
+            updated = 1979-05-27T07:32:00Z
+            shapes = ["Point", { Circle = 1.5 }, { Rect = { w = 1, h = 2 } }, { Pair = [-1, 1] }]
+            point = [3, 4]
+
+            [limits]
+            cpu = 4
+            mem = 1_024
+
+            [[servers]]
+            host = "alpha"
+            port = 8080
+            tags = []
+
+            [[servers]]
+            host = "beta"
+            port = 8081
+            tags = ["a", "b"]
+            weight = 0.5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.title, "example");
+        assert_eq!(config.updated.to_string(), "1979-05-27T07:32:00Z");
+        assert_eq!(config.servers.len(), 2);
+        assert_eq!(config.servers[1].tags, ["a", "b"]);
+        assert_eq!(config.servers[0].weight, None);
+        assert_eq!(config.servers[1].weight, Some(0.5));
+        assert_eq!(config.limits["mem"], 1024);
+        assert_eq!(
+            config.shapes,
+            [
+                Shape::Point,
+                Shape::Circle(1.5),
+                Shape::Rect { w: 1, h: 2 },
+                Shape::Pair(-1, 1)
+            ]
+        );
+        assert_eq!(config.missing, None);
+        assert_eq!(config.point, (3, 4));
+    }
+
+    #[test]
+    fn deserialize_value() {
+        let text = r#"
+            a = 1
+            b = [1.5, "two", 1979-05-27]
+            [c]
+            d = { e = true }
+            "#;
+        let table: Table = from_str(text).unwrap();
+        assert_eq!(table, crate::parse(text).unwrap());
+        let value: Value = from_str(text).unwrap();
+        assert_eq!(value, Value::Table(table));
+    }
 
     #[test]
-    fn lex_whitespace() {
-        let mut lex = Lex::new(StrRead::new("\t  \t"));
-        lex.lex();
-        assert!(lex.eof());
-
-        let mut lex = Lex::new(StrRead::new("\n \n\n \n"));
-        lex.lex();
-        assert!(lex.eof());
-
-        let mut lex = Lex::new(StrRead::new("\r\n \r\n\r\n "));
-        lex.lex();
-        assert!(lex.eof());
+    fn deserialize_spanned() {
+        #[derive(Debug, Deserialize)]
+        struct Server {
+            host: Spanned<String>,
+            port: Spanned<u16>,
+            started: Spanned<Datetime>,
+            limits: Spanned<HashMap<Spanned<String>, Spanned<u64>>>,
+            tags: Option<Spanned<Vec<Spanned<String>>>>,
+            shape: Spanned<Shape>,
+        }
+
+        let text = "host = 'alpha'\nport = 80\nstarted = 1979-05-27\nshape = 'Point'\n\
+                    tags = ['a', \"b\"]\n[limits]\ncpu = 4";
+        let source = |span: Span| &text[span.lo()..span.hi()];
+
+        let server: Spanned<Server> = from_str(text).unwrap();
+        assert_eq!(server.span(), Span::new(0, text.len()));
+        let server = server.into_inner();
+        assert_eq!(source(server.host.span()), "alpha");
+        assert_eq!(server.host.line_col(text), (1, 9));
+        assert_eq!(*server.port.get_ref(), 80);
+        assert_eq!(source(server.port.span()), "80");
+        assert_eq!(server.port.line_col(text), (2, 8));
+        assert_eq!(source(server.started.span()), "1979-05-27");
+        assert_eq!(*server.shape.get_ref(), Shape::Point);
+
+        let tags = server.tags.unwrap();
+        assert_eq!(source(tags.span()), "['a', \"b\"]");
+        assert_eq!(source(tags.get_ref()[1].span()), "b");
+
+        assert_eq!(source(server.limits.span()), "limits");
+        let (key, value) = server.limits.get_ref().iter().next().unwrap();
+        assert_eq!(source(key.span()), "cpu");
+        assert_eq!(key.line_col(text), (7, 1));
+        assert_eq!(source(value.span()), "4");
+        assert_eq!(
+            server.limits.get_ref()["cpu"],
+            Spanned::new(Span::new(0, 0), 4)
+        );
+
+        let value: HashMap<String, Spanned<Value>> = from_str("a = { b = 1 }").unwrap();
+        assert_eq!(value["a"].span(), Span::new(4, 13));
+
+        assert!(serde_json::from_str::<Spanned<u32>>("1").is_err());
+    }
+
+    #[test]
+    fn deserialize_fail() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Port {
+            port: u8,
+        }
+        assert!(from_str::<Port>("port = 256").is_err());
+        assert!(from_str::<Port>("port = -1").is_err());
+        assert!(from_str::<Port>("port = '1'").is_err());
+        assert!(from_str::<Port>("").is_err());
+        assert!(from_str::<Port>("port = ").is_err());
+        assert!(from_str::<(i64,)>("").is_err());
+        assert!(from_str::<HashMap<String, (i64,)>>("a = [1, 2]").is_err());
+        assert!(from_str::<HashMap<String, String>>("a = 1979-05-27").is_err());
+        assert!(from_str::<HashMap<String, Datetime>>("a = { b = 1 }").is_err());
+        assert!(from_str::<HashMap<String, Shape>>("a = 'Square'").is_err());
+        assert!(from_str::<HashMap<String, Shape>>("a = { Point = 1 }").is_err());
+        assert!(from_reader::<_, Table>(&b"a = '\xff'"[..]).is_err());
     }
 }
@@ -0,0 +1,437 @@
+//! Structural differences between two documents.
+//!
+//! Documents are compared by value, so formatting, comments, the order of keys and the way a
+//! table is written (a `[header]`, dotted keys or inline) never show up as changes. Array items
+//! are compared by position, unless the array is identified by a key with `Diff::identify`, in
+//! which case items are tables matched by the value of that key wherever they are in the array.
+//!
+//! ```
+//! use toml_rust::diff::{self, Diff};
+//!
+//! let old = "[[plugins]]\nname = 'lint'\nlevel = 1\n\n[[plugins]]\nname = 'fmt'\n";
+//! let new = "[[plugins]]\nname = 'fmt'\n\n[[plugins]]\nname = 'lint'\nlevel = 2\n";
+//! let mut diff = Diff::new();
+//! diff.identify("plugins".parse().unwrap(), "name");
+//! let changes = diff.run(old, new).unwrap();
+//! assert_eq!(
+//!     diff::report(&changes, old, new),
+//!     "~ plugins[1].level = 1 -> 2 (3:9 -> 6:9)\n"
+//! );
+//! ```
+
+use crate::error::{Error, Result};
+use crate::lex::Span;
+use crate::parse::{self, Node, NodeKind};
+use crate::path::{ByKeys, Path};
+use crate::value::Value;
+use std::collections::BTreeSet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// The value changed. Tables and arrays are never changed as a whole, only their contents,
+    /// but a value may change from one type to another.
+    Changed,
+}
+
+/// A value in one of the two documents.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Side {
+    path: Path,
+    span: Span,
+    value: Value,
+}
+
+impl Side {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the span of the value in its document. Tables defined by a `[header]` are spanned
+    /// by the last key of the header.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Returns the 1-based line and column of the start of the value in `text`, which must be
+    /// the document it's from.
+    pub fn line_col(&self, text: &str) -> (usize, usize) {
+        self.span.line_col(text)
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+}
+
+/// A difference between the two documents.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    kind: ChangeKind,
+    old: Option<Side>,
+    new: Option<Side>,
+}
+
+impl Change {
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// Returns the path of the value in the new document, or in the old one if it was removed.
+    /// The two differ only in the indices of items matched by identity.
+    pub fn path(&self) -> &Path {
+        match (&self.new, &self.old) {
+            (Some(side), _) | (None, Some(side)) => &side.path,
+            (None, None) => unreachable!("a change has at least one side"),
+        }
+    }
+
+    /// Returns the value in the old document, unless it was added.
+    pub fn before(&self) -> Option<&Side> {
+        self.old.as_ref()
+    }
+
+    /// Returns the value in the new document, unless it was removed.
+    pub fn after(&self) -> Option<&Side> {
+        self.new.as_ref()
+    }
+}
+
+/// Compares documents. Without any identities this is what `diff` does.
+#[derive(Clone, Debug, Default)]
+pub struct Diff {
+    identities: ByKeys<String>,
+}
+
+/// Compares two documents, matching array items by position.
+pub fn diff(old: &str, new: &str) -> Result<Vec<Change>> {
+    Diff::new().run(old, new)
+}
+
+impl Diff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches the items of the arrays at `path` by the value of their key `key`, so that
+    /// reordering them isn't a change. `products.variants` identifies the `variants` in every
+    /// item of `products`.
+    pub fn identify(&mut self, path: Path, key: impl Into<String>) {
+        self.identities.insert(&path, key.into());
+    }
+
+    /// Returns the changes from `old` to `new`, in key order for tables and item order for
+    /// arrays, with removed items before added ones. Errors in either document are reported as
+    /// in the source `old` or `new`.
+    pub fn run(&self, old: &str, new: &str) -> Result<Vec<Change>> {
+        let root = |name: &str, text: &str| match parse::parse_nodes(text) {
+            Ok(root) => Ok(Node {
+                span: Span::new(0, text.len()),
+                kind: NodeKind::Table(root),
+            }),
+            Err(e) => Err(e.in_source(name)),
+        };
+        let (old, new) = (root("old", old)?, root("new", new)?);
+        let mut changes = Vec::new();
+        let mut paths = Paths::default();
+        self.compare(&mut paths, &old, &new, &mut changes)?;
+        Ok(changes)
+    }
+
+    fn compare(
+        &self,
+        paths: &mut Paths,
+        old: &Node,
+        new: &Node,
+        changes: &mut Vec<Change>,
+    ) -> Result<()> {
+        match (&old.kind, &new.kind) {
+            (NodeKind::Table(old_table), NodeKind::Table(new_table)) => {
+                let keys: BTreeSet<_> = old_table
+                    .entries
+                    .keys()
+                    .chain(new_table.entries.keys())
+                    .collect();
+                for key in keys {
+                    let (old, new) = (old_table.entries.get(key), new_table.entries.get(key));
+                    paths.push_key(key);
+                    match (old, new) {
+                        (Some(old), Some(new)) => {
+                            self.compare(paths, &old.node, &new.node, changes)?
+                        }
+                        (Some(old), None) => changes.push(paths.removed(&old.node)),
+                        (None, Some(new)) => changes.push(paths.added(&new.node)),
+                        (None, None) => {}
+                    }
+                    paths.pop_key();
+                }
+            }
+            (NodeKind::Array(old_items, _), NodeKind::Array(new_items, _)) => {
+                match self.identities.get(&paths.keys) {
+                    Some(key) => {
+                        self.compare_by_identity(paths, key, old_items, new_items, changes)?
+                    }
+                    None => {
+                        for i in 0..old_items.len().max(new_items.len()) {
+                            paths.push_index(i, i);
+                            match (old_items.get(i), new_items.get(i)) {
+                                (Some(old), Some(new)) => self.compare(paths, old, new, changes)?,
+                                (Some(old), None) => changes.push(paths.removed(old)),
+                                (None, Some(new)) => changes.push(paths.added(new)),
+                                (None, None) => {}
+                            }
+                            paths.pop_index();
+                        }
+                    }
+                }
+            }
+            (old_kind, new_kind) => {
+                if !same(old_kind, new_kind) {
+                    changes.push(Change {
+                        kind: ChangeKind::Changed,
+                        old: Some(side(&paths.old, old)),
+                        new: Some(side(&paths.new, new)),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn compare_by_identity(
+        &self,
+        paths: &mut Paths,
+        key: &str,
+        old_items: &[Node],
+        new_items: &[Node],
+        changes: &mut Vec<Change>,
+    ) -> Result<()> {
+        let old_ids = identities(&paths.keys, key, "old", old_items)?;
+        let new_ids = identities(&paths.keys, key, "new", new_items)?;
+        let mut matched = vec![false; new_items.len()];
+        for (i, (old, id)) in old_items.iter().zip(&old_ids).enumerate() {
+            let found = (0..new_items.len()).find(|&j| !matched[j] && same(id, new_ids[j]));
+            match found {
+                Some(j) => {
+                    matched[j] = true;
+                    paths.push_index(i, j);
+                    self.compare(paths, old, &new_items[j], changes)?;
+                    paths.pop_index();
+                }
+                None => {
+                    paths.push_index(i, i);
+                    changes.push(paths.removed(old));
+                    paths.pop_index();
+                }
+            }
+        }
+        for (j, new) in new_items.iter().enumerate().filter(|(j, _)| !matched[*j]) {
+            paths.push_index(j, j);
+            changes.push(paths.added(new));
+            paths.pop_index();
+        }
+        Ok(())
+    }
+}
+
+/// The paths to the values being compared in each document, and the keys leading to them.
+#[derive(Default)]
+struct Paths {
+    old: Path,
+    new: Path,
+    keys: Path,
+}
+
+impl Paths {
+    fn push_key(&mut self, key: &str) {
+        self.old.push(key);
+        self.new.push(key);
+        self.keys.push(key);
+    }
+
+    fn pop_key(&mut self) {
+        self.old.pop();
+        self.new.pop();
+        self.keys.pop();
+    }
+
+    fn push_index(&mut self, old: usize, new: usize) {
+        self.old.push(old);
+        self.new.push(new);
+    }
+
+    fn pop_index(&mut self) {
+        self.old.pop();
+        self.new.pop();
+    }
+
+    fn added(&self, node: &Node) -> Change {
+        Change {
+            kind: ChangeKind::Added,
+            old: None,
+            new: Some(side(&self.new, node)),
+        }
+    }
+
+    fn removed(&self, node: &Node) -> Change {
+        Change {
+            kind: ChangeKind::Removed,
+            old: Some(side(&self.old, node)),
+            new: None,
+        }
+    }
+}
+
+fn side(path: &Path, node: &Node) -> Side {
+    Side {
+        path: path.clone(),
+        span: node.span,
+        value: node.clone().into_value(),
+    }
+}
+
+/// Returns the scalar value of `item`'s key `key`, if it's a table that has one.
+fn identity<'a>(item: &'a Node, key: &str) -> Option<&'a NodeKind> {
+    match &item.kind {
+        NodeKind::Table(table) => match &table.entries.get(key)?.node.kind {
+            NodeKind::Table(_) | NodeKind::Array(..) => None,
+            kind => Some(kind),
+        },
+        _ => None,
+    }
+}
+
+/// Returns the identity of each item, or an error naming the source `name` for one that has
+/// none.
+fn identities<'a>(
+    keys: &Path,
+    key: &str,
+    name: &str,
+    items: &'a [Node],
+) -> Result<Vec<&'a NodeKind>> {
+    items
+        .iter()
+        .map(|item| {
+            identity(item, key).ok_or_else(|| {
+                let error = Error::Invalid {
+                    message: format!("item of `{}` has no `{}` to match by", keys, key),
+                    span: item.span,
+                };
+                error.in_source(name)
+            })
+        })
+        .collect()
+}
+
+/// Compares scalars by value, treating every NaN as the same value.
+fn same(a: &NodeKind, b: &NodeKind) -> bool {
+    match (a, b) {
+        (NodeKind::Float(a), NodeKind::Float(b)) => a == b || (a.is_nan() && b.is_nan()),
+        _ => a == b,
+    }
+}
+
+/// Writes the changes one per line, as `+` for added, `-` for removed and `~` for changed
+/// values, followed by the path, the value and where it is in the documents. `old` and `new`
+/// are the documents the changes were found in.
+pub fn report(changes: &[Change], old: &str, new: &str) -> String {
+    let mut out = String::new();
+    for change in changes {
+        let path = change.path().to_string();
+        let path = if path.is_empty() { "." } else { &path };
+        let line = match (&change.old, &change.new) {
+            (Some(o), Some(n)) => {
+                let ((ol, oc), (nl, nc)) = (o.line_col(old), n.line_col(new));
+                format!(
+                    "~ {} = {} -> {} ({}:{} -> {}:{})",
+                    path, o.value, n.value, ol, oc, nl, nc
+                )
+            }
+            (None, Some(n)) => {
+                let (l, c) = n.line_col(new);
+                format!("+ {} = {} ({}:{})", path, n.value, l, c)
+            }
+            (Some(o), None) => {
+                let (l, c) = o.line_col(old);
+                format!("- {} = {} ({}:{})", path, o.value, l, c)
+            }
+            (None, None) => continue,
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(diff: &Diff, old: &str, new: &str) -> Vec<String> {
+        let changes = diff.run(old, new).unwrap();
+        report(&changes, old, new)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn diff_success() {
+        let plain = Diff::new();
+
+        // Formatting, comments, key order and table style aren't changes.
+        let old = "a = 1\nb = { c = 'x', d = [1, 2] }\nf = nan\n";
+        let new = "a = 0x1\nf = -nan\n# comment\n[b]\nd = [ 1,\n  2 ]\nc = \"x\"\n\n[top]\n";
+        assert_eq!(lines(&plain, old, new), ["+ top = {} (9:2)"]);
+        assert_eq!(lines(&plain, new, old), ["- top = {} (9:2)"]);
+
+        let old = "name = 'app'\nports = [80, 443]\n[server]\nhost = 'a'\nport = 80\n";
+        let new = "name = 'app'\nports = [80, 8443, 9000]\n[server]\nhost = 'b'\nport = '80'\n";
+        assert_eq!(
+            lines(&plain, old, new),
+            [
+                r#"~ ports[1] = 443 -> 8443 (2:14 -> 2:14)"#,
+                r#"+ ports[2] = 9000 (2:20)"#,
+                r#"~ server.host = "a" -> "b" (4:9 -> 4:9)"#,
+                r#"~ server.port = 80 -> "80" (5:8 -> 5:9)"#,
+            ]
+        );
+        assert_eq!(lines(&plain, new, old)[1], "- ports[2] = 9000 (2:20)");
+
+        // Arrays of tables matched by identity rather than position.
+        let old = "[[p]]\nname = 'a'\nv = 1\n\n[[p]]\nname = 'b'\n\n[[p]]\nname = 'c'\n";
+        let new = "[[p]]\nname = 'c'\n\n[[p]]\nname = 'd'\n\n[[p]]\nname = 'a'\nv = 2\n";
+        assert_eq!(lines(&plain, old, new).len(), 5);
+        let mut by_name = Diff::new();
+        by_name.identify("p".parse().unwrap(), "name");
+        let changes = by_name.run(old, new).unwrap();
+        assert_eq!(
+            report(&changes, old, new).lines().collect::<Vec<_>>(),
+            [
+                "~ p[2].v = 1 -> 2 (3:5 -> 9:5)",
+                r#"- p[1] = { name = "b" } (5:3)"#,
+                r#"+ p[1] = { name = "d" } (4:3)"#,
+            ]
+        );
+        assert_eq!(changes[0].before().unwrap().path().to_string(), "p[0].v");
+        assert_eq!(changes[0].after().unwrap().path().to_string(), "p[2].v");
+        assert_eq!(changes[1].kind(), ChangeKind::Removed);
+        assert!(changes[1].after().is_none());
+
+        assert!(diff(old, old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn diff_fail() {
+        let err = diff("a = 1", "a = ").unwrap_err();
+        assert_eq!(err.to_string(), "new: Unexpected { pos: 4 }");
+        let mut by_id = Diff::new();
+        by_id.identify("p".parse().unwrap(), "id");
+        let err = by_id.run("p = [{ id = 1 }, 2]", "p = []").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "old: item of `p` has no `id` to match by at 17"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Finding the configuration files of an application in the usual places, following the XDG
+//! base directory specification.
+//!
+//! For an application `app`, the places are, from most to least important:
+//!
+//! - `app.toml` in the current directory,
+//! - `$XDG_CONFIG_HOME/app/config.toml`, where `XDG_CONFIG_HOME` defaults to `$HOME/.config`,
+//! - `app/config.toml` in each of `$XDG_CONFIG_DIRS`, which defaults to `/etc/xdg`,
+//! - `/etc/app/config.toml`.
+//!
+//! Files are returned least important first, which is the order layers are added to a `Merge`.
+//!
+//! ```
+//! use std::path::PathBuf;
+//! use toml_rust::discover::Discovery;
+//!
+//! let mut discovery = Discovery::new("app");
+//! discovery.env([("HOME", "/home/me"), ("XDG_CONFIG_DIRS", "/opt/xdg:/etc/xdg")]);
+//! assert_eq!(
+//!     discovery.candidates(),
+//!     [
+//!         "/etc/app/config.toml",
+//!         "/etc/xdg/app/config.toml",
+//!         "/opt/xdg/app/config.toml",
+//!         "/home/me/.config/app/config.toml",
+//!         "app.toml",
+//!     ]
+//!     .map(PathBuf::from)
+//! );
+//! ```
+
+use crate::error::{Error, Result};
+use crate::merge::{Merge, Merged};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where to look for the configuration of an application.
+#[derive(Clone, Debug)]
+pub struct Discovery {
+    app: String,
+    file_name: String,
+    env: Option<BTreeMap<String, String>>,
+    current_dir: Option<PathBuf>,
+}
+
+impl Discovery {
+    /// Looks for the configuration of `app`, using the environment of the process.
+    pub fn new(app: impl Into<String>) -> Self {
+        Discovery {
+            app: app.into(),
+            file_name: "config.toml".to_string(),
+            env: None,
+            current_dir: None,
+        }
+    }
+
+    /// Sets the name of the file in configuration directories. The default is `config.toml`.
+    pub fn file_name(&mut self, name: impl Into<String>) {
+        self.file_name = name.into();
+    }
+
+    /// Uses `vars` as the environment instead of that of the process, such as in tests.
+    pub fn env<K, V>(&mut self, vars: impl IntoIterator<Item = (K, V)>)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let vars = vars.into_iter().map(|(k, v)| (k.into(), v.into()));
+        self.env = Some(vars.collect());
+    }
+
+    /// Sets the directory to look for `app.toml` in. By default it's looked for relative to the
+    /// current directory of the process.
+    pub fn current_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.current_dir = Some(dir.into());
+    }
+
+    /// Returns a variable of the environment, unless it's unset or empty.
+    fn var(&self, name: &str) -> Option<String> {
+        let value = match &self.env {
+            Some(env) => env.get(name).cloned(),
+            None => std::env::var(name).ok(),
+        };
+        value.filter(|v| !v.is_empty())
+    }
+
+    /// Returns every place a file is looked for, least important first. Relative paths in
+    /// `XDG_CONFIG_HOME` and `XDG_CONFIG_DIRS` are ignored, as the specification requires, and
+    /// places are only listed once.
+    pub fn candidates(&self) -> Vec<PathBuf> {
+        let in_dir = |dir: &Path| dir.join(&self.app).join(&self.file_name);
+        // Most important first, reversed at the end.
+        let mut places = Vec::new();
+        let local = format!("{}.toml", self.app);
+        places.push(match &self.current_dir {
+            Some(dir) => dir.join(local),
+            None => PathBuf::from(local),
+        });
+        let home = self
+            .var("HOME")
+            .map(|home| Path::new(&home).join(".config"));
+        let config_home = self.var("XDG_CONFIG_HOME").map(PathBuf::from);
+        if let Some(dir) = config_home.filter(|d| d.is_absolute()).or(home) {
+            places.push(in_dir(&dir));
+        }
+        let config_dirs = self.var("XDG_CONFIG_DIRS");
+        for dir in config_dirs.as_deref().unwrap_or("/etc/xdg").split(':') {
+            if Path::new(dir).is_absolute() {
+                places.push(in_dir(Path::new(dir)));
+            }
+        }
+        places.push(in_dir(Path::new("/etc")));
+
+        let mut candidates = Vec::new();
+        for place in places {
+            if !candidates.contains(&place) {
+                candidates.push(place);
+            }
+        }
+        candidates.reverse();
+        candidates
+    }
+
+    /// Returns the files that exist, least important first.
+    pub fn find(&self) -> Vec<PathBuf> {
+        let mut found = self.candidates();
+        found.retain(|path| path.is_file());
+        found
+    }
+
+    /// Loads the files that exist, merging them the default way.
+    pub fn load(&self) -> Result<Merged> {
+        let mut merge = Merge::new();
+        self.load_into(&mut merge)?;
+        merge.finish()
+    }
+
+    /// Adds the files that exist to `merge` as layers, named by their paths.
+    pub fn load_into(&self, merge: &mut Merge) -> Result<()> {
+        for path in self.find() {
+            let name = path.display().to_string();
+            let text = fs::read_to_string(&path)
+                .map_err(|e| Error::Message(format!("{}: {}", name, e)))?;
+            merge.add(name, text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn discover_success() {
+        let mut discovery = Discovery::new("app");
+        discovery.env([
+            ("HOME", "/home/me"),
+            ("XDG_CONFIG_HOME", "/cfg"),
+            ("XDG_CONFIG_DIRS", "relative:/etc:/opt/xdg"),
+        ]);
+        discovery.current_dir("/work");
+        discovery.file_name("app.toml");
+        assert_eq!(
+            discovery.candidates(),
+            [
+                "/opt/xdg/app/app.toml",
+                "/etc/app/app.toml",
+                "/cfg/app/app.toml",
+                "/work/app.toml",
+            ]
+            .map(PathBuf::from)
+        );
+
+        // Empty and relative values are as good as unset.
+        discovery.env([("XDG_CONFIG_HOME", "cfg"), ("XDG_CONFIG_DIRS", "")]);
+        assert_eq!(
+            discovery.candidates(),
+            [
+                "/etc/app/app.toml",
+                "/etc/xdg/app/app.toml",
+                "/work/app.toml"
+            ]
+            .map(PathBuf::from)
+        );
+
+        let dir = TempDir::new(
+            "discover",
+            &[
+                ("home/.config/app/config.toml", "a = 1\nb = 1\n"),
+                ("work/app.toml", "b = 2\n"),
+            ],
+        );
+        let dir = &dir.0;
+        let mut discovery = Discovery::new("app");
+        discovery.env([
+            ("HOME", dir.join("home").to_str().unwrap()),
+            ("XDG_CONFIG_DIRS", dir.join("none").to_str().unwrap()),
+        ]);
+        discovery.current_dir(dir.join("work"));
+        let found = discovery.find();
+        let found: Vec<_> = found.iter().map(|p| p.strip_prefix(dir).unwrap()).collect();
+        assert_eq!(
+            found,
+            ["home/.config/app/config.toml", "work/app.toml"].map(Path::new)
+        );
+        let merged = discovery.load().unwrap();
+        assert_eq!(merged.table()["a"].as_integer(), Some(1));
+        assert_eq!(merged.table()["b"].as_integer(), Some(2));
+        let origin = merged.origin(&"b".parse().unwrap()).unwrap();
+        assert!(origin.name().ends_with("work/app.toml"));
+    }
+}
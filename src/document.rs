@@ -0,0 +1,1206 @@
+//! Editing a document in place, keeping its comments, ordering and formatting.
+//!
+//! Every edit is made by splicing text into the document, after which it's parsed again. Bytes
+//! outside the spliced ranges are never touched, and an edit that would leave the document
+//! invalid is rolled back.
+
+use crate::cst::{Kind, NodeId, Tree};
+use crate::error::{Error, Result};
+use crate::lex::{Span, Sym, Symbol};
+use crate::parse;
+use crate::path::{self, Path, Segment};
+use crate::ser;
+use crate::value::{Array, Table, Value};
+use std::fmt;
+use std::str::FromStr;
+
+/// Where a path is written in the document. A path can be written in several places, such as a
+/// table extended by several dotted keys.
+#[derive(Clone, Copy, Debug)]
+enum Def {
+    /// A key/value pair in `container`. The first `depth` segments of its key lead to the path,
+    /// so if that's all of them the path is the value.
+    KeyValue {
+        node: NodeId,
+        container: NodeId,
+        depth: usize,
+    },
+    /// A table or array of tables element with its own header.
+    Section { node: NodeId },
+    /// An item of an array value.
+    Item { node: NodeId, array: NodeId },
+}
+
+/// Where new entries of a table go.
+enum Container {
+    /// After the key/value pairs of a table section, or the root.
+    Section(NodeId),
+    /// At the end of an inline table.
+    Inline(NodeId),
+    /// After the last of the dotted keys defining the table, with the same prefix.
+    Dotted { anchor: NodeId, depth: usize },
+    /// The table only exists as the parent of other tables, so it needs a header of its own.
+    Implicit,
+}
+
+/// A TOML document that can be edited without losing its formatting.
+///
+/// Values are read through `as_table` and friends; edits go through `TableMut` and `ArrayMut`
+/// handles. New keys are added after the existing ones of their table, new tables after the
+/// existing tables they belong in, and everything not being edited is written back exactly as
+/// it was read.
+///
+/// ```
+/// let mut doc: toml_rust::DocumentMut = "# Release\n[package]\nversion = \"1.0\"  # bump\n"
+///     .parse()
+///     .unwrap();
+/// let mut package = doc.table("package").unwrap();
+/// package.insert("version", "1.1").unwrap();
+/// package.insert("edition", "2021").unwrap();
+/// assert_eq!(
+///     doc.to_string(),
+///     "# Release\n[package]\nversion = \"1.1\"  # bump\nedition = \"2021\"\n"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct DocumentMut {
+    root: Table,
+    tree: Tree,
+    defs: Vec<(Vec<Segment>, Def)>,
+}
+
+impl DocumentMut {
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::build(text.to_string())
+    }
+
+    fn build(text: String) -> Result<Self> {
+        let root = parse::parse(&text)?;
+        let tree = Tree::new(&text)?;
+        let defs = Indexer::new(&tree).index()?;
+        Ok(DocumentMut { root, tree, defs })
+    }
+
+    /// The text of the document.
+    pub fn as_str(&self) -> &str {
+        self.tree.text()
+    }
+
+    /// The syntax tree of the document as it is now.
+    pub fn syntax(&self) -> &Tree {
+        &self.tree
+    }
+
+    pub fn as_table(&self) -> &Table {
+        &self.root
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.root.get(key)
+    }
+
+    /// Returns a handle for editing the root table.
+    pub fn root(&mut self) -> TableMut<'_> {
+        TableMut {
+            doc: self,
+            path: Vec::new(),
+        }
+    }
+
+    /// Returns a handle for editing the table at `key`, if there is one.
+    pub fn table(&mut self, key: &str) -> Option<TableMut<'_>> {
+        TableMut::new(self, vec![Segment::Key(key.to_string())])
+    }
+
+    /// Returns a handle for editing the array at `key`, if there is one.
+    pub fn array(&mut self, key: &str) -> Option<ArrayMut<'_>> {
+        ArrayMut::new(self, vec![Segment::Key(key.to_string())])
+    }
+
+    /// Returns a handle for editing the table at `path`, if there is one. The empty path is the
+    /// root table.
+    pub fn table_at(&mut self, path: &Path) -> Option<TableMut<'_>> {
+        TableMut::new(self, path.segments().to_vec())
+    }
+
+    /// Returns a handle for editing the array at `path`, if there is one.
+    pub fn array_at(&mut self, path: &Path) -> Option<ArrayMut<'_>> {
+        ArrayMut::new(self, path.segments().to_vec())
+    }
+
+    /// Sets `key` in the root table. See `TableMut::insert`.
+    pub fn insert(&mut self, key: &str, value: impl Into<Value>) -> Result<Option<Value>> {
+        self.root().insert(key, value)
+    }
+
+    /// Removes `key` from the root table. See `TableMut::remove`.
+    pub fn remove(&mut self, key: &str) -> Result<Option<Value>> {
+        self.root().remove(key)
+    }
+
+    fn lookup(&self, path: &[Segment]) -> Option<&Value> {
+        path::get_in(path, &self.root)
+    }
+
+    fn lookup_table(&self, path: &[Segment]) -> Option<&Table> {
+        match path {
+            [] => Some(&self.root),
+            _ => self.lookup(path)?.as_table(),
+        }
+    }
+
+    fn slice(&self, span: Span) -> &str {
+        &self.as_str()[span.lo()..span.hi()]
+    }
+
+    /// Runs `f`, restoring the document if it fails.
+    fn atomically<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let saved = self.clone();
+        let result = f(self);
+        if result.is_err() {
+            *self = saved;
+        }
+        result
+    }
+
+    /// Replaces each span with its text, with its line endings changed to those of the
+    /// document, and parses the result. The spans may not overlap.
+    fn splice(&mut self, mut edits: Vec<(Span, String)>) -> Result<()> {
+        edits.sort_by_key(|(span, _)| (span.lo(), span.hi()));
+        let newline = self.newline();
+        let mut text = String::with_capacity(self.as_str().len());
+        let mut pos = 0;
+        for (span, replacement) in &edits {
+            text.push_str(&self.as_str()[pos..span.lo()]);
+            text.push_str(&with_newline(replacement, newline));
+            pos = span.hi();
+        }
+        text.push_str(&self.as_str()[pos..]);
+        *self = Self::build(text)?;
+        Ok(())
+    }
+
+    /// Inserts `text` at `pos`.
+    fn insert_text(&mut self, pos: usize, text: String) -> Result<()> {
+        self.splice(vec![(Span::new(pos, pos), text)])
+    }
+
+    /// The line ending of the document, which is that of its first line, or `\n` if it has
+    /// only one.
+    pub(crate) fn newline(&self) -> &'static str {
+        let mut tokens = self.tree.root().descendants();
+        match tokens.find(|node| node.kind() == Kind::Newline) {
+            Some(node) if node.text() == "\r\n" => "\r\n",
+            _ => "\n",
+        }
+    }
+
+    /// The definitions of `path` itself.
+    fn defs_at<'a>(&'a self, path: &'a [Segment]) -> impl Iterator<Item = Def> + 'a {
+        self.defs
+            .iter()
+            .filter(move |(p, _)| p == path)
+            .map(|(_, def)| *def)
+    }
+
+    /// The definitions of `path` and everything below it.
+    fn defs_under<'a>(&'a self, path: &'a [Segment]) -> impl Iterator<Item = Def> + 'a {
+        self.defs
+            .iter()
+            .filter(move |(p, _)| p.starts_with(path))
+            .map(|(_, def)| *def)
+    }
+
+    /// The node of a value written in place: after a key, or in an array.
+    fn value_node(&self, path: &[Segment]) -> Option<NodeId> {
+        self.defs_at(path).find_map(|def| match def {
+            Def::KeyValue { node, depth, .. } if depth == self.tree.key_segments(node).len() => {
+                self.tree.value(node)
+            }
+            Def::Item { node, .. } => Some(node),
+            _ => None,
+        })
+    }
+
+    fn container(&self, path: &[Segment]) -> Container {
+        if path.is_empty() {
+            return Container::Section(self.tree.root_id());
+        }
+        if let Some(node) = self.value_node(path) {
+            return Container::Inline(node);
+        }
+        let mut dotted = None;
+        for def in self.defs_at(path) {
+            match def {
+                Def::Section { node } => return Container::Section(node),
+                Def::KeyValue { node, depth, .. } => dotted = Some((node, depth)),
+                Def::Item { .. } => {}
+            }
+        }
+        match dotted {
+            Some((anchor, depth)) => Container::Dotted { anchor, depth },
+            None => Container::Implicit,
+        }
+    }
+
+    /// The end of the last section belonging to `path`, where new sections below it go.
+    fn sections_end(&self, path: &[Segment]) -> Option<usize> {
+        self.defs_under(path)
+            .filter_map(|def| match def {
+                Def::Section { node } => Some(self.tree.span(node).hi()),
+                _ => None,
+            })
+            .max()
+    }
+
+    /// The start of the first section belonging to `path`.
+    fn sections_start(&self, path: &[Segment]) -> Option<usize> {
+        self.defs_under(path)
+            .filter_map(|def| match def {
+                Def::Section { node } => Some(self.attached_start(self.tree.span(node).lo())),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// The start of the comment lines directly above the line `pos` is on, which belong to it.
+    fn attached_start(&self, pos: usize) -> usize {
+        let mut pos = self.line_start(pos);
+        while pos > 0 {
+            let start = self.line_start(pos - 1);
+            if !self.as_str()[start..pos].trim_start().starts_with('#') {
+                break;
+            }
+            pos = start;
+        }
+        pos
+    }
+
+    fn line_start(&self, pos: usize) -> usize {
+        self.as_str()[..pos].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// The position after the newline ending the line `pos` is on.
+    fn line_end(&self, pos: usize) -> usize {
+        self.as_str()[pos..]
+            .find('\n')
+            .map_or(self.as_str().len(), |i| pos + i + 1)
+    }
+
+    /// The whitespace at the start of the line `pos` is on.
+    fn indent(&self, pos: usize) -> &str {
+        let start = self.line_start(pos);
+        let line = &self.as_str()[start..pos];
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+    }
+
+    /// Inserts `line` on its own line after the one `anchor` ends on, with the same indentation.
+    fn insert_line_after(&mut self, anchor: NodeId, line: &str) -> Result<()> {
+        let span = self.tree.span(anchor);
+        let pos = self.line_end(span.hi());
+        let mut text = String::new();
+        if !self.as_str()[..pos].ends_with('\n') && pos > 0 {
+            text.push('\n');
+        }
+        text.push_str(self.indent(span.lo()));
+        text.push_str(line);
+        text.push('\n');
+        self.insert_text(pos, text)
+    }
+
+    /// Inserts `line` before the first table, or at the end if there are none.
+    fn insert_root_line(&mut self, line: &str) -> Result<()> {
+        let root = self.tree.root_id();
+        let first_table = self.tree.child(root, Kind::Table);
+        let Some(table) = first_table else {
+            let mut text = String::new();
+            if !self.as_str().is_empty() && !self.as_str().ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(line);
+            text.push('\n');
+            return self.insert_text(self.as_str().len(), text);
+        };
+        let pos = self.attached_start(self.tree.span(table).lo());
+        self.insert_text(pos, format!("{}\n\n", line))
+    }
+
+    /// Inserts sections at `pos`, separated from what's around them by blank lines.
+    fn insert_sections(&mut self, pos: usize, sections: &str, before: bool) -> Result<()> {
+        let mut text = String::new();
+        let preceding = &self.as_str()[..pos];
+        let blank = preceding.ends_with("\n\n") || preceding.ends_with("\n\r\n");
+        if !preceding.is_empty() && !blank {
+            text.push_str(if preceding.ends_with('\n') {
+                "\n"
+            } else {
+                "\n\n"
+            });
+        }
+        text.push_str(sections);
+        if before {
+            text.push('\n');
+        }
+        self.insert_text(pos, text)
+    }
+
+    /// Adds `key` to the table at `path`, which doesn't have it yet.
+    fn insert_new(&mut self, path: &[Segment], key: &str, value: &Value) -> Result<()> {
+        let mut entry = String::new();
+        ser::write_key(&mut entry, key);
+        entry.push_str(" = ");
+        ser::write_value(&mut entry, value);
+
+        let keys = || -> Vec<&str> {
+            let keys = path.iter().filter_map(|segment| match segment {
+                Segment::Key(key) => Some(key.as_str()),
+                Segment::Index(_) => None,
+            });
+            keys.chain([key]).collect()
+        };
+        let render_sections = || {
+            let mut sections = String::new();
+            ser::write_section(&mut sections, &mut keys(), value);
+            sections
+        };
+
+        match self.container(path) {
+            Container::Inline(node) => self.insert_inline(node, &entry),
+            Container::Dotted { anchor, depth } => {
+                let segments = self.tree.key_segments(anchor);
+                let prefix = Span::new(
+                    self.tree.span(segments[0]).lo(),
+                    self.tree.span(segments[depth - 1]).hi(),
+                );
+                let entry = format!("{}.{}", self.slice(prefix), entry);
+                let inline = self.defs_at(path).any(|def| match def {
+                    Def::KeyValue { container, .. } => {
+                        self.tree.kind(container) == Kind::InlineTable
+                    }
+                    _ => false,
+                });
+                if inline {
+                    let pos = self.tree.span(anchor).hi();
+                    self.insert_text(pos, format!(", {}", entry))
+                } else {
+                    self.insert_line_after(anchor, &entry)
+                }
+            }
+            _ if ser::is_section(value) => {
+                let pos = self.sections_end(path).unwrap_or(self.as_str().len());
+                self.insert_sections(pos, &render_sections(), false)
+            }
+            Container::Section(node) => match self.tree.key_values(node).last() {
+                Some(&last) => self.insert_line_after(last, &entry),
+                None if node == self.tree.root_id() => self.insert_root_line(&entry),
+                None => {
+                    let header = self.tree.child(node, Kind::TableHeader);
+                    self.insert_line_after(header.unwrap_or(node), &entry)
+                }
+            },
+            Container::Implicit => {
+                let mut table = Table::new();
+                table.insert(key.to_string(), value.clone());
+                let table = Value::Table(table);
+                let mut sections = String::new();
+                let mut keys = keys();
+                keys.pop();
+                ser::write_section(&mut sections, &mut keys, &table);
+                let pos = self.sections_start(path).unwrap_or(self.as_str().len());
+                self.insert_sections(pos, &sections, true)
+            }
+        }
+    }
+
+    /// Adds `entry` at the end of an inline table.
+    fn insert_inline(&mut self, node: NodeId, entry: &str) -> Result<()> {
+        match self.tree.key_values(node).last() {
+            Some(&last) => {
+                let pos = self.tree.span(last).hi();
+                self.insert_text(pos, format!(", {}", entry))
+            }
+            None => {
+                let open = self.tree.child(node, Kind::LeftBrace);
+                let close = self.tree.child(node, Kind::RightBrace);
+                let (Some(open), Some(close)) = (open, close) else {
+                    return Err(Error::Message("malformed inline table".to_string()));
+                };
+                let inner = Span::new(self.tree.span(open).hi(), self.tree.span(close).lo());
+                self.splice(vec![(inner, format!(" {} ", entry))])
+            }
+        }
+    }
+
+    /// The spans to delete to remove `path` and everything below it.
+    fn removals(&self, path: &[Segment]) -> Vec<Span> {
+        let mut spans: Vec<Span> = self
+            .defs_under(path)
+            .map(|def| match def {
+                Def::KeyValue {
+                    node, container, ..
+                } if self.tree.kind(container) == Kind::InlineTable => {
+                    self.list_entry_span(container, &self.tree.key_values(container), node)
+                }
+                Def::KeyValue { node, .. } => self.line_span(node),
+                Def::Section { node } => self.section_span(node),
+                Def::Item { node, array } => {
+                    self.list_entry_span(array, &self.tree.items(array), node)
+                }
+            })
+            .collect();
+
+        spans.sort_by_key(|span| (span.lo(), std::cmp::Reverse(span.hi())));
+        let mut merged: Vec<Span> = Vec::new();
+        for span in spans {
+            match merged.last_mut() {
+                Some(last) if span.lo() < last.hi() => *last = last.join(span),
+                _ => merged.push(span),
+            }
+        }
+        merged
+    }
+
+    /// The whole lines of the key/value pair that writes the value at `path`, and the span of
+    /// the value. Pairs in inline tables have no lines of their own.
+    pub(crate) fn entry_lines(&self, path: &Path) -> Option<(Span, Span)> {
+        self.defs_at(path.segments()).find_map(|def| match def {
+            Def::KeyValue {
+                node,
+                container,
+                depth,
+            } if depth == self.tree.key_segments(node).len()
+                && self.tree.kind(container) != Kind::InlineTable =>
+            {
+                Some((self.line_span(node), self.tree.span(self.tree.value(node)?)))
+            }
+            _ => None,
+        })
+    }
+
+    /// The whole lines `node` is on.
+    fn line_span(&self, node: NodeId) -> Span {
+        let span = self.tree.span(node);
+        Span::new(self.line_start(span.lo()), self.line_end(span.hi()))
+    }
+
+    /// A table section along with the blank lines after it, or before it if it's at the end.
+    fn section_span(&self, node: NodeId) -> Span {
+        let span = self.tree.span(node);
+        let mut lo = self.line_start(span.lo());
+        // A section ends with the newline of its last line, if it has one.
+        let mut hi = match self.as_str()[..span.hi()].ends_with('\n') {
+            true => span.hi(),
+            false => self.line_end(span.hi()),
+        };
+        let blank = |line: &str| line.trim().is_empty();
+        let mut after = hi;
+        while after < self.as_str().len() && blank(&self.as_str()[after..self.line_end(after)]) {
+            after = self.line_end(after);
+        }
+        if after > hi && after < self.as_str().len() {
+            hi = after;
+        } else {
+            while lo > 0 && blank(&self.as_str()[self.line_start(lo - 1)..lo]) {
+                lo = self.line_start(lo - 1);
+            }
+            hi = after;
+        }
+        Span::new(lo, hi)
+    }
+
+    /// An entry of an array or inline table along with one of the commas next to it.
+    fn list_entry_span(&self, list: NodeId, entries: &[NodeId], node: NodeId) -> Span {
+        let span = self.tree.span(node);
+        let Some(i) = entries.iter().position(|&e| e == node) else {
+            return span;
+        };
+        if let Some(&next) = entries.get(i + 1) {
+            return Span::new(span.lo(), self.tree.span(next).lo());
+        }
+        if let Some(&prev) = i.checked_sub(1).and_then(|i| entries.get(i)) {
+            return Span::new(self.tree.span(prev).hi(), span.hi());
+        }
+        // The only entry, so take a trailing comma with it.
+        let children = self.tree.children(list).iter();
+        let comma = children
+            .copied()
+            .find(|&c| self.tree.kind(c) == Kind::Comma);
+        match comma {
+            Some(comma) => span.join(self.tree.span(comma)),
+            None => span,
+        }
+    }
+
+    fn remove_path(&mut self, path: &[Segment]) -> Result<()> {
+        let edits = self.removals(path);
+        self.splice(
+            edits
+                .into_iter()
+                .map(|span| (span, String::new()))
+                .collect(),
+        )
+    }
+
+    fn set(&mut self, path: &[Segment], key: &str, value: Value) -> Result<Option<Value>> {
+        if self.lookup_table(path).is_none() {
+            return Err(Error::Message("not a table".to_string()));
+        }
+        let mut child = path.to_vec();
+        child.push(Segment::Key(key.to_string()));
+        let old = self.lookup(&child).cloned();
+        self.atomically(|doc| {
+            match (&old, doc.value_node(&child)) {
+                (Some(_), Some(node)) => {
+                    let mut text = String::new();
+                    ser::write_value(&mut text, &value);
+                    doc.splice(vec![(doc.tree.span(node), text)])?;
+                }
+                (Some(_), None) => {
+                    doc.remove_path(&child)?;
+                    doc.insert_new(path, key, &value)?;
+                }
+                (None, _) => doc.insert_new(path, key, &value)?,
+            }
+            Ok(old)
+        })
+    }
+
+    /// Renames the last key of `path` to `key` wherever it's written: in key/value pairs, dotted
+    /// keys and the headers of the tables below it.
+    fn rename(&mut self, path: &[Segment], key: &str) -> Result<()> {
+        let position = path.iter().filter(|s| matches!(s, Segment::Key(_))).count() - 1;
+        let mut edits: Vec<Span> = Vec::new();
+        for def in self.defs_under(path) {
+            let segment = match def {
+                Def::Section { node } => self
+                    .tree
+                    .child(node, Kind::TableHeader)
+                    .and_then(|header| self.tree.key_segments(header).get(position).copied()),
+                _ => None,
+            };
+            edits.extend(segment.map(|segment| self.tree.span(segment)));
+        }
+        for def in self.defs_at(path) {
+            if let Def::KeyValue { node, depth, .. } = def {
+                let segment = self.tree.key_segments(node)[depth - 1];
+                edits.push(self.tree.span(segment));
+            }
+        }
+        edits.sort_by_key(|span| span.lo());
+        edits.dedup();
+        let mut text = String::new();
+        ser::write_key(&mut text, key);
+        self.splice(edits.into_iter().map(|span| (span, text.clone())).collect())
+    }
+
+    /// Inserts `value` into the array at `path` before item `index`.
+    fn insert_item(&mut self, path: &[Segment], index: usize, value: Value) -> Result<()> {
+        let len = match self.lookup(path) {
+            Some(Value::Array(items)) => items.len(),
+            _ => return Err(Error::Message("not an array".to_string())),
+        };
+        if index > len {
+            return Err(Error::Message(format!(
+                "index {} out of range for array of length {}",
+                index, len
+            )));
+        }
+
+        let Some(array) = self.value_node(path) else {
+            return self.insert_table_item(path, index, len, value);
+        };
+        let mut item = String::new();
+        ser::write_value(&mut item, &value);
+        let items = self.tree.items(array);
+        let multiline =
+            (self.tree.children(array).iter()).any(|&c| self.tree.kind(c) == Kind::Newline);
+
+        let edits = match (items.get(index), items.last()) {
+            (Some(&next), _) => {
+                let pos = self.tree.span(next).lo();
+                let separator = match multiline {
+                    true => format!(",\n{}", self.indent(pos)),
+                    false => ", ".to_string(),
+                };
+                vec![(Span::new(pos, pos), item + &separator)]
+            }
+            (None, Some(&last)) => self.push_after(array, last, item, multiline),
+            (None, None) => {
+                let pos = self.tree.span(array).lo() + 1;
+                vec![(Span::new(pos, pos), item)]
+            }
+        };
+        self.splice(edits)
+    }
+
+    /// The edits appending `item` to an array after its `last` item.
+    fn push_after(
+        &self,
+        array: NodeId,
+        last: NodeId,
+        item: String,
+        multiline: bool,
+    ) -> Vec<(Span, String)> {
+        let hi = self.tree.span(last).hi();
+        let children = self.tree.children(array);
+        let after = children.iter().skip_while(|&&c| c != last).skip(1);
+        let comma = after
+            .copied()
+            .find(|&c| self.tree.kind(c) == Kind::Comma)
+            .map(|c| self.tree.span(c));
+        let close = self.tree.span(array).hi() - 1;
+
+        // Each item is on its own line, so the new one goes on a line after the last one.
+        let pos = self.line_end(comma.map_or(hi, |comma| comma.hi()));
+        if multiline && pos <= close {
+            let mut edits = Vec::new();
+            if comma.is_none() {
+                edits.push((Span::new(hi, hi), ",".to_string()));
+            }
+            let indent = self.indent(self.tree.span(last).lo());
+            edits.push((Span::new(pos, pos), format!("{}{},\n", indent, item)));
+            return edits;
+        }
+        vec![(Span::new(hi, hi), format!(", {}", item))]
+    }
+
+    /// Inserts a table into an array of tables, as a new `[[header]]` section.
+    fn insert_table_item(
+        &mut self,
+        path: &[Segment],
+        index: usize,
+        len: usize,
+        value: Value,
+    ) -> Result<()> {
+        if !value.is_table() {
+            return Err(Error::Message(format!(
+                "can't add a {} to an array of tables",
+                value.type_str()
+            )));
+        }
+        let mut keys: Vec<&str> = path
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Key(key) => Some(key.as_str()),
+                Segment::Index(_) => None,
+            })
+            .collect();
+        let items = Value::Array(vec![value]);
+        let mut sections = String::new();
+        ser::write_section(&mut sections, &mut keys, &items);
+
+        let mut element = path.to_vec();
+        if index < len {
+            element.push(Segment::Index(index));
+            let pos = self.sections_start(&element).unwrap_or(self.as_str().len());
+            self.insert_sections(pos, &sections, true)
+        } else {
+            element.push(Segment::Index(len - 1));
+            let pos = self.sections_end(&element).unwrap_or(self.as_str().len());
+            self.insert_sections(pos, &sections, false)
+        }
+    }
+}
+
+impl FromStr for DocumentMut {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for DocumentMut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A table in a `DocumentMut` that can be edited.
+pub struct TableMut<'a> {
+    doc: &'a mut DocumentMut,
+    path: Vec<Segment>,
+}
+
+impl<'a> TableMut<'a> {
+    fn new(doc: &'a mut DocumentMut, path: Vec<Segment>) -> Option<Self> {
+        doc.lookup_table(&path)?;
+        Some(Self { doc, path })
+    }
+
+    fn child(&self, key: &str) -> Vec<Segment> {
+        let mut path = self.path.clone();
+        path.push(Segment::Key(key.to_string()));
+        path
+    }
+
+    pub fn as_table(&self) -> &Table {
+        // The path was checked when the handle was made, and only the handle can change it.
+        self.doc
+            .lookup_table(&self.path)
+            .expect("table disappeared")
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_table().get(key)
+    }
+
+    /// Returns a handle for editing the table at `key`, if there is one.
+    pub fn table(&mut self, key: &str) -> Option<TableMut<'_>> {
+        let path = self.child(key);
+        TableMut::new(self.doc, path)
+    }
+
+    /// Returns a handle for editing the array at `key`, if there is one.
+    pub fn array(&mut self, key: &str) -> Option<ArrayMut<'_>> {
+        let path = self.child(key);
+        ArrayMut::new(self.doc, path)
+    }
+
+    /// Sets `key` to `value`, returning the value it replaced.
+    ///
+    /// A value written after `key =` is replaced where it is. Other keys are added after the
+    /// existing keys of the table; tables and arrays of tables get their own headers after the
+    /// table's other subtables, unless the table is inline.
+    pub fn insert(&mut self, key: &str, value: impl Into<Value>) -> Result<Option<Value>> {
+        self.doc.set(&self.path, key, value.into())
+    }
+
+    /// Removes `key` and everything below it, returning the value it had.
+    ///
+    /// Key/value pairs are removed along with the rest of their line, tables along with their
+    /// headers and the blank lines after them.
+    pub fn remove(&mut self, key: &str) -> Result<Option<Value>> {
+        let path = self.child(key);
+        let old = self.doc.lookup(&path).cloned();
+        if old.is_some() {
+            self.doc.atomically(|doc| doc.remove_path(&path))?;
+        }
+        Ok(old)
+    }
+
+    /// Renames `from` to `to`, keeping its value, comments and position. Tables are renamed in
+    /// their headers and in those of the tables below them.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        if self.get(from).is_none() {
+            return Err(Error::Message(format!("no key `{}` to rename", from)));
+        }
+        if self.get(to).is_some() {
+            return Err(Error::Message(format!("key `{}` already exists", to)));
+        }
+        let path = self.child(from);
+        self.doc.atomically(|doc| doc.rename(&path, to))
+    }
+}
+
+/// An array in a `DocumentMut` that can be edited. Arrays of tables written with `[[headers]]`
+/// are edited by adding and removing sections.
+pub struct ArrayMut<'a> {
+    doc: &'a mut DocumentMut,
+    path: Vec<Segment>,
+}
+
+impl<'a> ArrayMut<'a> {
+    fn new(doc: &'a mut DocumentMut, path: Vec<Segment>) -> Option<Self> {
+        doc.lookup(&path)?.as_array()?;
+        Some(Self { doc, path })
+    }
+
+    fn item(&self, index: usize) -> Vec<Segment> {
+        let mut path = self.path.clone();
+        path.push(Segment::Index(index));
+        path
+    }
+
+    pub fn as_array(&self) -> &Array {
+        // The path was checked when the handle was made, and only the handle can change it.
+        let array = self.doc.lookup(&self.path).and_then(Value::as_array);
+        array.expect("array disappeared")
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_array().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_array().is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.as_array().get(index)
+    }
+
+    /// Returns a handle for editing the table at `index`, if there is one.
+    pub fn table(&mut self, index: usize) -> Option<TableMut<'_>> {
+        let path = self.item(index);
+        TableMut::new(self.doc, path)
+    }
+
+    /// Returns a handle for editing the array at `index`, if there is one.
+    pub fn array(&mut self, index: usize) -> Option<ArrayMut<'_>> {
+        let path = self.item(index);
+        ArrayMut::new(self.doc, path)
+    }
+
+    /// Appends `value`. In an array spread over several lines it gets a line of its own.
+    pub fn push(&mut self, value: impl Into<Value>) -> Result<()> {
+        let len = self.len();
+        self.insert(len, value)
+    }
+
+    /// Inserts `value` before the item at `index`.
+    pub fn insert(&mut self, index: usize, value: impl Into<Value>) -> Result<()> {
+        let value = value.into();
+        let path = &self.path;
+        self.doc
+            .atomically(|doc| doc.insert_item(path, index, value))
+    }
+
+    /// Removes and returns the item at `index`.
+    pub fn remove(&mut self, index: usize) -> Result<Value> {
+        let path = self.item(index);
+        let old = self.doc.lookup(&path).cloned().ok_or_else(|| {
+            Error::Message(format!(
+                "index {} out of range for array of length {}",
+                index,
+                self.len()
+            ))
+        })?;
+        self.doc.atomically(|doc| doc.remove_path(&path))?;
+        Ok(old)
+    }
+
+    /// Replaces the item at `index`, returning the old one.
+    pub fn replace(&mut self, index: usize, value: impl Into<Value>) -> Result<Value> {
+        let value = value.into();
+        let item = self.item(index);
+        let old = self.doc.lookup(&item).cloned().ok_or_else(|| {
+            Error::Message(format!(
+                "index {} out of range for array of length {}",
+                index,
+                self.len()
+            ))
+        })?;
+        let path = &self.path;
+        self.doc.atomically(|doc| match doc.value_node(&item) {
+            Some(node) => {
+                let mut text = String::new();
+                ser::write_value(&mut text, &value);
+                doc.splice(vec![(doc.tree.span(node), text)])
+            }
+            None => {
+                // The new table goes in first, so the array never disappears along the way.
+                doc.insert_item(path, index, value)?;
+                let mut next = path.clone();
+                next.push(Segment::Index(index + 1));
+                doc.remove_path(&next)
+            }
+        })?;
+        Ok(old)
+    }
+}
+
+/// Works out which paths each part of the tree defines.
+struct Indexer<'a> {
+    text: &'a str,
+    tree: &'a Tree,
+    defs: Vec<(Vec<Segment>, Def)>,
+    /// The number of elements in each array of tables so far.
+    arrays: Vec<(Vec<Segment>, usize)>,
+}
+
+impl<'a> Indexer<'a> {
+    fn new(tree: &'a Tree) -> Self {
+        Self {
+            text: tree.text(),
+            tree,
+            defs: Vec::new(),
+            arrays: Vec::new(),
+        }
+    }
+
+    fn index(mut self) -> Result<Vec<(Vec<Segment>, Def)>> {
+        let root = self.tree.root_id();
+        for &child in self.tree.children(root) {
+            match self.tree.kind(child) {
+                Kind::KeyValue => self.key_value(child, root, &[])?,
+                Kind::Table => self.table(child)?,
+                _ => {}
+            }
+        }
+        Ok(self.defs)
+    }
+
+    /// The decoded segments of the key of a key/value pair or header.
+    fn keys(&self, node: NodeId) -> Result<Vec<String>> {
+        let segments = self.tree.key_segments(node);
+        segments
+            .into_iter()
+            .map(|segment| {
+                let span = self.tree.span(segment);
+                match self.tree.kind(segment) {
+                    // Strings are parsed from their contents, as the lexer sees them.
+                    Kind::String if span.len() >= 2 => {
+                        let symbol = Symbol::with_span(Sym::String, span.lo() + 1, span.hi() - 2);
+                        parse::parse_key(self.text, symbol)
+                    }
+                    _ => Ok(self.text[span.lo()..span.hi()].to_string()),
+                }
+            })
+            .collect()
+    }
+
+    fn table(&mut self, node: NodeId) -> Result<()> {
+        let Some(header) = self.tree.child(node, Kind::TableHeader) else {
+            return Ok(());
+        };
+        let is_array = self.tree.child(header, Kind::DoubleLeftBracket).is_some();
+        let keys = self.keys(header)?;
+        let last = keys.len();
+        let mut path = Vec::new();
+        for (i, key) in keys.into_iter().enumerate() {
+            path.push(Segment::Key(key));
+            let count = self.arrays.iter_mut().find(|(p, _)| *p == path);
+            match count {
+                Some((_, n)) if is_array && i + 1 == last => {
+                    path.push(Segment::Index(*n));
+                    *n += 1;
+                }
+                None if is_array && i + 1 == last => {
+                    self.arrays.push((path.clone(), 1));
+                    path.push(Segment::Index(0));
+                }
+                Some((_, n)) => path.push(Segment::Index(*n - 1)),
+                None => {}
+            }
+        }
+
+        self.defs.push((path.clone(), Def::Section { node }));
+        for key_value in self.tree.key_values(node) {
+            self.key_value(key_value, node, &path)?;
+        }
+        Ok(())
+    }
+
+    fn key_value(&mut self, node: NodeId, container: NodeId, base: &[Segment]) -> Result<()> {
+        let mut path = base.to_vec();
+        for (i, key) in self.keys(node)?.into_iter().enumerate() {
+            path.push(Segment::Key(key));
+            let def = Def::KeyValue {
+                node,
+                container,
+                depth: i + 1,
+            };
+            self.defs.push((path.clone(), def));
+        }
+        match self.tree.value(node) {
+            Some(value) => self.value(value, &path),
+            None => Ok(()),
+        }
+    }
+
+    fn value(&mut self, node: NodeId, path: &[Segment]) -> Result<()> {
+        match self.tree.kind(node) {
+            Kind::Array => {
+                for (i, item) in self.tree.items(node).into_iter().enumerate() {
+                    let mut path = path.to_vec();
+                    path.push(Segment::Index(i));
+                    let def = Def::Item {
+                        node: item,
+                        array: node,
+                    };
+                    self.defs.push((path.clone(), def));
+                    self.value(item, &path)?;
+                }
+            }
+            Kind::InlineTable => {
+                for key_value in self.tree.key_values(node) {
+                    self.key_value(key_value, node, path)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Returns `text` with its line endings changed to `newline`.
+pub(crate) fn with_newline(text: &str, newline: &str) -> String {
+    match newline {
+        "\n" => text.replace("\r\n", "\n"),
+        newline => text.replace("\r\n", "\n").replace('\n', newline),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(text: &str) -> DocumentMut {
+        text.parse().expect(text)
+    }
+
+    /// Checks the text after an edit, and that its values agree with a fresh parse.
+    fn check(doc: &DocumentMut, expected: &str) {
+        assert_eq!(doc.as_str(), expected);
+        assert_eq!(doc.as_table(), &crate::parse(expected).unwrap());
+    }
+
+    #[test]
+    fn insert_success() {
+        let mut d = doc("# top\na = 1 # one\n\n[t]\n  b = 2\n\n# next\n[u.v]\n");
+        d.insert("a", 10).unwrap();
+        d.insert("c", "x").unwrap();
+        d.table("t").unwrap().insert("d", true).unwrap();
+        d.table("u").unwrap().insert("w", 1.5).unwrap();
+        check(
+            &d,
+            "# top\na = 10 # one\nc = \"x\"\n\n[t]\n  b = 2\n  d = true\n\n[u]\nw = 1.5\n\n# next\n[u.v]\n",
+        );
+
+        let mut d = doc("a.b = 1\nx = { y = 1 }\ne = {}\n[t]\n");
+        d.table("a").unwrap().insert("c", 2).unwrap();
+        d.table("x").unwrap().insert("z", 2).unwrap();
+        d.table("e").unwrap().insert("f", 3).unwrap();
+        let mut sub = Table::new();
+        sub.insert("k".to_string(), Value::from("v"));
+        d.table("t").unwrap().insert("sub", sub.clone()).unwrap();
+        d.insert("last key", Value::Array(vec![1.into(), 2.into()]))
+            .unwrap();
+        check(
+            &d,
+            "a.b = 1\na.c = 2\nx = { y = 1, z = 2 }\ne = { f = 3 }\n\"last key\" = [1, 2]\n\
+             [t]\n\n[t.sub]\nk = \"v\"\n",
+        );
+
+        let mut d = doc("[a]\nx = 1\n[b]\n");
+        d.insert("c", sub.clone()).unwrap();
+        d.insert("first", 1).unwrap();
+        check(&d, "first = 1\n\n[a]\nx = 1\n[b]\n\n[c]\nk = \"v\"\n");
+
+        // Inserted lines end the way the document's lines do.
+        let crlf = |text: &str| text.replace('\n', "\r\n");
+        let mut d = doc(&crlf(
+            "# top\na = 1 # one\n\n[t]\n  b = 2\n\n# next\n[u.v]\n",
+        ));
+        d.insert("c", "x").unwrap();
+        d.table("t").unwrap().insert("d", true).unwrap();
+        d.table("u").unwrap().insert("w", 1.5).unwrap();
+        d.insert("s", sub.clone()).unwrap();
+        check(
+            &d,
+            &crlf(
+                "# top\na = 1 # one\nc = \"x\"\n\n[t]\n  b = 2\n  d = true\n\n[u]\nw = 1.5\n\n\
+                 # next\n[u.v]\n\n[s]\nk = \"v\"\n",
+            ),
+        );
+
+        let mut d = doc("a = 1");
+        d.insert("b", 2).unwrap();
+        check(&d, "a = 1\nb = 2\n");
+    }
+
+    #[test]
+    fn remove_success() {
+        let mut d = doc("a = 1 # one\nb = 2\n\n[t]\nc = 3\n\n[t.u]\nd = 4\n\n[v]\ne = 5\n");
+        assert_eq!(d.remove("a").unwrap(), Some(Value::Integer(1)));
+        assert_eq!(d.remove("missing").unwrap(), None);
+        d.remove("t").unwrap();
+        check(&d, "b = 2\n\n[v]\ne = 5\n");
+        d.remove("v").unwrap();
+        check(&d, "b = 2\n");
+
+        let mut d = doc("x = { y = 1, z = 2 }\na.b = 1\na.c = 2\nd = 3\n");
+        d.table("x").unwrap().remove("y").unwrap();
+        d.table("a").unwrap().remove("c").unwrap();
+        check(&d, "x = { z = 2 }\na.b = 1\nd = 3\n");
+        d.remove("a").unwrap();
+        d.table("x").unwrap().remove("z").unwrap();
+        check(&d, "x = {  }\nd = 3\n");
+    }
+
+    #[test]
+    fn array_success() {
+        let mut d = doc("a = [1, 2]\nb = [\n  'x', # first\n  'y'\n]\nc = []\n");
+        let mut a = d.array("a").unwrap();
+        a.push(3).unwrap();
+        a.insert(0, 0).unwrap();
+        assert_eq!(a.remove(1).unwrap(), Value::Integer(1));
+        assert_eq!(a.replace(0, -1).unwrap(), Value::Integer(0));
+        assert_eq!(a.len(), 3);
+        d.array("b").unwrap().push("z").unwrap();
+        d.array("b").unwrap().insert(1, "w").unwrap();
+        d.array("c").unwrap().push(1).unwrap();
+        check(
+            &d,
+            "a = [-1, 2, 3]\nb = [\n  'x', # first\n  \"w\",\n  'y',\n  \"z\",\n]\nc = [1]\n",
+        );
+        assert!(d.array("c").unwrap().insert(5, 1).is_err());
+        assert!(d.array("c").unwrap().remove(5).is_err());
+
+        let mut d = doc("x = [\r\n  1,\r\n  2,\r\n]\r\n");
+        d.array("x").unwrap().push(3).unwrap();
+        d.array("x").unwrap().insert(0, 0).unwrap();
+        check(&d, "x = [\r\n  0,\r\n  1,\r\n  2,\r\n  3,\r\n]\r\n");
+    }
+
+    #[test]
+    fn array_of_tables_success() {
+        let mut d = doc("[[s]]\nn = 1\n[s.sub]\nx = 1\n\n[[s]]\nn = 2\n\n[t]\n");
+        let mut table = Table::new();
+        table.insert("n".to_string(), Value::Integer(3));
+        let mut s = d.array("s").unwrap();
+        s.push(table.clone()).unwrap();
+        s.table(0).unwrap().insert("m", 1).unwrap();
+        s.remove(1).unwrap();
+        assert!(s.push(1).is_err());
+        table.insert("n".to_string(), Value::Integer(0));
+        s.insert(0, table.clone()).unwrap();
+        table.insert("n".to_string(), Value::Integer(4));
+        s.replace(2, table).unwrap();
+        check(
+            &d,
+            "[[s]]\nn = 0\n\n[[s]]\nn = 1\nm = 1\n[s.sub]\nx = 1\n\n[[s]]\nn = 4\n\n[t]\n",
+        );
+    }
+
+    #[test]
+    fn rename_success() {
+        let mut d = doc(
+            "# a\na = 1 # one\nb.c = 2\nx = { y = 1 }\n[t] # t\nk = 1\n[t.u]\n[[t.v]]\n[[t.v]]\n",
+        );
+        d.root().rename("a", "a2").unwrap();
+        d.root().rename("b", "my b").unwrap();
+        d.table("x").unwrap().rename("y", "z").unwrap();
+        d.root().rename("t", "s").unwrap();
+        d.table("s").unwrap().rename("v", "w").unwrap();
+        check(
+            &d,
+            "# a\na2 = 1 # one\n\"my b\".c = 2\nx = { z = 1 }\n[s] # t\nk = 1\n[s.u]\n[[s.w]]\n[[s.w]]\n",
+        );
+
+        let mut d = doc("[p]\n[[p.q]]\nr.s = 1\n[p.q.t]\n");
+        let path = "p.q[0]".parse().unwrap();
+        d.table_at(&path).unwrap().rename("r", "R").unwrap();
+        d.table_at(&path).unwrap().rename("t", "T").unwrap();
+        check(&d, "[p]\n[[p.q]]\nR.s = 1\n[p.q.T]\n");
+        assert!(d.array_at(&"p.q".parse().unwrap()).is_some());
+        assert!(d.table_at(&"p.q".parse().unwrap()).is_none());
+
+        assert!(d.root().rename("missing", "x").is_err());
+        assert!(d.root().rename("p", "p").is_err());
+    }
+
+    #[test]
+    fn edit_fail() {
+        let text = "a = 1\n[t]\nb = 2\n";
+        let mut d = doc(text);
+        assert!(d.table("a").is_none());
+        assert!(d.array("t").is_none());
+        assert!(d.array("a").is_none());
+        let mut t = d.table("t").unwrap();
+        assert!(t.insert("b", f64::NAN).is_ok());
+        assert_eq!(d.as_str(), "a = 1\n[t]\nb = nan\n");
+        assert!(DocumentMut::parse("a = ").is_err());
+    }
+}
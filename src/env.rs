@@ -0,0 +1,259 @@
+//! Overriding configuration with environment variables, as containers usually do.
+//!
+//! With the prefix `APP`, the variable `APP__SERVER__PORT` sets `server.port`: the rest of its
+//! name is split on `__` and each segment is lowercased into a key. Values are written as in
+//! TOML, so `9000` is an integer, `true` a boolean and `[1, 2]` an array. Values that aren't
+//! valid TOML are taken as they are, as strings, so `APP__HOST=localhost` needs no quotes,
+//! unless they start like a string, an array or an inline table, which makes them an error.
+//!
+//! Each variable is a layer of a `Merge`, named `environment variable APP__SERVER__PORT`, added
+//! in order of names. A variable for a table is thus overridden by those for keys inside it.
+//! Values that don't deserialize into their type are errors in the source of their variable.
+//!
+//! ```
+//! use serde_derive::Deserialize;
+//! use toml_rust::env::Env;
+//!
+//! #[derive(Deserialize)]
+//! struct Config {
+//!     server: Server,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Server {
+//!     host: String,
+//!     port: u16,
+//! }
+//!
+//! let mut env = Env::new("APP");
+//! env.vars([("APP__SERVER__PORT", "9000"), ("HOME", "/home/me")]);
+//! let config: Config = env.from_str("[server]\nhost = 'a'\nport = 80\n").unwrap();
+//! assert_eq!((config.server.host.as_str(), config.server.port), ("a", 9000));
+//! ```
+
+use crate::error::{Error, Result};
+use crate::lex::Span;
+use crate::merge::Merge;
+use crate::parse::{self, Entry, Node, NodeKind, TableKind, TableNode};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+
+/// Reads configuration from the environment variables with a given prefix.
+#[derive(Clone, Debug)]
+pub struct Env {
+    prefix: String,
+    separator: String,
+    vars: Option<BTreeMap<String, String>>,
+}
+
+impl Env {
+    /// Reads the variables named `prefix`, the separator, and a path, from the environment of
+    /// the process.
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Env {
+            prefix: prefix.into(),
+            separator: "__".to_string(),
+            vars: None,
+        }
+    }
+
+    /// Sets what separates the prefix and the segments of paths in names. The default is `__`.
+    pub fn separator(&mut self, separator: impl Into<String>) {
+        self.separator = separator.into();
+    }
+
+    /// Uses `vars` as the environment instead of that of the process, such as in tests.
+    pub fn vars<K, V>(&mut self, vars: impl IntoIterator<Item = (K, V)>)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let vars = vars.into_iter().map(|(k, v)| (k.into(), v.into()));
+        self.vars = Some(vars.collect());
+    }
+
+    /// Returns the variables with the prefix, in order of names. Variables of the process that
+    /// aren't Unicode are skipped.
+    fn matching(&self) -> Vec<(String, String)> {
+        let vars: Vec<(String, String)> = match &self.vars {
+            Some(vars) => vars.clone().into_iter().collect(),
+            None => {
+                let mut vars: Vec<_> = std::env::vars_os()
+                    .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+                    .collect();
+                vars.sort();
+                vars
+            }
+        };
+        let prefix = format!("{}{}", self.prefix, self.separator);
+        vars.into_iter()
+            .filter(|(name, _)| name.starts_with(&prefix))
+            .collect()
+    }
+
+    /// Adds a layer to `merge` for each variable with the prefix.
+    pub fn load_into(&self, merge: &mut Merge) -> Result<()> {
+        let start = self.prefix.len() + self.separator.len();
+        for (name, text) in self.matching() {
+            let source = format!("environment variable {}", name);
+            let keys: Vec<String> = name[start..]
+                .split(self.separator.as_str())
+                .map(str::to_lowercase)
+                .collect();
+            if keys.iter().any(String::is_empty) {
+                let message = format!("`{}` has an empty key", name);
+                return Err(Error::Message(message).in_source(source));
+            }
+            let value = value_node(&text).map_err(|e| e.in_source(&source))?;
+            merge.add_root(source, text, nest(&keys, value));
+        }
+        Ok(())
+    }
+
+    /// Deserializes an instance of `T` from a TOML document with the variables merged over it.
+    pub fn from_str<T: DeserializeOwned>(&self, text: &str) -> Result<T> {
+        let mut merge = Merge::with_document(text)?;
+        self.load_into(&mut merge)?;
+        merge.finish()?.deserialize()
+    }
+}
+
+/// Parses the value of a variable, falling back to the text as a string.
+fn value_node(text: &str) -> Result<Node> {
+    match parse::parse_value_node(text) {
+        Ok(node) => Ok(node),
+        Err(e) if text.trim_start().starts_with(['"', '\'', '[', '{']) => Err(e),
+        Err(_) => Ok(Node {
+            span: Span::new(0, text.len()),
+            kind: NodeKind::String(text.to_string()),
+        }),
+    }
+}
+
+/// Returns a table holding `value` at the path `keys`, spanned by the value.
+fn nest(keys: &[String], value: Node) -> TableNode {
+    let span = value.span;
+    let (last, parents) = keys.split_last().expect("names have a key");
+    let mut table = TableNode::new(TableKind::Dotted);
+    table.entries.insert(
+        last.clone(),
+        Entry {
+            key_span: span,
+            node: value,
+        },
+    );
+    for key in parents.iter().rev() {
+        let node = Node {
+            span,
+            kind: NodeKind::Table(table),
+        };
+        table = TableNode::new(TableKind::Dotted);
+        table.entries.insert(
+            key.clone(),
+            Entry {
+                key_span: span,
+                node,
+            },
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    #[test]
+    fn env_success() {
+        let mut env = Env::new("APP");
+        env.vars([
+            ("APP__SERVER__PORT", "9000"),
+            ("APP__SERVER__HOSTS", "[\"a\", \"b\"]"),
+            ("APP__SERVER", "{ port = 1, tls = true }"),
+            ("APP__NAME", "my app"),
+            ("APP__RELEASE", " 2024-05-01 "),
+            ("APP_OTHER", "1"),
+            ("OTHER__X", "1"),
+        ]);
+        let mut merge = Merge::new();
+        merge
+            .add("app.toml", "name = 'x'\n[server]\nport = 80\n")
+            .unwrap();
+        env.load_into(&mut merge).unwrap();
+        let merged = merge.finish().unwrap();
+        let table = Value::Table(merged.table().clone());
+        assert_eq!(
+            table.to_string(),
+            "{ name = \"my app\", release = 2024-05-01, server = { hosts = [\"a\", \"b\"], port = 9000, tls = true } }"
+        );
+        let origin = |path: &str| {
+            let origin = merged.origin(&path.parse().unwrap()).unwrap();
+            (origin.name().to_string(), origin.span().lo())
+        };
+        assert_eq!(
+            origin("server.port"),
+            ("environment variable APP__SERVER__PORT".to_string(), 0)
+        );
+        assert_eq!(
+            origin("server.hosts[1]"),
+            ("environment variable APP__SERVER__HOSTS".to_string(), 7)
+        );
+        assert_eq!(
+            origin("server.tls"),
+            ("environment variable APP__SERVER".to_string(), 18)
+        );
+        assert_eq!(
+            origin("release"),
+            ("environment variable APP__RELEASE".to_string(), 1)
+        );
+
+        let mut env = Env::new("my");
+        env.separator("_");
+        env.vars([("my_Log_Level", "debug")]);
+        let table: Value = env.from_str("").unwrap();
+        assert_eq!(table.get_path("log.level").unwrap().as_str(), Some("debug"));
+    }
+
+    #[test]
+    fn env_fail() {
+        let fail = |name: &str, value: &str| {
+            let mut env = Env::new("APP");
+            env.vars([(name, value)]);
+            env.from_str::<Value>("").unwrap_err().to_string()
+        };
+        assert_eq!(
+            fail("APP__A", "[1, 2"),
+            "environment variable APP__A: MissingDelimiter { pos: 5 }"
+        );
+        assert_eq!(
+            fail("APP__A____B", "1"),
+            "environment variable APP__A____B: `APP__A____B` has an empty key"
+        );
+        #[derive(Debug, serde_derive::Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            server: Server,
+        }
+        #[derive(Debug, serde_derive::Deserialize)]
+        #[allow(dead_code)]
+        struct Server {
+            port: u16,
+        }
+        let mut env = Env::new("APP");
+        env.vars([("APP__SERVER__PORT", "abc")]);
+        assert_eq!(
+            env.from_str::<Config>("[server]\nport = 80\n")
+                .unwrap_err()
+                .to_string(),
+            "environment variable APP__SERVER__PORT: invalid type: string \"abc\", expected u16 at 0"
+        );
+
+        let mut env = Env::new("APP");
+        env.vars([("APP__A", "1")]);
+        assert_eq!(
+            env.from_str::<Value>("a = ").unwrap_err().to_string(),
+            "Unexpected { pos: 4 }"
+        );
+    }
+}
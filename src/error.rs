@@ -1,3 +1,4 @@
+use crate::lex::{self, Span};
 use serde::{de, ser};
 use std::fmt::{self, Display};
 
@@ -6,6 +7,73 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Clone, Debug, PartialEq)]
 pub enum Error {
     Message(String),
+    /// A `None` was serialized somewhere it can't simply be left out, such as in an array.
+    UnsupportedNone,
+    /// The input was rejected by the lexer.
+    Lex(lex::Error),
+    /// A string contains an escape sequence that is not defined by TOML.
+    InvalidEscape {
+        pos: usize,
+    },
+    /// A string contains a control character that must be escaped.
+    ControlCharacter {
+        pos: usize,
+    },
+    /// An integer or float literal is malformed or does not fit the target type.
+    InvalidNumber {
+        span: Span,
+    },
+    /// A date-time literal is malformed or out of range.
+    InvalidDatetime {
+        span: Span,
+    },
+    /// A key or table was defined more than once, or a table was extended after it was closed.
+    DuplicateKey {
+        key: String,
+        span: Span,
+    },
+    /// A value is valid TOML but was rejected for a reason of its own, such as lacking the key
+    /// a merge matches array items by.
+    Invalid {
+        message: String,
+        span: Span,
+    },
+    /// An error in one of several named inputs, such as a layer of a merged configuration. The
+    /// position of `error` is in that input's text.
+    Source {
+        name: String,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Returns the byte offset in the source text the error refers to, if it has one.
+    pub fn pos(&self) -> Option<usize> {
+        match self {
+            Error::Message(_) | Error::UnsupportedNone => None,
+            Error::Lex(e) => Some(e.pos()),
+            Error::InvalidEscape { pos } | Error::ControlCharacter { pos } => Some(*pos),
+            Error::InvalidNumber { span }
+            | Error::InvalidDatetime { span }
+            | Error::DuplicateKey { span, .. }
+            | Error::Invalid { span, .. } => Some(span.lo()),
+            Error::Source { error, .. } => error.pos(),
+        }
+    }
+
+    /// Wraps the error to say which input it's in.
+    pub fn in_source(self, name: impl Into<String>) -> Error {
+        Error::Source {
+            name: name.into(),
+            error: Box::new(self),
+        }
+    }
+}
+
+impl From<lex::Error> for Error {
+    fn from(e: lex::Error) -> Self {
+        Error::Lex(e)
+    }
 }
 
 impl ser::Error for Error {
@@ -24,6 +92,17 @@ impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Message(msg) => write!(f, "{}", msg),
+            Error::UnsupportedNone => write!(f, "unsupported None value"),
+            Error::Lex(e) => write!(f, "{}", e),
+            Error::InvalidEscape { pos } => write!(f, "invalid escape sequence at {}", pos),
+            Error::ControlCharacter { pos } => write!(f, "illegal control character at {}", pos),
+            Error::InvalidNumber { span } => write!(f, "invalid number at {}", span.lo()),
+            Error::InvalidDatetime { span } => write!(f, "invalid datetime at {}", span.lo()),
+            Error::DuplicateKey { key, span } => {
+                write!(f, "duplicate key `{}` at {}", key, span.lo())
+            }
+            Error::Invalid { message, span } => write!(f, "{} at {}", message, span.lo()),
+            Error::Source { name, error } => write!(f, "{}: {}", name, error),
         }
     }
 }
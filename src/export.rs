@@ -0,0 +1,313 @@
+//! Rendering documents as `KEY=value` lines, for programs that only read their configuration
+//! from the environment.
+//!
+//! Each value gets a variable named by its path, with the prefix and keys uppercased and joined
+//! by a separator, so `server.port` becomes `SERVER_PORT`. Characters that can't be in names
+//! become `_`. Values are written as in a document, except for strings, which are written as
+//! they are, and everything is quoted for POSIX shells where needed, so the lines may be given
+//! to `export` or sourced. How arrays, and the tables in them, are written is chosen with
+//! `Nested`. Empty tables have no variables.
+//!
+//! ```
+//! use toml_rust::export::{Export, Nested};
+//!
+//! let text = "name = \"it's\"\n[server]\nport = 80\nhosts = ['a', 'b']\n";
+//! let table = toml_rust::parse(text).unwrap();
+//! let mut export = Export::new();
+//! export.prefix("APP");
+//! assert_eq!(
+//!     export.render(&table).unwrap(),
+//!     "APP_NAME='it'\\''s'\nAPP_SERVER_HOSTS='[\"a\",\"b\"]'\nAPP_SERVER_PORT=80\n"
+//! );
+//! export.nested(Nested::Indices);
+//! assert!(export.render(&table).unwrap().contains("APP_SERVER_HOSTS_1=b\n"));
+//! ```
+
+use crate::error::{Error, Result};
+use crate::path::{Path, Segment};
+use crate::value::{Table, Value};
+use serde_json::{Map, Number, Value as Json};
+use std::collections::HashMap;
+
+/// How arrays, along with the tables in them, are exported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Nested {
+    /// `HOSTS='["a","b"]'`, as JSON.
+    #[default]
+    Json,
+    /// `HOSTS_0=a` and `HOSTS_1=b`, item by item, with the index as a segment of the name.
+    Indices,
+    /// Arrays are an error.
+    Error,
+}
+
+/// Renders tables as variable assignments.
+#[derive(Clone, Debug)]
+pub struct Export {
+    prefix: Option<String>,
+    separator: String,
+    nested: Nested,
+}
+
+impl Default for Export {
+    fn default() -> Self {
+        Export {
+            prefix: None,
+            separator: "_".to_string(),
+            nested: Nested::default(),
+        }
+    }
+}
+
+impl Export {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts every name with `prefix`, written as keys are, and the separator.
+    pub fn prefix(&mut self, prefix: impl Into<String>) {
+        self.prefix = Some(prefix.into());
+    }
+
+    /// Sets what joins the segments of names. The default is `_`. Names with characters other
+    /// than letters, digits and `_` can't be exported.
+    pub fn separator(&mut self, separator: impl Into<String>) {
+        self.separator = separator.into();
+    }
+
+    /// Sets how arrays are exported. The default is `Nested::Json`.
+    pub fn nested(&mut self, nested: Nested) {
+        self.nested = nested;
+    }
+
+    /// Returns a `NAME=value` line for each value in `table`, in path order. Two paths with the
+    /// same name, names that aren't a letter or `_` followed by letters, digits and `_`, and
+    /// arrays with `Nested::Error` are errors.
+    pub fn render(&self, table: &Table) -> Result<String> {
+        let mut lines = Lines {
+            export: self,
+            names: HashMap::new(),
+            text: String::new(),
+        };
+        let mut path = Path::new();
+        lines.add_table(&mut path, table)?;
+        Ok(lines.text)
+    }
+
+    /// Returns the variable name for `path`.
+    fn name(&self, path: &Path) -> String {
+        let mut segments = Vec::new();
+        segments.extend(self.prefix.as_deref().map(word));
+        for segment in path.segments() {
+            segments.push(match segment {
+                Segment::Key(key) => word(key),
+                Segment::Index(index) => index.to_string(),
+            });
+        }
+        segments.join(&self.separator)
+    }
+}
+
+/// Returns `key` uppercased, with characters that can't be in names changed to `_`.
+fn word(key: &str) -> String {
+    key.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect()
+}
+
+/// The lines rendered so far, and the path each name was used for.
+struct Lines<'a> {
+    export: &'a Export,
+    names: HashMap<String, String>,
+    text: String,
+}
+
+impl Lines<'_> {
+    fn add_table(&mut self, path: &mut Path, table: &Table) -> Result<()> {
+        for (key, value) in table {
+            path.push(key.as_str());
+            self.add(path, value)?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, path: &mut Path, value: &Value) -> Result<()> {
+        let text = match value {
+            Value::Table(table) => return self.add_table(path, table),
+            Value::Array(items) => match self.export.nested {
+                Nested::Json => to_json(value).to_string(),
+                Nested::Indices => {
+                    for (i, item) in items.iter().enumerate() {
+                        path.push(i);
+                        self.add(path, item)?;
+                        path.pop();
+                    }
+                    return Ok(());
+                }
+                Nested::Error => {
+                    let message = format!("`{}` is an array, which can't be exported", path);
+                    return Err(Error::Message(message));
+                }
+            },
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
+        };
+
+        let name = self.export.name(path);
+        let mut chars = name.chars();
+        let first = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+        if !first || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            let message = format!("`{}` can't be exported as `{}`", path, name);
+            return Err(Error::Message(message));
+        }
+        if let Some(other) = self.names.insert(name.clone(), path.to_string()) {
+            let message = format!("`{}` and `{}` are both exported as `{}`", other, path, name);
+            return Err(Error::Message(message));
+        }
+        self.text.push_str(&name);
+        self.text.push('=');
+        quote(&mut self.text, &text);
+        self.text.push('\n');
+        Ok(())
+    }
+}
+
+/// Writes `s` as a single word for POSIX shells, in single quotes unless it's made only of
+/// characters that need none.
+fn quote(out: &mut String, s: &str) {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "%+,-./:@_".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        out.push_str(s);
+        return;
+    }
+    out.push('\'');
+    for c in s.chars() {
+        match c {
+            '\'' => out.push_str("'\\''"),
+            c => out.push(c),
+        }
+    }
+    out.push('\'');
+}
+
+/// Converts a value into plain JSON. Date-times and floats JSON can't hold are written as they
+/// are in a document, as strings.
+fn to_json(value: &Value) -> Json {
+    match value {
+        Value::String(s) => Json::String(s.clone()),
+        Value::Integer(i) => Json::Number((*i).into()),
+        Value::Float(f) => match Number::from_f64(*f) {
+            Some(n) => Json::Number(n),
+            None => Json::String(value.to_string()),
+        },
+        Value::Boolean(b) => Json::Bool(*b),
+        Value::Datetime(d) => Json::String(d.to_string()),
+        Value::Array(items) => Json::Array(items.iter().map(to_json).collect()),
+        Value::Table(table) => Json::Object(
+            table
+                .iter()
+                .map(|(k, v)| (k.clone(), to_json(v)))
+                .collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"
+title = "Tom's \"config\""
+empty = ""
+path = "/usr/local/bin:/bin"
+multi = "a\nb $HOME"
+ratio = 0.5
+inf = -inf
+debug = false
+when = 1979-05-27T07:32:00Z
+"content-type" = "text/plain"
+none = {}
+
+[[servers]]
+host = "a"
+ports = [80, 443]
+
+[[servers]]
+host = "b"
+"#;
+
+    #[test]
+    fn export_success() {
+        let table = crate::parse(TEXT).unwrap();
+        let export = Export::new();
+        assert_eq!(
+            export.render(&table).unwrap(),
+            r#"CONTENT_TYPE=text/plain
+DEBUG=false
+EMPTY=''
+INF=-inf
+MULTI='a
+b $HOME'
+PATH=/usr/local/bin:/bin
+RATIO=0.5
+SERVERS='[{"host":"a","ports":[80,443]},{"host":"b"}]'
+TITLE='Tom'\''s "config"'
+WHEN=1979-05-27T07:32:00Z
+"#
+        );
+
+        let mut export = Export::new();
+        export.nested(Nested::Indices);
+        export.separator("__");
+        export.prefix("my-app");
+        let servers = Value::Table(table).get("servers").unwrap().clone();
+        let table: Table = [("servers".to_string(), servers)].into_iter().collect();
+        assert_eq!(
+            export.render(&table).unwrap(),
+            "MY_APP__SERVERS__0__HOST=a\nMY_APP__SERVERS__0__PORTS__0=80\nMY_APP__SERVERS__0__PORTS__1=443\nMY_APP__SERVERS__1__HOST=b\n"
+        );
+    }
+
+    #[test]
+    fn export_fail() {
+        let fail = |text: &str, nested: Nested| {
+            let mut export = Export::new();
+            export.nested(nested);
+            let table = crate::parse(text).unwrap();
+            export.render(&table).unwrap_err().to_string()
+        };
+        assert_eq!(
+            fail("a = [1]", Nested::Error),
+            "`a` is an array, which can't be exported"
+        );
+        assert_eq!(
+            fail("a-b = 1\na_b = 2", Nested::Json),
+            "`a-b` and `a_b` are both exported as `A_B`"
+        );
+        assert_eq!(
+            fail("a.b = 1\na_b = 2", Nested::Json),
+            "`a.b` and `a_b` are both exported as `A_B`"
+        );
+        assert_eq!(
+            fail("a = [1, 2]\na_1 = 3", Nested::Indices),
+            "`a[1]` and `a_1` are both exported as `A_1`"
+        );
+        assert_eq!(
+            fail("1a = 1", Nested::Json),
+            "`1a` can't be exported as `1A`"
+        );
+        let mut export = Export::new();
+        export.separator(".");
+        let table = crate::parse("server.port = 80").unwrap();
+        assert_eq!(
+            export.render(&table).unwrap_err().to_string(),
+            "`server.port` can't be exported as `SERVER.PORT`"
+        );
+    }
+}
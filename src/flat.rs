@@ -0,0 +1,302 @@
+//! Conversion between documents and flat maps from dotted paths to values, as used by logging,
+//! metrics labels and key-value stores.
+//!
+//! Keys are written as in TOML, quoted only where needed, so every key of a flat map parses
+//! back to the path it came from. Arrays are flattened item by item, with indices written as
+//! `[0]` or as `.0`, as chosen with `IndexStyle`. Empty tables and arrays are kept as values, so
+//! that nothing is lost.
+//!
+//! ```
+//! use toml_rust::flat;
+//!
+//! let table = toml_rust::parse("a.\"b.c\" = 1\nports = [80, 443]\n").unwrap();
+//! let map = flat::flatten(&table);
+//! let keys: Vec<_> = map.keys().map(String::as_str).collect();
+//! assert_eq!(keys, ["a.\"b.c\"", "ports[0]", "ports[1]"]);
+//! assert_eq!(flat::unflatten(&map).unwrap(), table);
+//! ```
+
+use crate::error::{Error, Result};
+use crate::path::{Path, Segment};
+use crate::ser;
+use crate::value::{Table, Value};
+use std::collections::BTreeMap;
+
+/// How array indices are written in flat keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexStyle {
+    /// `servers[0].host`, as in paths.
+    #[default]
+    Brackets,
+    /// `servers.0.host`. Keys made of digits are quoted to tell them apart from indices.
+    Dots,
+}
+
+/// Flattens tables into maps and back.
+#[derive(Clone, Debug, Default)]
+pub struct Flatten {
+    indices: IndexStyle,
+}
+
+/// Flattens `table` with indices in brackets.
+pub fn flatten(table: &Table) -> BTreeMap<String, Value> {
+    Flatten::new().flatten(table)
+}
+
+/// Rebuilds a table from a map with indices in brackets.
+pub fn unflatten(map: &BTreeMap<String, Value>) -> Result<Table> {
+    Flatten::new().unflatten(map)
+}
+
+impl Flatten {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how array indices are written. The default is `IndexStyle::Brackets`.
+    pub fn indices(&mut self, style: IndexStyle) {
+        self.indices = style;
+    }
+
+    /// Returns the values in `table` that aren't non-empty tables or arrays, by their paths.
+    pub fn flatten(&self, table: &Table) -> BTreeMap<String, Value> {
+        let mut map = BTreeMap::new();
+        for (key, value) in table {
+            let mut path = Path::new();
+            path.push(key.as_str());
+            self.add(&mut map, &mut path, value);
+        }
+        map
+    }
+
+    fn add(&self, map: &mut BTreeMap<String, Value>, path: &mut Path, value: &Value) {
+        match value {
+            Value::Table(table) if !table.is_empty() => {
+                for (key, value) in table {
+                    path.push(key.as_str());
+                    self.add(map, path, value);
+                    path.pop();
+                }
+            }
+            Value::Array(items) if !items.is_empty() => {
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i);
+                    self.add(map, path, item);
+                    path.pop();
+                }
+            }
+            value => {
+                map.insert(self.key(path), value.clone());
+            }
+        }
+    }
+
+    /// Writes `path` as a flat key.
+    fn key(&self, path: &Path) -> String {
+        match self.indices {
+            IndexStyle::Brackets => path.to_string(),
+            IndexStyle::Dots => {
+                let mut key = String::new();
+                for (i, segment) in path.segments().iter().enumerate() {
+                    if i > 0 {
+                        key.push('.');
+                    }
+                    match segment {
+                        Segment::Key(k) if k.bytes().all(|b| b.is_ascii_digit()) => {
+                            ser::write_string(&mut key, k)
+                        }
+                        Segment::Key(k) => ser::write_key(&mut key, k),
+                        Segment::Index(index) => key.push_str(&index.to_string()),
+                    }
+                }
+                key
+            }
+        }
+    }
+
+    /// Rebuilds a table from the values in `map`, by their paths. Keys that don't parse are
+    /// reported as in the source of that key. Two keys for the same path, a path inside another,
+    /// and arrays with missing indices are errors.
+    pub fn unflatten(&self, map: &BTreeMap<String, Value>) -> Result<Table> {
+        let mut paths = BTreeMap::new();
+        for (key, value) in map {
+            let path = Path::parse_with(key, self.indices == IndexStyle::Dots)
+                .map_err(|e| e.in_source(key.as_str()))?;
+            match path.segments().first() {
+                Some(Segment::Key(_)) => {}
+                Some(Segment::Index(_)) | None => {
+                    let message = format!("`{}` doesn't start with a key", key);
+                    return Err(Error::Message(message));
+                }
+            }
+            if let Some((other, _)) = paths.insert(path, (key, value)) {
+                let message = format!("`{}` and `{}` are the same path", other, key);
+                return Err(Error::Message(message));
+            }
+        }
+
+        // In path order, tables and arrays are filled in before anything comes after them.
+        let mut root = Value::Table(Table::new());
+        let mut last: Option<(&Path, &String)> = None;
+        for (path, (key, value)) in &paths {
+            if let Some((last_path, last_key)) = last {
+                if path.starts_with(last_path) {
+                    let message = format!("`{}` is inside `{}`", key, last_key);
+                    return Err(Error::Message(message));
+                }
+            }
+            let mut node = &mut root;
+            let (leaf, parents) = path.segments().split_last().unwrap();
+            for (i, segment) in parents.iter().enumerate() {
+                let next = &path.segments()[i + 1];
+                let empty = || match next {
+                    Segment::Key(_) => Value::Table(Table::new()),
+                    Segment::Index(_) => Value::Array(Vec::new()),
+                };
+                node = match (node, segment) {
+                    (Value::Table(table), Segment::Key(k)) => {
+                        table.entry(k.clone()).or_insert_with(empty)
+                    }
+                    (Value::Array(items), Segment::Index(index)) => {
+                        if *index == items.len() {
+                            items.push(empty());
+                        }
+                        item(items, *index, key)?
+                    }
+                    _ => return Err(mixed(key)),
+                };
+            }
+            match (node, leaf) {
+                (Value::Table(table), Segment::Key(k)) => {
+                    table.insert(k.clone(), (*value).clone());
+                }
+                (Value::Array(items), Segment::Index(index)) => {
+                    if *index == items.len() {
+                        items.push((*value).clone());
+                    }
+                    item(items, *index, key)?;
+                }
+                _ => return Err(mixed(key)),
+            }
+            last = Some((path, key));
+        }
+        match root {
+            Value::Table(table) => Ok(table),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Returns the item at `index` of an array being rebuilt, which must have been added.
+fn item<'a>(items: &'a mut [Value], index: usize, key: &str) -> Result<&'a mut Value> {
+    let len = items.len();
+    items
+        .get_mut(index)
+        .ok_or_else(|| Error::Message(format!("`{}` skips index {} of its array", key, len)))
+}
+
+/// Returns an error for a key that uses an index where another key used a key, or the other
+/// way around.
+fn mixed(key: &str) -> Error {
+    Error::Message(format!(
+        "`{}` conflicts with another key over a table or array",
+        key
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flatten_success() {
+        let text = r#"
+title = "x"
+"a.b" = 1
+"0" = true
+empty = {}
+none = []
+nested = [[1, 2], []]
+
+[[servers]]
+host = "a"
+tags = ["x"]
+
+[[servers]]
+host = "b"
+"#;
+        let table = crate::parse(text).unwrap();
+        let map = flatten(&table);
+        let entries: Vec<String> = map.iter().map(|(k, v)| format!("{} = {}", k, v)).collect();
+        assert_eq!(
+            entries,
+            [
+                "\"a.b\" = 1",
+                "0 = true",
+                "empty = {}",
+                "nested[0][0] = 1",
+                "nested[0][1] = 2",
+                "nested[1] = []",
+                "none = []",
+                "servers[0].host = \"a\"",
+                "servers[0].tags[0] = \"x\"",
+                "servers[1].host = \"b\"",
+                "title = \"x\"",
+            ]
+        );
+        assert_eq!(unflatten(&map).unwrap(), table);
+
+        let mut flat = Flatten::new();
+        flat.indices(IndexStyle::Dots);
+        let map = flat.flatten(&table);
+        let keys: Vec<_> = map.keys().map(String::as_str).collect();
+        assert_eq!(
+            keys,
+            [
+                "\"0\"",
+                "\"a.b\"",
+                "empty",
+                "nested.0.0",
+                "nested.0.1",
+                "nested.1",
+                "none",
+                "servers.0.host",
+                "servers.0.tags.0",
+                "servers.1.host",
+                "title",
+            ]
+        );
+        assert_eq!(flat.unflatten(&map).unwrap(), table);
+
+        // Indices are ordered as numbers, not as text.
+        let map: BTreeMap<String, Value> = (0..12)
+            .map(|i| (format!("a[{}]", i), Value::Integer(i)))
+            .collect();
+        let table = unflatten(&map).unwrap();
+        assert_eq!(table["a"].as_array().unwrap()[10].as_integer(), Some(10));
+    }
+
+    #[test]
+    fn unflatten_fail() {
+        let fail = |keys: &[&str]| {
+            let map = keys
+                .iter()
+                .map(|k| (k.to_string(), Value::Integer(1)))
+                .collect();
+            unflatten(&map).unwrap_err().to_string()
+        };
+        assert_eq!(fail(&["a", "\"a\""]), "`\"a\"` and `a` are the same path");
+        assert_eq!(fail(&["a", "a.b"]), "`a.b` is inside `a`");
+        assert_eq!(
+            fail(&["a.b", "a[0]"]),
+            "`a[0]` conflicts with another key over a table or array"
+        );
+        assert_eq!(fail(&["a[1]"]), "`a[1]` skips index 0 of its array");
+        assert_eq!(
+            fail(&["a[0].b", "a[2].b"]),
+            "`a[2].b` skips index 1 of its array"
+        );
+        assert_eq!(fail(&["[0]"]), "`[0]` doesn't start with a key");
+        assert_eq!(fail(&["a..b"]), "a..b: Unexpected { pos: 2 }");
+    }
+}
@@ -0,0 +1,312 @@
+//! Loading configuration split across files with an include directive.
+//!
+//! A file can name other files in a top-level key, `include` by default:
+//!
+//! ```toml
+//! include = ["db.toml", "features/*.toml"]
+//! ```
+//!
+//! Paths are relative to the including file, and `*` and `?` can be used in their last
+//! component, matching files in lexical order. Included files are merged as layers before the
+//! file that includes them, so its own values take precedence, and they can include files of
+//! their own. A file included more than once is only loaded the first time. The result is a `Merged`, whose origins name the file each value came from; errors
+//! are reported in their file with `Error::in_source`.
+//!
+//! The include key is only special to `Loader`: `parse` and everything else treat it as any
+//! other key.
+
+use crate::error::{Error, Result};
+use crate::lex::Span;
+use crate::merge::{Merge, Merged};
+use crate::parse::{self, NodeKind};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Loads files, following their includes.
+#[derive(Clone, Debug)]
+pub struct Loader {
+    key: String,
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        Loader {
+            key: "include".to_string(),
+        }
+    }
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the top-level key that lists the files to include.
+    pub fn key(&mut self, key: impl Into<String>) {
+        self.key = key.into();
+    }
+
+    /// Loads the file at `path` and everything it includes, merging them the default way.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<Merged> {
+        let mut merge = Merge::new();
+        self.load_into(&mut merge, path)?;
+        merge.finish()
+    }
+
+    /// Adds the file at `path` and everything it includes to `merge` as layers, included files
+    /// first. This way, strategies for arrays can be set on `merge`.
+    pub fn load_into(&self, merge: &mut Merge, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let read = || {
+            let canonical = path.canonicalize()?;
+            Ok((canonical, fs::read_to_string(path)?))
+        };
+        let (canonical, text) =
+            read().map_err(|e: std::io::Error| Error::Message(e.to_string()).in_source(&name))?;
+        let mut loaded = HashSet::from([canonical.clone()]);
+        let mut stack = vec![(canonical, path.to_path_buf())];
+        self.layer(merge, name, text, &mut stack, &mut loaded)
+    }
+
+    /// Adds the file `name` with the text `text` after what it includes. `stack` holds the files
+    /// being loaded, this one last, and `loaded` every file loaded so far, by canonical path.
+    fn layer(
+        &self,
+        merge: &mut Merge,
+        name: String,
+        text: String,
+        stack: &mut Vec<(PathBuf, PathBuf)>,
+        loaded: &mut HashSet<PathBuf>,
+    ) -> Result<()> {
+        let mut root = parse::parse_nodes(&text).map_err(|e| e.in_source(&name))?;
+        if let Some(entry) = root.entries.remove(&self.key) {
+            let file = &stack.last().unwrap().1;
+            let dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+            let patterns = match entry.node.kind {
+                NodeKind::String(pattern) => vec![(pattern, entry.node.span)],
+                NodeKind::Array(items, _) => items
+                    .into_iter()
+                    .map(|item| match item.kind {
+                        NodeKind::String(pattern) => Ok((pattern, item.span)),
+                        _ => Err(item.span),
+                    })
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|span| self.not_paths(&name, span))?,
+                _ => return Err(self.not_paths(&name, entry.node.span)),
+            };
+            for (pattern, span) in patterns {
+                let at = |message: String| Error::Invalid { message, span }.in_source(&name);
+                for file in expand(&dir, &pattern).map_err(at)? {
+                    let cant_read = |e| format!("can't read `{}`: {}", file.display(), e);
+                    let canonical = file.canonicalize().map_err(|e| at(cant_read(e)))?;
+                    if let Some(pos) = stack.iter().position(|(c, _)| *c == canonical) {
+                        let mut cycle: Vec<_> = stack[pos..]
+                            .iter()
+                            .map(|(_, path)| path.display().to_string())
+                            .collect();
+                        cycle.push(file.display().to_string());
+                        return Err(at(format!("include cycle: {}", cycle.join(" -> "))));
+                    }
+                    if !loaded.insert(canonical.clone()) {
+                        continue;
+                    }
+                    let text = fs::read_to_string(&file).map_err(|e| at(cant_read(e)))?;
+                    let name = file.display().to_string();
+                    stack.push((canonical, file));
+                    self.layer(merge, name, text, stack, loaded)?;
+                    stack.pop();
+                }
+            }
+        }
+        merge.add_root(name, text, root);
+        Ok(())
+    }
+
+    fn not_paths(&self, name: &str, span: Span) -> Error {
+        let message = format!("`{}` must be a path or an array of paths", self.key);
+        Error::Invalid { message, span }.in_source(name)
+    }
+}
+
+/// Returns the files `pattern`, relative to `dir`, names.
+fn expand(dir: &Path, pattern: &str) -> std::result::Result<Vec<PathBuf>, String> {
+    let is_glob = |s: &str| s.contains(['*', '?']);
+    let (sub, file) = pattern.rsplit_once('/').unwrap_or(("", pattern));
+    if is_glob(sub) {
+        return Err(format!("`{}` has wildcards outside its file name", pattern));
+    }
+    if !is_glob(file) {
+        return Ok(vec![dir.join(pattern)]);
+    }
+    let dir = dir.join(sub);
+    let entries =
+        fs::read_dir(&dir).map_err(|e| format!("can't read `{}`: {}", dir.display(), e))?;
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| !t.is_dir()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| glob(file, name))
+        .collect();
+    names.sort();
+    Ok(names.into_iter().map(|name| dir.join(name)).collect())
+}
+
+/// Matches `name` against `pattern`, where `*` stands for any run of characters and `?` for
+/// any one.
+fn glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where matching goes on after the last `*`, and where in `name` what it takes ends.
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // The last `*` takes one more character, and matching goes on after it.
+                Some((after, end)) => {
+                    star = Some((after, end + 1));
+                    (p, n) = (after, end + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn include_success() {
+        let dir = TempDir::new(
+            "include-success",
+            &[
+                (
+                    "main.toml",
+                    "include = ['db.toml', 'features/*.toml']\nname = 'app'\n[db]\nport = 6543\n",
+                ),
+                (
+                    "db.toml",
+                    "include = 'common/base.toml'\n[db]\nport = 5432\nhost = 'h'\n",
+                ),
+                ("common/base.toml", "name = 'base'\n[db]\nuser = 'u'\n"),
+                ("features/b.toml", "[features]\nb = true\nlast = 'b'\n"),
+                ("features/a.toml", "[features]\na = true\nlast = 'a'\n"),
+                ("features/notes.txt", "not toml"),
+            ],
+        );
+        let merged = Loader::new().load(dir.0.join("main.toml")).unwrap();
+        let table = crate::Value::Table(merged.table().clone());
+        assert_eq!(
+            table.to_string(),
+            "{ db = { host = \"h\", port = 6543, user = \"u\" }, \
+             features = { a = true, b = true, last = \"b\" }, name = \"app\" }"
+        );
+        let origin = |path: &str| {
+            let origin = merged.origin(&path.parse().unwrap()).unwrap();
+            let name = Path::new(origin.name()).strip_prefix(&dir.0).unwrap();
+            (name.display().to_string(), origin.line_col())
+        };
+        assert_eq!(origin("db.user"), ("common/base.toml".to_string(), (3, 9)));
+        assert_eq!(origin("db.host"), ("db.toml".to_string(), (4, 9)));
+        assert_eq!(origin("db.port"), ("main.toml".to_string(), (4, 8)));
+        assert_eq!(
+            origin("features.a"),
+            ("features/a.toml".to_string(), (2, 5))
+        );
+
+        // Both `a.toml` and `b.toml` include `c.toml`, which is loaded before `a.toml` only.
+        let diamond = TempDir::new(
+            "include-diamond",
+            &[
+                ("main.toml", "include = ['a.toml', 'b.toml']\n"),
+                ("a.toml", "include = 'c.toml'\nx = 'a'\n"),
+                ("b.toml", "include = 'c.toml'\n"),
+                ("c.toml", "x = 'c'\n"),
+            ],
+        );
+        let merged = Loader::new().load(diamond.0.join("main.toml")).unwrap();
+        assert_eq!(merged.table()["x"].as_str(), Some("a"));
+
+        let mut loader = Loader::new();
+        loader.key("import");
+        let merged = loader.load(dir.0.join("main.toml")).unwrap();
+        assert_eq!(merged.table()["include"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn include_fail() {
+        let dir = TempDir::new(
+            "include-fail",
+            &[
+                ("a.toml", "x = 1\ninclude = ['b.toml']\n"),
+                ("b.toml", "include = ['sub/c.toml']\n"),
+                ("sub/c.toml", "include = ['../a.toml']\n"),
+                ("missing.toml", "include = ['nope.toml']"),
+                ("bad.toml", "include = [1]"),
+                ("glob.toml", "include = ['*/c.toml']"),
+                ("broken.toml", "include = ['d.toml']"),
+                ("d.toml", "a = "),
+            ],
+        );
+        let fail = |name: &str| {
+            let path = dir.0.join(name);
+            let (name, error) = match Loader::new().load(&path).unwrap_err() {
+                Error::Source { name, error } => (name, error),
+                e => panic!("{:?}", e),
+            };
+            let name = Path::new(&name).strip_prefix(&dir.0).unwrap().to_owned();
+            let message = error
+                .to_string()
+                .replace(&format!("{}/", dir.0.display()), "");
+            (name.display().to_string(), message)
+        };
+        assert_eq!(
+            fail("a.toml"),
+            (
+                "sub/c.toml".to_string(),
+                "include cycle: a.toml -> b.toml -> sub/c.toml -> sub/../a.toml at 12".to_string()
+            )
+        );
+        let err = Loader::new().load(dir.0.join("none.toml")).unwrap_err();
+        assert!(matches!(&err, Error::Source { name, .. } if name.ends_with("none.toml")));
+        let (name, message) = fail("missing.toml");
+        assert_eq!(name, "missing.toml");
+        assert!(
+            message.starts_with("can't read `nope.toml`: "),
+            "{}",
+            message
+        );
+        assert_eq!(
+            fail("bad.toml").1,
+            "`include` must be a path or an array of paths at 11"
+        );
+        assert_eq!(
+            fail("glob.toml").1,
+            "`*/c.toml` has wildcards outside its file name at 12"
+        );
+        assert_eq!(
+            fail("broken.toml"),
+            ("d.toml".to_string(), "Unexpected { pos: 4 }".to_string())
+        );
+
+        assert!(glob("*.toml", "a.toml"));
+        assert!(glob("a?c*", "aéc"));
+        assert!(glob("*a*b*", "xaxxbx"));
+        assert!(!glob("*.toml", "a.toml.bak"));
+        assert!(!glob("a*a*a*a*a*a*a*a*a*a*b", &"a".repeat(100)));
+    }
+}
@@ -0,0 +1,326 @@
+//! Expansion of `${...}` references in string values, done as a pass after parsing.
+//!
+//! A reference names either a value of the same document, by its path, or a variable of the
+//! environment given to `Interpolate::env`. Paths are looked up first, so a key shadows a
+//! variable of the same name. Referenced strings are expanded themselves; other scalars are
+//! written as they would be in TOML, and arrays and tables can't be referenced.
+//!
+//! Interpolation is opt-in: `parse` and everything else leave strings as they are.
+//!
+//! ```
+//! use toml_rust::interpolate::Interpolate;
+//!
+//! let text = "url = \"postgres://${DB_HOST}:${db.port}/app\"\n[db]\nport = 5432\n";
+//! let mut interpolate = Interpolate::new();
+//! interpolate.env([("DB_HOST", "localhost")]);
+//! let table = interpolate.run(text).unwrap();
+//! assert_eq!(table["url"].as_str(), Some("postgres://localhost:5432/app"));
+//! ```
+
+use crate::error::{Error, Result};
+use crate::lex::Span;
+use crate::parse::{self, Node, NodeKind};
+use crate::path::{Path, Segment};
+use crate::value::{Table, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+/// Expands references in the strings of a document.
+#[derive(Clone, Debug)]
+pub struct Interpolate {
+    env: BTreeMap<String, String>,
+    escape: String,
+}
+
+impl Default for Interpolate {
+    fn default() -> Self {
+        Interpolate {
+            env: BTreeMap::new(),
+            escape: "$${".to_string(),
+        }
+    }
+}
+
+impl Interpolate {
+    /// Returns an interpolation with an empty environment, where `$${` stands for a literal `${`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds variables to the environment, replacing any with the same names. To use the
+    /// process environment, pass `std::env::vars()`.
+    pub fn env<K, V>(&mut self, vars: impl IntoIterator<Item = (K, V)>)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env
+            .extend(vars.into_iter().map(|(k, v)| (k.into(), v.into())));
+    }
+
+    /// Sets the text that stands for a literal `${`, such as `\${`. An empty escape turns
+    /// escaping off.
+    pub fn escape(&mut self, escape: impl Into<String>) {
+        self.escape = escape.into();
+    }
+
+    /// Parses `text` and expands the references in its strings. Undefined references, cycles
+    /// and references to arrays or tables are errors with the span of the reference, or of the
+    /// whole string if the reference is written with escapes.
+    pub fn run(&self, text: &str) -> Result<Table> {
+        let root = Node {
+            span: Span::new(0, text.len()),
+            kind: NodeKind::Table(parse::parse_nodes(text)?),
+        };
+        let mut resolver = Resolver {
+            config: self,
+            text,
+            root: &root,
+            resolved: HashMap::new(),
+            stack: Vec::new(),
+        };
+        resolver.walk(&mut Path::new(), &root)?;
+
+        let mut value = root.clone().into_value();
+        for (path, s) in resolver.resolved {
+            if let Some(v) = path.get_mut(&mut value) {
+                *v = Value::String(s);
+            }
+        }
+        match value {
+            Value::Table(table) => Ok(table),
+            _ => unreachable!(),
+        }
+    }
+}
+
+struct Resolver<'a> {
+    config: &'a Interpolate,
+    text: &'a str,
+    root: &'a Node,
+    /// Expanded strings by path.
+    resolved: HashMap<Path, String>,
+    /// The strings being expanded, innermost last.
+    stack: Vec<Path>,
+}
+
+impl<'a> Resolver<'a> {
+    fn walk(&mut self, path: &mut Path, node: &'a Node) -> Result<()> {
+        match &node.kind {
+            NodeKind::String(_) if !self.resolved.contains_key(path) => {
+                self.string(path, node)?;
+            }
+            NodeKind::Array(items, _) => {
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i);
+                    self.walk(path, item)?;
+                    path.pop();
+                }
+            }
+            NodeKind::Table(table) => {
+                for (key, entry) in &table.entries {
+                    path.push(key.as_str());
+                    self.walk(path, &entry.node)?;
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Expands the string `node` at `path`.
+    fn string(&mut self, path: &Path, node: &'a Node) -> Result<String> {
+        let NodeKind::String(s) = &node.kind else {
+            unreachable!()
+        };
+        self.stack.push(path.clone());
+        let escape = self.config.escape.as_str();
+        let mut out = String::new();
+        let mut rest = s.as_str();
+        while !rest.is_empty() {
+            if !escape.is_empty() && rest.starts_with(escape) {
+                out.push_str("${");
+                rest = &rest[escape.len()..];
+            } else if rest.starts_with("${") {
+                let Some(end) = rest.find('}') else {
+                    let at = s.len() - rest.len();
+                    let message = "unclosed `${`".to_string();
+                    return Err(self.invalid(node, at..s.len(), message));
+                };
+                let at = s.len() - rest.len();
+                let value = self.reference(node, at, &rest[..=end])?;
+                out.push_str(&value);
+                rest = &rest[end + 1..];
+            } else {
+                let c = rest.chars().next().unwrap();
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        self.stack.pop();
+        self.resolved.insert(path.clone(), out.clone());
+        Ok(out)
+    }
+
+    /// Returns the text the reference `reference`, written at byte `at` of the string `node`,
+    /// expands to.
+    fn reference(&mut self, node: &'a Node, at: usize, reference: &str) -> Result<String> {
+        let part = at..at + reference.len();
+        let name = reference[2..reference.len() - 1].trim();
+        if name.is_empty() {
+            return Err(self.invalid(node, part.clone(), "empty reference".to_string()));
+        }
+        if let Some(target) = Path::parse(name).ok().and_then(|path| {
+            let target = node_at(self.root, &path)?;
+            Some((path, target))
+        }) {
+            let (path, target) = target;
+            return match &target.kind {
+                NodeKind::String(_) => {
+                    if let Some(pos) = self.stack.iter().position(|p| *p == path) {
+                        let mut cycle: Vec<String> =
+                            self.stack[pos..].iter().map(Path::to_string).collect();
+                        cycle.push(path.to_string());
+                        let message = format!("cycle in references: {}", cycle.join(" -> "));
+                        return Err(self.invalid(node, part.clone(), message));
+                    }
+                    match self.resolved.get(&path) {
+                        Some(s) => Ok(s.clone()),
+                        None => self.string(&path, target),
+                    }
+                }
+                NodeKind::Array(..) | NodeKind::Table(_) => {
+                    let message = format!(
+                        "`{}` is {}, which can't be interpolated",
+                        name,
+                        match target.kind {
+                            NodeKind::Table(_) => "a table",
+                            _ => "an array",
+                        }
+                    );
+                    Err(self.invalid(node, part.clone(), message))
+                }
+                _ => Ok(target.clone().into_value().to_string()),
+            };
+        }
+        match self.config.env.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => {
+                let message = format!("undefined reference `{}`", name);
+                Err(self.invalid(node, part.clone(), message))
+            }
+        }
+    }
+
+    /// Returns an error at the bytes `part` of the string `node`, or at the whole string if it
+    /// has escapes, as then its bytes aren't those of the source.
+    fn invalid(&self, node: &Node, part: Range<usize>, message: String) -> Error {
+        let NodeKind::String(s) = &node.kind else {
+            unreachable!()
+        };
+        let source = &self.text[node.span.lo()..node.span.hi()];
+        // Multi-line strings may start with a newline that isn't part of them.
+        let trimmed = source.len().saturating_sub(s.len());
+        let span = match source.split_at_checked(trimmed) {
+            Some(("" | "\n" | "\r\n", rest)) if rest == s => {
+                let lo = node.span.lo() + trimmed;
+                Span::new(lo + part.start, lo + part.end)
+            }
+            _ => node.span,
+        };
+        Error::Invalid { message, span }
+    }
+}
+
+fn node_at<'a>(root: &'a Node, path: &Path) -> Option<&'a Node> {
+    if path.is_empty() {
+        return None;
+    }
+    path.segments()
+        .iter()
+        .try_fold(root, |node, segment| match (segment, &node.kind) {
+            (Segment::Key(key), NodeKind::Table(table)) => table.entries.get(key).map(|e| &e.node),
+            (Segment::Index(i), NodeKind::Array(items, _)) => items.get(*i),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str) -> Result<Table> {
+        let mut interpolate = Interpolate::new();
+        interpolate.env([("HOME", "/home/me"), ("db", "shadowed")]);
+        interpolate.run(text)
+    }
+
+    #[test]
+    fn interpolate_success() {
+        let table = run(r#"
+            dir = "${HOME}/app"
+            log = '${dir}/log'
+            cost = "$${HOME} is ${HOME}"
+            hosts = ["a", "${hosts[0]}b"]
+            [db]
+            url = "${ db.host }:${db.port}"
+            host = "${hosts[1]}"
+            port = 5432
+            when = "${db.at}"
+            at = 1979-05-27
+        "#)
+        .unwrap();
+        let table = Value::Table(table);
+        let get = |path: &str| table.get_path(path).unwrap().as_str();
+        assert_eq!(get("dir"), Some("/home/me/app"));
+        assert_eq!(get("log"), Some("/home/me/app/log"));
+        assert_eq!(get("cost"), Some("${HOME} is /home/me"));
+        assert_eq!(get("hosts[1]"), Some("ab"));
+        assert_eq!(get("db.url"), Some("ab:5432"));
+        assert_eq!(get("db.when"), Some("1979-05-27"));
+        assert_eq!(table.get_path("db.port").unwrap().as_integer(), Some(5432));
+
+        let mut interpolate = Interpolate::new();
+        interpolate.escape("\\${");
+        let table = interpolate.run(r#"a = '\${a} $$'"#).unwrap();
+        assert_eq!(table["a"].as_str(), Some("${a} $$"));
+        interpolate.escape("");
+        let table = interpolate.run("a = '$${x}'\nx = 1").unwrap();
+        assert_eq!(table["a"].as_str(), Some("$1"));
+    }
+
+    #[test]
+    fn interpolate_fail() {
+        let fail = |text: &str| {
+            let e = run(text).unwrap_err();
+            let pos = e.pos().unwrap();
+            (e.to_string(), Span::new(pos, pos).line_col(text))
+        };
+        assert_eq!(
+            fail("a = 1\nb = \"x ${nope}\""),
+            ("undefined reference `nope` at 13".to_string(), (2, 8))
+        );
+        assert_eq!(
+            fail("a = \"${b}\"\nb = \"${c}\"\nc = \"${a}\""),
+            (
+                "cycle in references: a -> b -> c -> a at 27".to_string(),
+                (3, 6)
+            )
+        );
+        assert_eq!(fail("a = '${a}'").0, "cycle in references: a -> a at 5");
+        assert_eq!(
+            fail("t = {}\na = 'x${t}'").0,
+            "`t` is a table, which can't be interpolated at 13"
+        );
+        assert_eq!(fail("a = 'x${y'").0, "unclosed `${` at 6");
+        assert_eq!(fail("a = 'x${ }'").0, "empty reference at 6");
+        // Written with escapes, so the error covers the whole string.
+        assert_eq!(fail("a = \"\\u0024{x}\"").0, "undefined reference `x` at 5");
+        assert_eq!(
+            fail("a = \"$${x} ${x}\"").0,
+            "undefined reference `x` at 11"
+        );
+        assert_eq!(fail("a = '''\n${x}'''").0, "undefined reference `x` at 8");
+    }
+}
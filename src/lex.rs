@@ -39,6 +39,28 @@ pub enum Error {
     Unexpected {
         pos: usize,
     },
+    /// Arrays, inline tables and dotted keys are nested deeper than `MAX_DEPTH`.
+    TooDeep {
+        pos: usize,
+    },
+}
+
+impl Error {
+    /// Returns the byte offset at which the error was detected.
+    pub fn pos(&self) -> usize {
+        match *self {
+            Error::ControlCharacter { pos }
+            | Error::TooManyQuotesInString { pos, .. }
+            | Error::UnterminatedString { pos, .. }
+            | Error::MultilineKey { pos }
+            | Error::MultilineString { pos }
+            | Error::MissingDelimiter { pos }
+            | Error::UnconsumedInput { pos }
+            | Error::Expected { pos, .. }
+            | Error::Unexpected { pos }
+            | Error::TooDeep { pos } => pos,
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -49,12 +71,53 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Span {
     lo: usize,
     hi: usize,
 }
 
+impl Span {
+    pub const fn new(lo: usize, hi: usize) -> Self {
+        Span { lo, hi }
+    }
+
+    /// Index of the first byte covered by the span.
+    pub const fn lo(&self) -> usize {
+        self.lo
+    }
+
+    /// Index one past the last byte covered by the span.
+    pub const fn hi(&self) -> usize {
+        self.hi
+    }
+
+    pub const fn len(&self) -> usize {
+        self.hi - self.lo
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.hi == self.lo
+    }
+
+    /// Returns the 1-based line and column of the start of the span in `text`. Columns count
+    /// characters, not bytes, and a span past the end of `text` is placed at its end.
+    pub fn line_col(&self, text: &str) -> (usize, usize) {
+        let before = text.get(..self.lo).unwrap_or(text);
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line = before.matches('\n').count() + 1;
+        (line, before[line_start..].chars().count() + 1)
+    }
+
+    /// Returns the smallest span covering both `self` and `other`.
+    pub fn join(self, other: Span) -> Span {
+        Span {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Sym {
     Eof,
@@ -74,7 +137,7 @@ pub enum Sym {
     DateTime,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Symbol {
     sym: Sym,
     span: Span,
@@ -107,11 +170,39 @@ impl Symbol {
     }
 }
 
-pub struct Lex<'a> {
+/// Receives symbols from `Lex` as they're scanned.
+///
+/// `Vec<Symbol>` simply collects them. Other sinks can count, filter or forward symbols, or
+/// build something from them directly, without an intermediate vector.
+pub trait SymbolSink {
+    fn push(&mut self, symbol: Symbol);
+}
+
+impl SymbolSink for Vec<Symbol> {
+    #[inline(always)]
+    fn push(&mut self, symbol: Symbol) {
+        Vec::push(self, symbol)
+    }
+}
+
+impl<S: SymbolSink + ?Sized> SymbolSink for &mut S {
+    #[inline(always)]
+    fn push(&mut self, symbol: Symbol) {
+        (**self).push(symbol)
+    }
+}
+
+/// How deeply arrays, inline tables and the segments of dotted keys may be nested. Values are
+/// scanned, parsed and dropped recursively, so this keeps hostile input from overflowing the
+/// stack.
+pub const MAX_DEPTH: usize = 128;
+
+pub struct Lex<'a, S: SymbolSink = Vec<Symbol>> {
     pub text: &'a str,
     index: usize,
     current: u8,
-    pub symbols: Vec<Symbol>,
+    depth: usize,
+    pub symbols: S,
 
     #[cfg(test)]
     pub crash_on_error: bool,
@@ -119,11 +210,19 @@ pub struct Lex<'a> {
 
 impl<'a> Lex<'a> {
     pub fn new(text: &'a str) -> Self {
+        Self::with_sink(text, Vec::new())
+    }
+}
+
+impl<'a, S: SymbolSink> Lex<'a, S> {
+    /// Creates a lexer that hands each symbol to `sink` as it's scanned.
+    pub fn with_sink(text: &'a str, sink: S) -> Self {
         Self {
             text,
             index: 0,
-            current: *text.as_bytes().get(0).unwrap_or(&0),
-            symbols: Vec::new(),
+            current: *text.as_bytes().first().unwrap_or(&0),
+            depth: 0,
+            symbols: sink,
 
             #[cfg(test)]
             crash_on_error: false,
@@ -152,6 +251,12 @@ impl<'a> Lex<'a> {
         *self.text.as_bytes().get(self.index + 1).unwrap_or(&0)
     }
 
+    /// The remaining input, starting at the current byte. Empty once the end has been passed.
+    #[inline(always)]
+    fn rest(&self) -> &'a [u8] {
+        self.text.as_bytes().get(self.index..).unwrap_or(&[])
+    }
+
     #[inline(always)]
     fn advance(&mut self, index: usize) {
         self.index = index;
@@ -233,6 +338,20 @@ impl<'a> Lex<'a> {
         self.err(Error::MissingDelimiter { pos: self.index })
     }
 
+    #[cold]
+    fn err_too_deep(&self) -> Result<(), Error> {
+        self.err(Error::TooDeep { pos: self.index })
+    }
+
+    /// Enters an array, inline table or the next segment of a dotted key.
+    fn descend(&mut self) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            self.err_too_deep()?;
+        }
+        Ok(())
+    }
+
     /// Consumes a comment until the end of line or end of file.
     fn consume_comment(&mut self) -> Result<(), Error> {
         debug_assert_eq!(self.current, b'#');
@@ -287,7 +406,6 @@ impl<'a> Lex<'a> {
                     }
                     self.next();
                     self.next();
-                    break;
                 }
                 b'\n' | b' ' | b'\t' => self.next(),
                 b'#' => self.consume_comment()?,
@@ -299,27 +417,39 @@ impl<'a> Lex<'a> {
 
     /// Skip only spaces and tabs. Stops on comments and newlines.
     fn skip_whitespace(&mut self) -> Result<(), Error> {
-        loop {
-            match self.current {
-                b' ' | b'\t' => self.next(),
-                _ => break,
-            }
+        while let b' ' | b'\t' = self.current {
+            self.next();
         }
         Ok(())
     }
 
+    /// Checks that the value just scanned is followed by something that may legally follow a
+    /// value.
+    fn expect_value_end(&mut self) -> Result<(), Error> {
+        match self.current {
+            0 | b' ' | b'\t' | b'\r' | b'\n' | b'#' | b',' | b']' | b'}' => Ok(()),
+            _ => self.err_unexpected(),
+        }
+    }
+
     fn scan_key(&mut self) -> Result<(), Error> {
         let start = self.index;
+        self.skip_bare_key();
+        match self.current {
+            b' ' | b'\t' | b'=' | b'.' | b']' => {}
+            _ => self.err_unexpected()?,
+        }
+        self.push_span(Sym::Key, start, self.index - 1);
+        Ok(())
+    }
+
+    fn skip_bare_key(&mut self) {
         loop {
             self.next();
-            match self.current {
-                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => {}
-                b' ' | b'\t' | b'=' | b'.' | b']' => break,
-                _ => self.err_unexpected()?,
+            if !matches!(self.current, b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-') {
+                break;
             }
         }
-        self.push_span(Sym::Key, start, self.index - 1);
-        Ok(())
     }
 
     fn scan_multiline_basic_string(&mut self) -> Result<(), Error> {
@@ -346,28 +476,25 @@ impl<'a> Lex<'a> {
                 }
                 b'\\' => {
                     match quote_count {
-                        0 | 1 | 2 => {}
-                        3 | 4 | 5 => {
-                            let extra = quote_count - 3;
-                            break self.push_span(Sym::String, start, self.index - extra);
-                        }
+                        0..=2 => {}
+                        // Up to two quotes may directly precede the closing delimiter, they
+                        // belong to the string's content.
+                        3..=5 => break self.push_span(Sym::String, start, self.index - 4),
                         _ => self.err_too_many_quotes_in_string(start)?,
                     }
 
                     slash_count += 1;
                     quote_count = 0;
                 }
-                ch @ _ => {
+                ch => {
                     match quote_count {
-                        0 | 1 | 2 => {
+                        0..=2 => {
                             if ch == 0 {
                                 self.err_unterminated_string(start)?;
                             }
+                            slash_count = 0;
                         }
-                        3 | 4 | 5 => {
-                            let extra = quote_count - 3;
-                            break self.push_span(Sym::String, start, self.index - extra);
-                        }
+                        3..=5 => break self.push_span(Sym::String, start, self.index - 4),
                         _ => self.err_too_many_quotes_in_string(start)?,
                     }
                     quote_count = 0;
@@ -402,7 +529,7 @@ impl<'a> Lex<'a> {
             self.next();
         }
 
-        self.push_span(Sym::String, start, self.index);
+        self.push_span(Sym::String, start, self.index - 1);
         self.next();
         Ok(())
     }
@@ -416,18 +543,14 @@ impl<'a> Lex<'a> {
         self.next();
 
         let start = self.index;
-        let rest = &self.text.as_bytes()[start..];
+        let rest = self.rest();
 
-        if let Some(index) = memmem::find(rest, &[b'\'', b'\'', b'\'']) {
+        if let Some(index) = memmem::find(rest, b"'''") {
             self.advance(start + index + 3);
-            if self.eat(b'\'') {
-                if self.eat(b'\'') {
-                    if self.current == b'\'' {
-                        self.err_too_many_quotes_in_string(start)?;
-                    }
-                }
+            if self.eat(b'\'') && self.eat(b'\'') && self.current == b'\'' {
+                self.err_too_many_quotes_in_string(start)?;
             }
-            self.push_span(Sym::String, start, self.index - 3);
+            self.push_span(Sym::String, start, self.index - 4);
             Ok(())
         } else {
             self.err_unterminated_string(start)
@@ -439,7 +562,7 @@ impl<'a> Lex<'a> {
         self.next();
 
         let start = self.index;
-        let rest = &self.text.as_bytes()[start..];
+        let rest = self.rest();
         if let Some(index) = memchr::memchr3(b'\n', b'\'', b'\0', rest) {
             self.advance(start + index + 1);
             if rest[index] == b'\'' {
@@ -451,140 +574,142 @@ impl<'a> Lex<'a> {
     }
 
     fn scan_string(&mut self) -> Result<(), Error> {
-        match &self.text.as_bytes()[self.index..] {
+        match self.rest() {
             [b'\'', b'\'', b'\'', ..] => self.scan_multiline_literal_string(),
             [b'"', b'"', b'"', ..] => self.scan_multiline_basic_string(),
             [b'\'', ..] => self.scan_literal_string(),
             [b'"', ..] => self.scan_basic_string(),
-            _ => panic!(),
+            _ => self.err_unexpected(),
         }
     }
 
     fn scan_single_line_string(&mut self) -> Result<(), Error> {
-        match &self.text.as_bytes()[self.index..] {
+        match self.rest() {
             [b'\'', b'\'', b'\'', ..] => self.err_illegal_multiline_string(),
             [b'"', b'"', b'"', ..] => self.err_illegal_multiline_string(),
             [b'\'', ..] => self.scan_literal_string(),
             [b'"', ..] => self.scan_basic_string(),
-            _ => panic!(),
+            _ => self.err_unexpected(),
         }
     }
 
+    /// Scans a run of digits accepted by `is_digit`, which may be separated by single
+    /// underscores.
+    fn scan_digits(&mut self, is_digit: fn(u8) -> bool) -> Result<(), Error> {
+        if !is_digit(self.current) {
+            self.err_unexpected()?;
+        }
+        loop {
+            self.next();
+            match self.current {
+                c if is_digit(c) => {}
+                b'_' => {
+                    self.next();
+                    if !is_digit(self.current) {
+                        self.err_unexpected()?;
+                    }
+                }
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
     fn scan_number(&mut self) -> Result<(), Error> {
         let start = self.index;
 
-        match self.current {
-            b'-' | b'+' => self.next(),
-            _ => {}
+        let signed = match self.current {
+            b'-' | b'+' => {
+                self.next();
+                true
+            }
+            _ => false,
         };
 
-        if self.current == b'0' {
-            match self.peek() {
-                b'x' | b'X' => {
-                    self.next();
-                    self.next();
-                    let mut allow_underscore = false;
-                    loop {
-                        match self.current {
-                            b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F' => {
-                                allow_underscore = true;
-                                self.next();
-                            }
-                            b'_' => {
-                                if !allow_underscore {
-                                    self.err_unexpected()?;
-                                }
-                                allow_underscore = false;
-                            }
-                            0 | b' ' | b'\t' | b'\n' | b'#' | b',' => break,
-                            _ => self.err_unexpected()?,
-                        }
-                    }
-                    self.push_span(Sym::Integer, start, self.index - 1);
-                    return Ok(());
-                }
-                b'o' | b'O' => {
-                    self.next();
-                    self.next();
-                    let mut allow_underscore = false;
-                    loop {
-                        match self.current {
-                            b'0'..=b'7' => {
-                                allow_underscore = true;
-                                self.next();
-                            }
-                            b'_' => {
-                                if !allow_underscore {
-                                    self.err_unexpected()?;
-                                }
-                                allow_underscore = false;
-                                self.next();
-                            }
-                            0 | b' ' | b'\t' | b'\n' | b'#' | b',' => break,
-                            _ => self.err_unexpected()?,
-                        }
-                    }
-                    self.push_span(Sym::Integer, start, self.index - 1);
-                    return Ok(());
-                }
-                b'b' | b'B' => {
-                    self.next();
-                    self.next();
-                    let mut allow_underscore = false;
-                    loop {
-                        match self.current {
-                            b'0' | b'1' => {
-                                allow_underscore = true;
-                                self.next();
-                            }
-                            b'_' => {
-                                if !allow_underscore {
-                                    self.err_unexpected()?;
-                                }
-                                allow_underscore = false;
-                                self.next();
-                            }
-                            0 | b' ' | b'\t' | b'\n' | b'#' | b',' => break,
-                            _ => self.err_unexpected()?,
-                        }
-                    }
-                    self.push_span(Sym::Integer, start, self.index - 1);
-                    return Ok(());
+        match self.rest() {
+            [b'i', b'n', b'f', ..] | [b'n', b'a', b'n', ..] => {
+                self.advance(self.index + 3);
+                self.expect_value_end()?;
+                self.push_span(Sym::Float, start, self.index - 1);
+                return Ok(());
+            }
+            [b'0', radix @ (b'x' | b'o' | b'b'), ..] => {
+                if signed {
+                    self.err_unexpected()?;
                 }
-                _ => {}
+                let is_digit: fn(u8) -> bool = match radix {
+                    b'x' => |c| c.is_ascii_hexdigit(),
+                    b'o' => |c| matches!(c, b'0'..=b'7'),
+                    _ => |c| matches!(c, b'0' | b'1'),
+                };
+                self.next();
+                self.next();
+                self.scan_digits(is_digit)?;
+                self.expect_value_end()?;
+                self.push_span(Sym::Integer, start, self.index - 1);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let int_start = self.index;
+        self.scan_digits(|c| c.is_ascii_digit())?;
+        if self.text.as_bytes().get(int_start) == Some(&b'0') && self.index - int_start > 1 {
+            // Leading zeros are not allowed.
+            self.advance(int_start + 1);
+            self.err_unexpected()?;
+        }
+
+        let mut sym = Sym::Integer;
+        if self.eat(b'.') {
+            sym = Sym::Float;
+            self.scan_digits(|c| c.is_ascii_digit())?;
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            sym = Sym::Float;
+            if !self.eat(b'+') {
+                self.eat(b'-');
             }
+            self.scan_digits(|c| c.is_ascii_digit())?;
         }
 
-        let mut allow_underscore = false;
+        self.expect_value_end()?;
+        self.push_span(sym, start, self.index - 1);
+
+        Ok(())
+    }
+
+    /// Scans an offset date-time, local date-time, local date or local time. Only the overall
+    /// shape is checked here, the individual fields are validated when the value is parsed.
+    fn scan_datetime(&mut self) -> Result<(), Error> {
+        let start = self.index;
         loop {
             match self.current {
-                b'0'..=b'9' => {
-                    allow_underscore = true;
-                    self.next();
-                }
-                b'_' => {
-                    if !allow_underscore {
-                        self.err_unexpected()?;
-                    }
-                    allow_underscore = false;
-                    self.next();
-                }
-                0 | b' ' | b'\t' | b'\n' | b'#' | b',' => break,
-                _ => self.err_unexpected()?,
+                b'0'..=b'9' | b'-' | b'+' | b':' | b'.' | b'T' | b't' | b'Z' | b'z' => self.next(),
+                // A space may separate the date and time, but only directly after a full date.
+                b' ' if self.index - start == 10 && self.peek().is_ascii_digit() => self.next(),
+                _ => break,
             }
         }
-        self.push_span(Sym::Integer, start, self.index - 1);
-
+        self.expect_value_end()?;
+        self.push_span(Sym::DateTime, start, self.index - 1);
         Ok(())
     }
 
     fn scan_number_or_date(&mut self) -> Result<(), Error> {
-        todo!()
+        let rest = self.rest();
+        let digits = |n: usize| rest.len() > n && rest[..n].iter().all(u8::is_ascii_digit);
+        if (digits(4) && rest[4] == b'-') || (digits(2) && rest[2] == b':') {
+            self.scan_datetime()
+        } else {
+            self.scan_number()
+        }
     }
 
     fn scan_array(&mut self) -> Result<(), Error> {
         debug_assert_eq!(self.current, b'[');
 
+        self.descend()?;
         self.push(Sym::Array);
         self.next();
 
@@ -593,6 +718,7 @@ impl<'a> Lex<'a> {
         if self.current == b']' {
             self.push(Sym::ArrayEnd);
             self.next();
+            self.depth -= 1;
             return Ok(());
         }
 
@@ -616,6 +742,7 @@ impl<'a> Lex<'a> {
 
         self.push(Sym::ArrayEnd);
         self.next();
+        self.depth -= 1;
 
         Ok(())
     }
@@ -623,19 +750,20 @@ impl<'a> Lex<'a> {
     fn scan_inline_table(&mut self) -> Result<(), Error> {
         debug_assert_eq!(self.current, b'{');
 
+        self.descend()?;
         self.push(Sym::InlineTable);
         self.next();
 
         self.skip_whitespace()?;
 
-        if self.eat(b'}') {
+        if self.current == b'}' {
             self.push(Sym::InlineTableEnd);
+            self.next();
+            self.depth -= 1;
             return Ok(());
         }
 
-        self.scan_key_like()?;
-        self.skip_whitespace()?;
-        self.scan_value()?;
+        self.scan_keyval()?;
 
         loop {
             self.skip_whitespace()?;
@@ -643,9 +771,7 @@ impl<'a> Lex<'a> {
                 b',' => {
                     self.next();
                     self.skip_whitespace()?;
-                    self.scan_key_like()?;
-                    self.skip_whitespace()?;
-                    self.scan_value()?;
+                    self.scan_keyval()?;
                 }
                 b'}' => break,
                 _ => self.err_missing_delimiter()?,
@@ -654,12 +780,15 @@ impl<'a> Lex<'a> {
 
         self.push(Sym::InlineTableEnd);
         self.next();
+        self.depth -= 1;
         Ok(())
     }
 
     /// Consumes the remainder of a key-like after the first key or string up to the '=' character.
-    fn scan_dotted(&mut self) -> Result<(), Error> {
+    /// Returns the number of dots, by which the depth has been increased.
+    fn scan_dotted(&mut self) -> Result<usize, Error> {
         let mut saw_dot = false;
+        let mut dots = 0;
         loop {
             self.skip_whitespace()?;
             match self.current {
@@ -675,6 +804,8 @@ impl<'a> Lex<'a> {
                         self.err_unexpected()?;
                     }
                     saw_dot = true;
+                    dots += 1;
+                    self.descend()?;
                     self.next();
                 }
                 b'=' => {
@@ -688,7 +819,7 @@ impl<'a> Lex<'a> {
                         self.err_unexpected()?
                     }
                     saw_dot = false;
-                    self.scan_string()?
+                    self.scan_single_line_string()?
                 }
                 b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => {
                     if !saw_dot {
@@ -705,17 +836,25 @@ impl<'a> Lex<'a> {
         self.push(Sym::Assign);
         self.next();
 
-        Ok(())
+        Ok(dots)
     }
 
     /// Scan an entire key-like up to the '=' character.
-    fn scan_key_like(&mut self) -> Result<(), Error> {
+    fn scan_key_like(&mut self) -> Result<usize, Error> {
         match self.current {
-            b'"' | b'\'' => self.scan_string()?,
+            b'"' | b'\'' => self.scan_single_line_string()?,
             b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => self.scan_key()?,
             _ => self.err_unexpected()?,
         }
-        self.scan_dotted()?;
+        self.scan_dotted()
+    }
+
+    /// Scans a `key = value` pair, leaving the depth as it was.
+    fn scan_keyval(&mut self) -> Result<(), Error> {
+        let dots = self.scan_key_like()?;
+        self.skip_whitespace()?;
+        self.scan_value()?;
+        self.depth -= dots;
         Ok(())
     }
 
@@ -724,30 +863,22 @@ impl<'a> Lex<'a> {
             b'"' | b'\'' => self.scan_string()?,
             b'{' => self.scan_inline_table()?,
             b'[' => self.scan_array()?,
-            b't' | b'f' => match &self.text.as_bytes()[self.index..] {
-                &[b't', b'r', b'u', b'e', ..] => {
+            b't' | b'f' => match *self.rest() {
+                [b't', b'r', b'u', b'e', ..] => {
                     let start = self.index;
-                    let end = self.index + 4;
-                    self.advance(end);
-                    self.push_span(Sym::Bool, start, end);
+                    self.advance(start + 4);
+                    self.expect_value_end()?;
+                    self.push_span(Sym::Bool, start, self.index - 1);
                 }
-                &[b'f', b'a', b'l', b's', b'e', ..] => {
+                [b'f', b'a', b'l', b's', b'e', ..] => {
                     let start = self.index;
-                    let end = self.index + 5;
-                    self.advance(end);
-                    self.push_span(Sym::Bool, start, end);
-                }
-                _ => self.err_unexpected()?,
-            },
-            b'i' | b'n' => match &self.text.as_bytes()[self.index..] {
-                &[b'i', b'n', b'f'] | &[b'n', b'a', b'n'] => {
-                    let start = self.index;
-                    let end = self.index + 3;
-                    self.advance(end);
-                    self.push_span(Sym::Float, start, end);
+                    self.advance(start + 5);
+                    self.expect_value_end()?;
+                    self.push_span(Sym::Bool, start, self.index - 1);
                 }
                 _ => self.err_unexpected()?,
             },
+            b'i' | b'n' => self.scan_number()?,
             b'+' | b'-' => self.scan_number()?,
             b'0'..=b'9' => self.scan_number_or_date()?,
             _ => self.err_unexpected()?,
@@ -762,6 +893,9 @@ impl<'a> Lex<'a> {
 
         let is_array = self.eat(b'[');
 
+        // Key/value pairs that follow the header are nested as deeply as the header is dotted.
+        self.depth = 0;
+
         self.push(if is_array {
             Sym::ArrayOfTable
         } else {
@@ -784,6 +918,7 @@ impl<'a> Lex<'a> {
                         self.err_unexpected()?;
                     }
                     saw_dot = true;
+                    self.descend()?;
                     self.next();
                 }
                 b']' => {
@@ -836,9 +971,7 @@ impl<'a> Lex<'a> {
                 b'#' => self.consume_comment()?,
                 b'[' => self.scan_table()?,
                 b'"' | b'\'' | b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => {
-                    self.scan_key_like()?;
-                    self.skip_whitespace()?;
-                    self.scan_value()?;
+                    self.scan_keyval()?;
                     self.consume_line()?;
                 }
                 0 => break,
@@ -853,6 +986,104 @@ impl<'a> Lex<'a> {
         self.push(Sym::Eof);
         Ok(())
     }
+
+    /// Scans one bare or quoted key segment, without the dots and whitespace around it.
+    fn scan_key_segment(&mut self) -> Result<(), Error> {
+        match self.current {
+            b'"' | b'\'' => self.scan_single_line_string(),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => {
+                let start = self.index;
+                self.skip_bare_key();
+                self.push_span(Sym::Key, start, self.index - 1);
+                Ok(())
+            }
+            _ => self.err_unexpected(),
+        }
+    }
+
+    /// Scans the key segment starting at `index`, returning the index just past it. Lets
+    /// small languages embedded in the text, such as queries, share the grammar of keys.
+    pub(crate) fn scan_key_segment_at(&mut self, index: usize) -> Result<usize, Error> {
+        self.advance(index);
+        self.scan_key_segment()?;
+        Ok(self.index)
+    }
+
+    /// Scans the string, number, boolean or date-time starting at `index`, returning the index
+    /// just past it.
+    pub(crate) fn scan_scalar_at(&mut self, index: usize) -> Result<usize, Error> {
+        self.advance(index);
+        match self.current {
+            b'[' | b'{' => self.err_unexpected()?,
+            _ => self.scan_value()?,
+        }
+        Ok(self.index)
+    }
+
+    /// Scans a value standing on its own, such as one given in an environment variable, with
+    /// nothing but spaces and tabs around it.
+    pub(crate) fn scan_lone_value(&mut self) -> Result<(), Error> {
+        self.skip_whitespace()?;
+        self.scan_value()?;
+        self.scan_lone_end()
+    }
+
+    /// Scans a `key = value` pair standing on its own, such as one given on a command line,
+    /// with nothing but spaces and tabs around it.
+    pub(crate) fn scan_lone_keyval(&mut self) -> Result<(), Error> {
+        self.skip_whitespace()?;
+        self.scan_keyval()?;
+        self.scan_lone_end()
+    }
+
+    fn scan_lone_end(&mut self) -> Result<(), Error> {
+        self.skip_whitespace()?;
+        if self.index != self.text.len() {
+            self.err_unconsumed_input()?;
+        }
+        self.push(Sym::Eof);
+        Ok(())
+    }
+
+    /// Scans a path into a value, made of key segments written as in a dotted key and array
+    /// indices written as `[n]`: `servers."my.host".ip` or `products[2].name`. Keys come out as
+    /// `Key` or `String` symbols and indices as `Integer` symbols spanning their digits.
+    pub(crate) fn scan_path(&mut self) -> Result<(), Error> {
+        let mut need_key = false;
+        loop {
+            self.skip_whitespace()?;
+            match self.current {
+                b'"' | b'\'' | b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => {
+                    self.scan_key_segment()?
+                }
+                b'[' if !need_key => {
+                    self.next();
+                    self.skip_whitespace()?;
+                    let start = self.index;
+                    if !self.current.is_ascii_digit() {
+                        self.err_unexpected()?;
+                    }
+                    while self.current.is_ascii_digit() {
+                        self.next();
+                    }
+                    self.push_span(Sym::Integer, start, self.index - 1);
+                    self.skip_whitespace()?;
+                    if !self.eat(b']') {
+                        self.err_expected(b']')?;
+                    }
+                }
+                0 if !need_key && self.index == self.text.len() => break,
+                _ => self.err_unexpected()?,
+            }
+            self.skip_whitespace()?;
+            need_key = self.eat(b'.');
+            if !need_key && !matches!(self.current, b'[' | 0) {
+                self.err_unexpected()?;
+            }
+        }
+        self.push(Sym::Eof);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -903,6 +1134,32 @@ mod tests {
         fail!("\0", Error::UnconsumedInput { pos: 0 });
     }
 
+    #[test]
+    fn nesting_fail() {
+        let deep = |open: &str, close: &str, n: usize| {
+            format!("a = {}{}", open.repeat(n), close.repeat(n))
+        };
+        Lex::new(&deep("[", "]", MAX_DEPTH)).scan().unwrap();
+        let arrays = deep("[", "]", MAX_DEPTH + 1);
+        fail!(&arrays, Error::TooDeep { pos: 132 });
+        let tables = deep("{b=", "}", 100_000);
+        fail!(&tables, Error::TooDeep { pos: 388 });
+
+        let dotted = format!("{} = 1", ["a"; MAX_DEPTH + 2].join("."));
+        fail!(&dotted, Error::TooDeep { pos: 257 });
+        let header = format!("[{}]", ["a"; MAX_DEPTH + 2].join("."));
+        fail!(&header, Error::TooDeep { pos: 258 });
+
+        // Header and dotted key segments add up.
+        let both = format!("[{}]\nb.b.b = 1", ["a"; MAX_DEPTH].join("."));
+        fail!(
+            &both,
+            Error::TooDeep {
+                pos: 2 * MAX_DEPTH + 5
+            }
+        );
+    }
+
     #[test]
     fn basic_success() {
         succ!("", &[Symbol::new(Sym::Eof, 0)]);
@@ -929,7 +1186,7 @@ mod tests {
             &[
                 Symbol::with_span(Sym::Key, 0, 4),
                 Symbol::new(Sym::Assign, 6),
-                Symbol::with_span(Sym::Bool, 8, 12),
+                Symbol::with_span(Sym::Bool, 8, 11),
                 Symbol::new(Sym::Eof, 12),
             ]
         );
@@ -938,7 +1195,7 @@ mod tests {
             &[
                 Symbol::with_span(Sym::Key, 0, 4),
                 Symbol::new(Sym::Assign, 6),
-                Symbol::with_span(Sym::Bool, 8, 13),
+                Symbol::with_span(Sym::Bool, 8, 12),
                 Symbol::new(Sym::Eof, 13),
             ]
         );
@@ -1004,7 +1261,7 @@ mod tests {
             &[
                 Symbol::new(Sym::Table, 1),
                 Symbol::with_span(Sym::Key, 1, 5),
-                Symbol::with_span(Sym::String, 8, 22),
+                Symbol::with_span(Sym::String, 8, 21),
                 Symbol::new(Sym::TableEnd, 24),
                 Symbol::new(Sym::Eof, 24),
             ]
@@ -1014,7 +1271,7 @@ mod tests {
             &[
                 Symbol::new(Sym::Table, 1),
                 Symbol::with_span(Sym::Key, 2, 2),
-                Symbol::with_span(Sym::String, 7, 11),
+                Symbol::with_span(Sym::String, 7, 10),
                 Symbol::with_span(Sym::String, 16, 16),
                 Symbol::new(Sym::TableEnd, 20),
                 Symbol::new(Sym::Eof, 20),
@@ -1050,8 +1307,8 @@ mod tests {
                 Symbol::new(Sym::Key, 0),
                 Symbol::new(Sym::Assign, 2),
                 Symbol::new(Sym::Array, 4),
-                Symbol::with_span(Sym::Bool, 22, 26),
-                Symbol::with_span(Sym::Bool, 50, 55),
+                Symbol::with_span(Sym::Bool, 22, 25),
+                Symbol::with_span(Sym::Bool, 50, 54),
                 Symbol::new(Sym::ArrayEnd, 85),
                 Symbol::new(Sym::Eof, 86),
             ]
@@ -1063,11 +1320,11 @@ mod tests {
                 Symbol::with_span(Sym::Key, 0, 5),
                 Symbol::new(Sym::Assign, 7),
                 Symbol::new(Sym::Array, 9),
-                Symbol::with_span(Sym::String, 12, 15),
-                Symbol::with_span(Sym::String, 19, 25),
+                Symbol::with_span(Sym::String, 12, 14),
+                Symbol::with_span(Sym::String, 19, 24),
                 Symbol::new(Sym::Array, 28),
-                Symbol::with_span(Sym::String, 31, 36),
-                Symbol::with_span(Sym::String, 40, 46),
+                Symbol::with_span(Sym::String, 31, 35),
+                Symbol::with_span(Sym::String, 40, 45),
                 Symbol::new(Sym::ArrayEnd, 49),
                 Symbol::new(Sym::ArrayEnd, 52),
                 Symbol::new(Sym::Eof, 53),
@@ -1095,7 +1352,7 @@ mod tests {
                 Symbol::new(Sym::InlineTable, 9),
                 Symbol::with_span(Sym::Key, 11, 13),
                 Symbol::new(Sym::Assign, 15),
-                Symbol::with_span(Sym::Bool, 17, 21),
+                Symbol::with_span(Sym::Bool, 17, 20),
                 Symbol::new(Sym::InlineTableEnd, 22),
                 Symbol::new(Sym::Eof, 23)
             ]
@@ -1108,7 +1365,7 @@ mod tests {
                 Symbol::new(Sym::InlineTable, 9),
                 Symbol::with_span(Sym::Key, 11, 13),
                 Symbol::new(Sym::Assign, 15),
-                Symbol::with_span(Sym::Bool, 17, 21),
+                Symbol::with_span(Sym::Bool, 17, 20),
                 Symbol::with_span(Sym::Key, 23, 27),
                 Symbol::new(Sym::Assign, 29),
                 Symbol::with_span(Sym::String, 32, 39),
@@ -1125,7 +1382,7 @@ mod tests {
                 Symbol::with_span(Sym::Key, 11, 14),
                 Symbol::with_span(Sym::Key, 16, 19),
                 Symbol::new(Sym::Assign, 21),
-                Symbol::with_span(Sym::String, 24, 27),
+                Symbol::with_span(Sym::String, 24, 26),
                 Symbol::new(Sym::InlineTableEnd, 29),
                 Symbol::new(Sym::Eof, 30)
             ]
@@ -1176,4 +1433,128 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn numbers_fail() {
+        fail!("a = 1__0", Error::Unexpected { pos: 6 });
+        fail!("a = 1_", Error::Unexpected { pos: 6 });
+        fail!("a = 012", Error::Unexpected { pos: 5 });
+        fail!("a = +0x1", Error::Unexpected { pos: 5 });
+        fail!("a = 0xg", Error::Unexpected { pos: 6 });
+        fail!("a = 1.", Error::Unexpected { pos: 6 });
+        fail!("a = 1e", Error::Unexpected { pos: 6 });
+        fail!("a = 1x", Error::Unexpected { pos: 5 });
+        fail!("a = infinity", Error::Unexpected { pos: 7 });
+    }
+
+    #[test]
+    fn numbers_success() {
+        succ!(
+            "a = [0xf_f, 0o7, 0b1, -1_000, +0.5e-3, -inf, nan]",
+            &[
+                Symbol::new(Sym::Key, 0),
+                Symbol::new(Sym::Assign, 2),
+                Symbol::new(Sym::Array, 4),
+                Symbol::with_span(Sym::Integer, 5, 9),
+                Symbol::with_span(Sym::Integer, 12, 14),
+                Symbol::with_span(Sym::Integer, 17, 19),
+                Symbol::with_span(Sym::Integer, 22, 27),
+                Symbol::with_span(Sym::Float, 30, 36),
+                Symbol::with_span(Sym::Float, 39, 42),
+                Symbol::with_span(Sym::Float, 45, 47),
+                Symbol::new(Sym::ArrayEnd, 48),
+                Symbol::new(Sym::Eof, 49),
+            ]
+        );
+    }
+
+    #[test]
+    fn datetimes_success() {
+        succ!(
+            "a = 1979-05-27 07:32:00Z\nb = 07:32:00.5 # time",
+            &[
+                Symbol::new(Sym::Key, 0),
+                Symbol::new(Sym::Assign, 2),
+                Symbol::with_span(Sym::DateTime, 4, 23),
+                Symbol::new(Sym::Key, 25),
+                Symbol::new(Sym::Assign, 27),
+                Symbol::with_span(Sym::DateTime, 29, 38),
+                Symbol::new(Sym::Eof, 46),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiline_strings_success() {
+        succ!(
+            r#"a = """x"""""
+b = '''y'''''"#,
+            &[
+                Symbol::new(Sym::Key, 0),
+                Symbol::new(Sym::Assign, 2),
+                Symbol::with_span(Sym::String, 7, 9),
+                Symbol::new(Sym::Key, 14),
+                Symbol::new(Sym::Assign, 16),
+                Symbol::with_span(Sym::String, 21, 23),
+                Symbol::new(Sym::Eof, 27),
+            ]
+        );
+    }
+
+    #[test]
+    fn sink_success() {
+        #[derive(Default)]
+        struct Count(usize);
+
+        impl SymbolSink for Count {
+            fn push(&mut self, _: Symbol) {
+                self.0 += 1;
+            }
+        }
+
+        struct Strings(Vec<Symbol>);
+
+        impl SymbolSink for Strings {
+            fn push(&mut self, symbol: Symbol) {
+                if symbol.sym() == Sym::String {
+                    self.0.push(symbol);
+                }
+            }
+        }
+
+        let text = "a = 'b'\n[c]\nd = [\"e\", 1, {f = 'g'}]";
+        let mut all = Lex::new(text);
+        all.scan().unwrap();
+
+        let mut count = Lex::with_sink(text, Count::default());
+        count.scan().unwrap();
+        assert_eq!(count.symbols.0, all.symbols.len());
+
+        let mut strings = Lex::with_sink(text, Strings(Vec::new()));
+        strings.scan().unwrap();
+        let expected: Vec<_> = all
+            .symbols
+            .iter()
+            .filter(|s| s.sym() == Sym::String)
+            .copied()
+            .collect();
+        assert_eq!(strings.symbols.0, expected);
+        assert_eq!(expected.len(), 3);
+
+        let mut borrowed = Vec::new();
+        Lex::with_sink(text, &mut borrowed).scan().unwrap();
+        assert_eq!(borrowed, all.symbols);
+    }
+
+    #[test]
+    fn line_col_success() {
+        let text = "a = 1\n\u{e9} = 'x'\r\n\nb = 2";
+        assert_eq!(Span::new(0, 1).line_col(text), (1, 1));
+        assert_eq!(Span::new(4, 5).line_col(text), (1, 5));
+        assert_eq!(Span::new(6, 8).line_col(text), (2, 1));
+        assert_eq!(Span::new(12, 13).line_col(text), (2, 6));
+        assert_eq!(Span::new(16, 17).line_col(text), (3, 1));
+        assert_eq!(Span::new(17, 18).line_col(text), (4, 1));
+        assert_eq!(Span::new(99, 99).line_col(text), (4, 6));
+    }
 }
@@ -1,12 +1,47 @@
+pub mod codegen;
+pub mod conf_d;
+pub mod cst;
+mod datetime;
+mod de;
+pub mod diff;
+pub mod discover;
+mod document;
+pub mod env;
 mod error;
+pub mod export;
+pub mod flat;
+pub mod include;
+pub mod interpolate;
 mod lex;
+pub mod merge;
+pub mod overrides;
+mod parse;
+pub mod patch;
+mod path;
+pub mod query;
 mod read;
+pub mod schema;
+mod ser;
+mod shape;
+mod spanned;
+pub mod tagged;
+#[cfg(test)]
+mod test_util;
+pub mod three_way;
+mod validate;
+mod value;
 
-//pub use crate::de::{from_reader, from_str, Deserializer};
-//pub use crate::error::{Error, Result};
-//pub use crate::ser::{to_string, Serializer};
-
-pub use crate::lex::{Error, Lex, Symbol};
+pub use crate::datetime::{Date, Datetime, DatetimeKind, DatetimeParseError, Offset, Time};
+pub use crate::de::{from_reader, from_str, Deserializer};
+pub use crate::document::{ArrayMut, DocumentMut, TableMut};
+pub use crate::error::{Error, Result};
+pub use crate::lex::{Error as LexError, Lex, Span, Sym, Symbol, SymbolSink, MAX_DEPTH};
+pub use crate::parse::parse;
+pub use crate::path::{Path, Segment};
+pub use crate::ser::{to_string, to_value, ValueSerializer};
+pub use crate::spanned::Spanned;
+pub use crate::validate::validate;
+pub use crate::value::{Array, Table, Value};
 
 #[cfg(test)]
 mod tests {}
@@ -0,0 +1,588 @@
+//! Merging layers of configuration, such as built-in defaults overridden by a system file, a
+//! user file and a local file, while remembering where each value came from.
+//!
+//! Tables are merged key by key, all the way down. Any other value in a later layer replaces the
+//! one before it, except for arrays, which follow an `ArrayStrategy`.
+//!
+//! ```
+//! use toml_rust::merge::{ArrayStrategy, Merge};
+//! use toml_rust::Path;
+//!
+//! let mut merge = Merge::new();
+//! merge.arrays_at("servers".parse().unwrap(), ArrayStrategy::MergeByKey("name".to_string()));
+//! merge.add("defaults", "port = 80\n[[servers]]\nname = 'a'\nip = '10.0.0.1'\n").unwrap();
+//! merge.add("app.toml", "[[servers]]\nname = 'a'\nip = '10.0.0.2'\n").unwrap();
+//! let merged = merge.finish().unwrap();
+//!
+//! let ip: Path = "servers[0].ip".parse().unwrap();
+//! assert_eq!(ip.get_in(merged.table()).unwrap().as_str(), Some("10.0.0.2"));
+//! let origin = merged.origin(&ip).unwrap();
+//! assert_eq!((origin.name(), origin.line_col()), ("app.toml", (3, 7)));
+//! assert_eq!(merged.origin(&"port".parse().unwrap()).unwrap().name(), "defaults");
+//! ```
+
+use crate::de::{NodeDeserializer, Track};
+use crate::error::{Error, Result};
+use crate::lex::Span;
+use crate::parse::{self, ArrayKind, Entry, Node, NodeKind, TableKind, TableNode};
+use crate::path::{ByKeys, Path};
+use crate::value::{Table, Value};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+
+/// How an array in a later layer is combined with the array it overrides.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ArrayStrategy {
+    /// The later array replaces the earlier one.
+    #[default]
+    Replace,
+    /// The items of the later array are added after those of the earlier one.
+    Append,
+    /// Items are tables identified by the value of the given key. A later item with the same
+    /// identity as an earlier one is merged into it, others are added at the end. Items that
+    /// aren't tables with that key are an error.
+    MergeByKey(String),
+}
+
+/// A value from one of the layers, along with where it was written.
+#[derive(Clone, Debug)]
+struct Layered {
+    source: usize,
+    span: Span,
+    kind: Kind,
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Value(Value),
+    Array(Vec<Layered>),
+    Table(BTreeMap<String, Layered>),
+}
+
+impl Layered {
+    fn new(source: usize, node: Node) -> Self {
+        let kind = match node.kind {
+            NodeKind::Table(table) => Kind::Table(
+                table
+                    .entries
+                    .into_iter()
+                    .map(|(key, entry)| (key, Layered::new(source, entry.node)))
+                    .collect(),
+            ),
+            NodeKind::Array(items, _) => Kind::Array(
+                items
+                    .into_iter()
+                    .map(|item| Layered::new(source, item))
+                    .collect(),
+            ),
+            kind => Kind::Value(
+                Node {
+                    span: node.span,
+                    kind,
+                }
+                .into_value(),
+            ),
+        };
+        Layered {
+            source,
+            span: node.span,
+            kind,
+        }
+    }
+
+    /// Converts the value back, recording where it and everything below it came from.
+    fn into_value(self, path: &mut Path, origins: &mut BTreeMap<Path, (usize, Span)>) -> Value {
+        origins.insert(path.clone(), (self.source, self.span));
+        match self.kind {
+            Kind::Value(value) => value,
+            Kind::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, item)| {
+                        path.push(i);
+                        let value = item.into_value(path, origins);
+                        path.pop();
+                        value
+                    })
+                    .collect(),
+            ),
+            Kind::Table(entries) => Value::Table(
+                entries
+                    .into_iter()
+                    .map(|(key, entry)| {
+                        path.push(key.as_str());
+                        let value = entry.into_value(path, origins);
+                        path.pop();
+                        (key, value)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Collects layers of configuration and merges them, later layers taking precedence.
+#[derive(Debug, Default)]
+pub struct Merge {
+    sources: Vec<(String, String)>,
+    layers: Vec<Layered>,
+    arrays: ArrayStrategy,
+    arrays_at: ByKeys<ArrayStrategy>,
+}
+
+impl Merge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the strategy for arrays that have none of their own. The default is to replace.
+    pub fn arrays(&mut self, strategy: ArrayStrategy) {
+        self.arrays = strategy;
+    }
+
+    /// Sets the strategy for the arrays at `path`. Indices in `path` are ignored, so
+    /// `products.tags` applies to the `tags` of every item of `products`.
+    pub fn arrays_at(&mut self, path: Path, strategy: ArrayStrategy) {
+        self.arrays_at.insert(&path, strategy);
+    }
+
+    /// Parses `text` and adds it as the next layer. `name` identifies it in origins and errors,
+    /// so it's usually a file name.
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> Result<()> {
+        let (name, text) = (name.into(), text.into());
+        let root = match parse::parse_nodes(&text) {
+            Ok(root) => root,
+            Err(e) => return Err(e.in_source(name)),
+        };
+        self.add_root(name, text, root);
+        Ok(())
+    }
+
+    /// Starts a merge with the document `text` as its first layer, named `document`, for
+    /// overlays such as environment variables to be added over it before deserializing. Errors
+    /// in it are reported as by `from_str`.
+    pub fn with_document(text: impl Into<String>) -> Result<Merge> {
+        let text = text.into();
+        let root = parse::parse_nodes(&text)?;
+        let mut merge = Merge::new();
+        merge.add_root("document".to_string(), text, root);
+        Ok(merge)
+    }
+
+    /// Adds `root`, parsed from `text`, as the next layer.
+    pub(crate) fn add_root(&mut self, name: String, text: String, root: TableNode) {
+        let node = Node {
+            span: Span::new(0, text.len()),
+            kind: NodeKind::Table(root),
+        };
+        self.layers.push(Layered::new(self.sources.len(), node));
+        self.sources.push((name, text));
+    }
+
+    /// Merges the layers added so far.
+    pub fn finish(self) -> Result<Merged> {
+        let mut layers = self.layers.iter().cloned();
+        let mut origins = BTreeMap::new();
+        let table = match layers.next() {
+            Some(first) => {
+                let mut keys = Path::new();
+                let root =
+                    layers.try_fold(first, |base, layer| self.merge(&mut keys, base, layer))?;
+                match root.into_value(&mut Path::new(), &mut origins) {
+                    Value::Table(table) => table,
+                    _ => unreachable!("layers are tables"),
+                }
+            }
+            None => Table::new(),
+        };
+        Ok(Merged {
+            table,
+            sources: self.sources,
+            origins,
+        })
+    }
+
+    pub(crate) fn strategy(&self, keys: &Path) -> &ArrayStrategy {
+        self.arrays_at.get(keys).unwrap_or(&self.arrays)
+    }
+
+    /// Merges `over` into `base`. `keys` is the path to them without indices.
+    fn merge(&self, keys: &mut Path, base: Layered, over: Layered) -> Result<Layered> {
+        let kind = match (base.kind, over.kind) {
+            (Kind::Table(mut entries), Kind::Table(over_entries)) => {
+                for (key, value) in over_entries {
+                    let value = match entries.remove(&key) {
+                        Some(base) => {
+                            keys.push(key.as_str());
+                            let value = self.merge(keys, base, value);
+                            keys.pop();
+                            value?
+                        }
+                        None => value,
+                    };
+                    entries.insert(key, value);
+                }
+                Kind::Table(entries)
+            }
+            (Kind::Array(mut items), Kind::Array(over_items)) => match self.strategy(keys) {
+                ArrayStrategy::Replace => Kind::Array(over_items),
+                ArrayStrategy::Append => {
+                    items.extend(over_items);
+                    Kind::Array(items)
+                }
+                ArrayStrategy::MergeByKey(id) => {
+                    for item in &items {
+                        self.identity(keys, id, item)?;
+                    }
+                    for item in over_items {
+                        let identity = self.identity(keys, id, &item)?;
+                        let found = items
+                            .iter()
+                            .position(|base| self.identity(keys, id, base).ok() == Some(identity));
+                        match found {
+                            Some(i) => {
+                                let base = items.remove(i);
+                                items.insert(i, self.merge(keys, base, item)?);
+                            }
+                            None => items.push(item),
+                        }
+                    }
+                    Kind::Array(items)
+                }
+            },
+            (_, kind) => kind,
+        };
+        Ok(Layered {
+            source: over.source,
+            span: over.span,
+            kind,
+        })
+    }
+
+    /// Returns the value that identifies `item` in an array merged by the key `id`.
+    fn identity<'a>(&self, keys: &Path, id: &str, item: &'a Layered) -> Result<&'a Value> {
+        if let Kind::Table(entries) = &item.kind {
+            if let Some(Layered {
+                kind: Kind::Value(value),
+                ..
+            }) = entries.get(id)
+            {
+                return Ok(value);
+            }
+        }
+        let error = Error::Invalid {
+            message: format!("item of `{}` has no `{}` to merge by", keys, id),
+            span: item.span,
+        };
+        Err(error.in_source(&self.sources[item.source].0))
+    }
+}
+
+/// The result of a merge: a table along with the origin of each of its values.
+#[derive(Clone, Debug)]
+pub struct Merged {
+    table: Table,
+    sources: Vec<(String, String)>,
+    origins: BTreeMap<Path, (usize, Span)>,
+}
+
+/// Where a merged value was written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Origin<'a> {
+    name: &'a str,
+    text: &'a str,
+    span: Span,
+}
+
+impl<'a> Origin<'a> {
+    /// Returns the name of the layer the value came from.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the text of the layer the value came from.
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    /// Returns the span of the value in its layer. Tables defined by a `[header]` are spanned by
+    /// the last key of the header, and a whole layer by all of its text.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Returns the 1-based line and column of the start of the value in its layer.
+    pub fn line_col(&self) -> (usize, usize) {
+        self.span.line_col(self.text)
+    }
+}
+
+impl Merged {
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    pub fn into_table(self) -> Table {
+        self.table
+    }
+
+    /// Deserializes an instance of `T` from the merged table, as `from_str` does from a
+    /// document. `Spanned` values are spanned as in the layer they came from, which `origin`
+    /// names, and keys of maps as their values are. Errors are in the source of the value that
+    /// failed, at its span.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T> {
+        let root = self.node(&mut Path::new(), Value::Table(self.table.clone()));
+        let track = Track::new();
+        T::deserialize(NodeDeserializer::tracked(root, track.clone())).map_err(|e| {
+            let mut paths = std::iter::successors(Some(track.path()), Path::parent);
+            let Some(origin) = paths.find_map(|path| self.origin(&path)) else {
+                return e;
+            };
+            let e = match e.pos() {
+                Some(_) => e,
+                None => Error::Invalid {
+                    message: e.to_string(),
+                    span: origin.span(),
+                },
+            };
+            e.in_source(origin.name())
+        })
+    }
+
+    /// Converts the value at `path` back into a node, with the spans recorded in `origins`.
+    fn node(&self, path: &mut Path, value: Value) -> Node {
+        let span = self
+            .origins
+            .get(path)
+            .map_or(Span::new(0, 0), |&(_, span)| span);
+        let kind = match value {
+            Value::Table(table) => {
+                let mut node = TableNode::new(TableKind::Implicit);
+                for (key, value) in table {
+                    path.push(key.as_str());
+                    let child = self.node(path, value);
+                    path.pop();
+                    let key_span = child.span;
+                    node.entries.insert(
+                        key,
+                        Entry {
+                            key_span,
+                            node: child,
+                        },
+                    );
+                }
+                NodeKind::Table(node)
+            }
+            Value::Array(items) => {
+                let mut nodes = Vec::new();
+                for (i, item) in items.into_iter().enumerate() {
+                    path.push(i);
+                    nodes.push(self.node(path, item));
+                    path.pop();
+                }
+                NodeKind::Array(nodes, ArrayKind::Static)
+            }
+            value => return Node::from_value(value, span),
+        };
+        Node { span, kind }
+    }
+
+    /// Returns where the value at `path` came from. A table merged from several layers comes
+    /// from the last of them that defines it; the empty path names the last layer.
+    pub fn origin(&self, path: &Path) -> Option<Origin<'_>> {
+        let &(source, span) = self.origins.get(path)?;
+        Some(self.make_origin(source, span))
+    }
+
+    /// Returns the origin of every value, in path order.
+    pub fn origins(&self) -> impl Iterator<Item = (&Path, Origin<'_>)> {
+        self.origins
+            .iter()
+            .map(|(path, &(source, span))| (path, self.make_origin(source, span)))
+    }
+
+    fn make_origin(&self, source: usize, span: Span) -> Origin<'_> {
+        let (name, text) = &self.sources[source];
+        Origin { name, text, span }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spanned::Spanned;
+
+    const DEFAULTS: &str = r#"
+name = "app"
+tags = ["a"]
+
+[server]
+host = "localhost"
+port = 80
+
+[[plugins]]
+name = "lint"
+level = 1
+
+[[plugins]]
+name = "fmt"
+"#;
+
+    const SYSTEM: &str = r#"
+tags = ["b"]
+
+[server]
+port = 8080
+
+[[plugins]]
+name = "lint"
+level = 2
+
+[[plugins]]
+name = "docs"
+"#;
+
+    const LOCAL: &str = "server.host = '0.0.0.0'\nname = { full = 'app' }\n";
+
+    fn merged(configure: impl FnOnce(&mut Merge)) -> Merged {
+        let mut merge = Merge::new();
+        configure(&mut merge);
+        merge.add("defaults", DEFAULTS).unwrap();
+        merge.add("/etc/app.toml", SYSTEM).unwrap();
+        merge.add("app.local.toml", LOCAL).unwrap();
+        merge.finish().unwrap()
+    }
+
+    fn get<'a>(merged: &'a Merged, path: &str) -> &'a Value {
+        path.parse::<Path>()
+            .unwrap()
+            .get_in(merged.table())
+            .unwrap()
+    }
+
+    fn origin(merged: &Merged, path: &str) -> (String, (usize, usize)) {
+        let origin = merged.origin(&path.parse().unwrap()).unwrap();
+        (origin.name().to_string(), origin.line_col())
+    }
+
+    #[test]
+    fn merge_success() {
+        let m = merged(|_| {});
+        assert_eq!(get(&m, "server.host").as_str(), Some("0.0.0.0"));
+        assert_eq!(get(&m, "server.port").as_integer(), Some(8080));
+        assert_eq!(get(&m, "name.full").as_str(), Some("app"));
+        assert_eq!(get(&m, "tags"), &Value::Array(vec!["b".into()]));
+        assert_eq!(get(&m, "plugins").as_array().unwrap().len(), 2);
+        assert_eq!(get(&m, "plugins[1].name").as_str(), Some("docs"));
+
+        assert_eq!(
+            origin(&m, "server.host"),
+            ("app.local.toml".into(), (1, 16))
+        );
+        assert_eq!(origin(&m, "server.port"), ("/etc/app.toml".into(), (5, 8)));
+        assert_eq!(origin(&m, "server"), ("app.local.toml".into(), (1, 1)));
+        assert_eq!(
+            origin(&m, "plugins[0].level"),
+            ("/etc/app.toml".into(), (9, 9))
+        );
+        assert_eq!(origin(&m, ""), ("app.local.toml".into(), (1, 1)));
+        assert!(m.origin(&"missing".parse().unwrap()).is_none());
+        assert_eq!(m.origins().count(), 14);
+
+        #[derive(serde_derive::Deserialize)]
+        struct Config {
+            server: BTreeMap<Spanned<String>, Spanned<Value>>,
+        }
+        let config: Config = m.deserialize().unwrap();
+        for (key, value) in &config.server {
+            let path = format!("server.{}", key.get_ref()).parse().unwrap();
+            let span = m.origin(&path).unwrap().span();
+            assert_eq!((key.span(), value.span()), (span, span));
+        }
+        let port = Spanned::new(Span::new(0, 0), "port".to_string());
+        assert_eq!(config.server[&port].span(), Span::new(31, 35));
+
+        let m = merged(|merge| merge.arrays(ArrayStrategy::Append));
+        assert_eq!(get(&m, "tags"), &Value::Array(vec!["a".into(), "b".into()]));
+        assert_eq!(get(&m, "plugins").as_array().unwrap().len(), 4);
+        assert_eq!(origin(&m, "tags[0]"), ("defaults".into(), (3, 10)));
+        assert_eq!(origin(&m, "tags[1]"), ("/etc/app.toml".into(), (2, 10)));
+
+        let m = merged(|merge| {
+            merge.arrays(ArrayStrategy::Append);
+            let by_name = ArrayStrategy::MergeByKey("name".to_string());
+            merge.arrays_at("plugins".parse().unwrap(), by_name);
+        });
+        assert_eq!(get(&m, "tags").as_array().unwrap().len(), 2);
+        let names: Vec<_> = get(&m, "plugins")
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p.get("name").unwrap().as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["lint", "fmt", "docs"]);
+        assert_eq!(get(&m, "plugins[0].level").as_integer(), Some(2));
+        assert_eq!(
+            origin(&m, "plugins[0].level"),
+            ("/etc/app.toml".into(), (9, 9))
+        );
+        assert_eq!(origin(&m, "plugins[1].name"), ("defaults".into(), (14, 9)));
+
+        assert!(Merge::new().finish().unwrap().table().is_empty());
+    }
+
+    #[test]
+    fn merge_fail() {
+        let mut merge = Merge::new();
+        merge.add("a.toml", "x = 1").unwrap();
+        let err = merge.add("b.toml", "x = ").unwrap_err();
+        assert!(matches!(&err, Error::Source { name, .. } if name == "b.toml"));
+        assert_eq!(err.pos(), Some(4));
+
+        let mut merge = Merge::new();
+        merge.arrays(ArrayStrategy::MergeByKey("id".to_string()));
+        merge.add("a.toml", "items = [{ id = 1 }]").unwrap();
+        merge
+            .add("b.toml", "items = [{ id = 1 }, { name = 2 }]")
+            .unwrap();
+        let err = merge.finish().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "b.toml: item of `items` has no `id` to merge by at 21"
+        );
+
+        #[derive(Debug, serde_derive::Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            server: Server,
+        }
+        #[derive(Debug, serde_derive::Deserialize)]
+        #[allow(dead_code)]
+        struct Server {
+            host: String,
+            ports: Vec<u16>,
+        }
+        let deserialize = |over: &str| {
+            let mut merge = Merge::new();
+            merge
+                .add("a.toml", "[server]\nhost = 'a'\nports = [80]\n")
+                .unwrap();
+            merge.add("b.toml", over).unwrap();
+            let merged = merge.finish().unwrap();
+            merged.deserialize::<Config>().unwrap_err().to_string()
+        };
+        assert_eq!(
+            deserialize("server.ports = [80, 'x']"),
+            "b.toml: invalid type: string \"x\", expected u16 at 21"
+        );
+        assert_eq!(
+            deserialize("server.host = 1"),
+            "b.toml: invalid type: integer `1`, expected a string at 14"
+        );
+        assert_eq!(
+            deserialize("[server.ports]"),
+            "b.toml: invalid type: map, expected a sequence at 8"
+        );
+    }
+}
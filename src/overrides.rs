@@ -0,0 +1,163 @@
+//! Overriding configuration from the command line, as in `--set server.port=9000`.
+//!
+//! An override is a `key = value` pair written as in a document, on a line of its own: keys may
+//! be dotted and quoted, and values are TOML values, so strings must be quoted, as in
+//! `--set 'tags=["a", "b"]'` or `--set 'name="app"'`. Errors give positions in the override
+//! itself, which is named as their source.
+//!
+//! Each override is a layer of a `Merge`, named `override server.port=9000`, added in the order
+//! they were given, so later overrides win. Values that don't deserialize into their type are
+//! errors in the override that set them.
+//!
+//! ```
+//! use toml_rust::overrides::Overrides;
+//!
+//! let mut overrides = Overrides::new();
+//! overrides.add("server.port=9000").unwrap();
+//! overrides.add("'tags'=[\"a\", \"b\"]").unwrap();
+//! let text = "[server]\nport = 80\nhost = 'a'\n";
+//! let config: toml_rust::Value = overrides.from_str(text).unwrap();
+//! assert_eq!(
+//!     config.to_string(),
+//!     "{ server = { host = \"a\", port = 9000 }, tags = [\"a\", \"b\"] }"
+//! );
+//!
+//! let err = overrides.add("server.=1").unwrap_err();
+//! assert_eq!(err.to_string(), "override server.=1: Unexpected { pos: 7 }");
+//! ```
+
+use crate::error::Result;
+use crate::merge::Merge;
+use crate::parse::{self, TableNode};
+use serde::de::DeserializeOwned;
+
+/// A list of overrides, in the order they were given.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    overrides: Vec<(String, TableNode)>,
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses `text` and adds it after the overrides before it.
+    pub fn add(&mut self, text: impl Into<String>) -> Result<()> {
+        let text = text.into();
+        let root = parse::parse_keyval_node(&text).map_err(|e| e.in_source(name(&text)))?;
+        self.overrides.push((text, root));
+        Ok(())
+    }
+
+    /// Adds a layer to `merge` for each override.
+    pub fn load_into(&self, merge: &mut Merge) {
+        for (text, root) in &self.overrides {
+            merge.add_root(name(text), text.clone(), root.clone());
+        }
+    }
+
+    /// Deserializes an instance of `T` from a TOML document with the overrides merged over it.
+    pub fn from_str<T: DeserializeOwned>(&self, text: &str) -> Result<T> {
+        let mut merge = Merge::with_document(text)?;
+        self.load_into(&mut merge);
+        merge.finish()?.deserialize()
+    }
+}
+
+/// Returns the name of the layer for the override `text`.
+fn name(text: &str) -> String {
+    format!("override {}", text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    #[test]
+    fn overrides_success() {
+        let mut overrides = Overrides::new();
+        overrides.add("server.port=9000").unwrap();
+        overrides
+            .add(" server . \"my.host\" = { ip = '10.0.0.1' } ")
+            .unwrap();
+        overrides.add("tags=[\"a\",\n\"b\"]").unwrap();
+        overrides.add("server.port = 9001").unwrap();
+        overrides.add("when=2024-05-01T10:00:00Z").unwrap();
+        let mut merge = Merge::new();
+        merge.add("app.toml", "[server]\nport = 80\n").unwrap();
+        overrides.load_into(&mut merge);
+        let merged = merge.finish().unwrap();
+        let table = Value::Table(merged.table().clone());
+        assert_eq!(
+            table.to_string(),
+            "{ server = { \"my.host\" = { ip = \"10.0.0.1\" }, port = 9001 }, tags = [\"a\", \"b\"], when = 2024-05-01T10:00:00Z }"
+        );
+        let origin = merged
+            .origin(&"server.\"my.host\".ip".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            (origin.name(), origin.span().lo()),
+            ("override  server . \"my.host\" = { ip = '10.0.0.1' } ", 30)
+        );
+        let origin = merged.origin(&"tags[1]".parse().unwrap()).unwrap();
+        assert_eq!(origin.line_col(), (2, 2));
+
+        let table: Value = overrides.from_str("name = 'app'\n").unwrap();
+        assert_eq!(
+            table.get_path("server.port").unwrap().as_integer(),
+            Some(9001)
+        );
+        assert_eq!(table.get_path("name").unwrap().as_str(), Some("app"));
+    }
+
+    #[test]
+    fn overrides_fail() {
+        let fail = |text: &str| Overrides::new().add(text).unwrap_err().to_string();
+        assert_eq!(fail(""), "override : Unexpected { pos: 0 }");
+        assert_eq!(fail("port"), "override port: Unexpected { pos: 4 }");
+        assert_eq!(fail("name=app"), "override name=app: Unexpected { pos: 5 }");
+        assert_eq!(
+            fail("a=1 b=2"),
+            "override a=1 b=2: UnconsumedInput { pos: 4 }"
+        );
+        assert_eq!(
+            fail("a=1\nb=2"),
+            "override a=1\nb=2: UnconsumedInput { pos: 3 }"
+        );
+        assert_eq!(
+            fail("a.b=1 # x"),
+            "override a.b=1 # x: UnconsumedInput { pos: 6 }"
+        );
+        assert_eq!(
+            fail("a={b=1,b=2}"),
+            "override a={b=1,b=2}: duplicate key `b` at 7"
+        );
+
+        #[derive(Debug, serde_derive::Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            server: Server,
+        }
+        #[derive(Debug, serde_derive::Deserialize)]
+        #[allow(dead_code)]
+        struct Server {
+            port: u16,
+        }
+        let fail = |text: &str| {
+            let mut overrides = Overrides::new();
+            overrides.add(text).unwrap();
+            let err = overrides.from_str::<Config>("[server]\nport = 80\n");
+            err.unwrap_err().to_string()
+        };
+        assert_eq!(
+            fail("server.port='x'"),
+            "override server.port='x': invalid type: string \"x\", expected u16 at 13"
+        );
+        assert_eq!(
+            fail("server.port.x=1"),
+            "override server.port.x=1: invalid type: map, expected u16 at 7"
+        );
+    }
+}
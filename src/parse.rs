@@ -0,0 +1,759 @@
+use crate::datetime::Datetime;
+use crate::error::{Error, Result};
+use crate::lex::{self, Lex, Span, Sym, Symbol};
+use crate::value::{Table, Value};
+use std::collections::BTreeMap;
+
+/// How a table came into existence, which decides whether it may be extended later on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TableKind {
+    /// Created as the parent of a `[table]` header, or the document root.
+    Implicit,
+    /// Defined by a `[table]` or `[[table]]` header.
+    Header,
+    /// Created by a dotted key.
+    Dotted,
+    /// An inline table, which is closed once its `}` is seen.
+    Inline,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ArrayKind {
+    /// An array value written with `[ ... ]`.
+    Static,
+    /// An array of tables built up with `[[table]]` headers.
+    Tables,
+}
+
+/// A parsed value along with the span of source text it came from.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Node {
+    pub span: Span,
+    pub kind: NodeKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum NodeKind {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Datetime(Datetime),
+    Array(Vec<Node>, ArrayKind),
+    Table(TableNode),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TableNode {
+    pub kind: TableKind,
+    pub entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Entry {
+    pub key_span: Span,
+    pub node: Node,
+}
+
+impl TableNode {
+    pub fn new(kind: TableKind) -> Self {
+        TableNode {
+            kind,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn into_table(self) -> Table {
+        self.entries
+            .into_iter()
+            .map(|(k, e)| (k, e.node.into_value()))
+            .collect()
+    }
+}
+
+impl Node {
+    fn table(span: Span, kind: TableKind) -> Self {
+        Node {
+            span,
+            kind: NodeKind::Table(TableNode::new(kind)),
+        }
+    }
+
+    /// Converts `value` into a node, spanning it and everything in it with `span`.
+    pub fn from_value(value: Value, span: Span) -> Self {
+        let kind = match value {
+            Value::String(s) => NodeKind::String(s),
+            Value::Integer(i) => NodeKind::Integer(i),
+            Value::Float(f) => NodeKind::Float(f),
+            Value::Boolean(b) => NodeKind::Boolean(b),
+            Value::Datetime(d) => NodeKind::Datetime(d),
+            Value::Array(items) => NodeKind::Array(
+                items
+                    .into_iter()
+                    .map(|item| Node::from_value(item, span))
+                    .collect(),
+                ArrayKind::Static,
+            ),
+            Value::Table(table) => NodeKind::Table(TableNode {
+                kind: TableKind::Inline,
+                entries: table
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let node = Node::from_value(value, span);
+                        (
+                            key,
+                            Entry {
+                                key_span: span,
+                                node,
+                            },
+                        )
+                    })
+                    .collect(),
+            }),
+        };
+        Node { span, kind }
+    }
+
+    pub fn into_value(self) -> Value {
+        match self.kind {
+            NodeKind::String(s) => Value::String(s),
+            NodeKind::Integer(i) => Value::Integer(i),
+            NodeKind::Float(f) => Value::Float(f),
+            NodeKind::Boolean(b) => Value::Boolean(b),
+            NodeKind::Datetime(d) => Value::Datetime(d),
+            NodeKind::Array(items, _) => {
+                Value::Array(items.into_iter().map(Node::into_value).collect())
+            }
+            NodeKind::Table(t) => Value::Table(t.into_table()),
+        }
+    }
+}
+
+/// Builds a tree of values from the symbols produced by `Lex`, checking the rules about
+/// redefining keys and tables along the way.
+pub(crate) struct Parser<'a> {
+    text: &'a str,
+    symbols: &'a [Symbol],
+    index: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(text: &'a str, symbols: &'a [Symbol]) -> Self {
+        Self {
+            text,
+            symbols,
+            index: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn peek(&self) -> Symbol {
+        match self.symbols.get(self.index) {
+            Some(symbol) => *symbol,
+            None => Symbol::new(Sym::Eof, self.text.len()),
+        }
+    }
+
+    #[inline(always)]
+    fn next(&mut self) -> Symbol {
+        let symbol = self.peek();
+        self.index += 1;
+        symbol
+    }
+
+    #[cold]
+    fn err_unexpected<T>(&self, symbol: Symbol) -> Result<T> {
+        Err(Error::Lex(lex::Error::Unexpected {
+            pos: symbol.span().lo(),
+        }))
+    }
+
+    pub fn parse(&mut self) -> Result<TableNode> {
+        let mut root = TableNode::new(TableKind::Implicit);
+        let mut current = Vec::new();
+
+        loop {
+            match self.peek().sym() {
+                Sym::Eof => break,
+                Sym::Table | Sym::ArrayOfTable => current = self.parse_header(&mut root)?,
+                _ => {
+                    let symbol = self.peek();
+                    match lookup(&mut root, &current) {
+                        Some(table) => self.parse_keyval(table)?,
+                        None => return self.err_unexpected(symbol),
+                    }
+                }
+            }
+        }
+
+        Ok(root)
+    }
+
+    /// Parses a single key segment, either bare or quoted.
+    fn parse_key(&mut self) -> Result<(String, Span)> {
+        let symbol = self.next();
+        match symbol.sym() {
+            Sym::Key | Sym::String => Ok((parse_key(self.text, symbol)?, symbol.span())),
+            _ => self.err_unexpected(symbol),
+        }
+    }
+
+    /// Parses a `[table]` or `[[table]]` header, returning the path of the table that
+    /// following key/value pairs belong to.
+    fn parse_header(&mut self, root: &mut TableNode) -> Result<Vec<String>> {
+        let is_array = self.next().sym() == Sym::ArrayOfTable;
+
+        let mut keys = Vec::new();
+        while self.peek().sym() != Sym::TableEnd {
+            keys.push(self.parse_key()?);
+        }
+        let end = self.next();
+
+        match keys.split_last() {
+            Some((last, prefix)) => define_header(root, prefix, last, is_array)?,
+            None => return self.err_unexpected(end),
+        }
+
+        Ok(keys.into_iter().map(|(key, _)| key).collect())
+    }
+
+    fn parse_keyval(&mut self, table: &mut TableNode) -> Result<()> {
+        let mut keys = Vec::new();
+        loop {
+            if self.peek().sym() == Sym::Assign {
+                self.next();
+                break;
+            }
+            keys.push(self.parse_key()?);
+        }
+
+        let value = self.parse_value()?;
+
+        match keys.split_last() {
+            Some((last, prefix)) => insert_keyval(table, prefix, last, value),
+            None => self.err_unexpected(self.peek()),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Node> {
+        let symbol = self.next();
+        let span = symbol.span();
+        match symbol.sym() {
+            Sym::Array => {
+                let mut items = Vec::new();
+                while self.peek().sym() != Sym::ArrayEnd {
+                    items.push(self.parse_value()?);
+                }
+                let end = self.next();
+                Ok(Node {
+                    span: span.join(end.span()),
+                    kind: NodeKind::Array(items, ArrayKind::Static),
+                })
+            }
+            Sym::InlineTable => {
+                let mut table = TableNode::new(TableKind::Inline);
+                while self.peek().sym() != Sym::InlineTableEnd {
+                    self.parse_keyval(&mut table)?;
+                }
+                let end = self.next();
+                Ok(Node {
+                    span: span.join(end.span()),
+                    kind: NodeKind::Table(table),
+                })
+            }
+            _ => Ok(Node {
+                span,
+                kind: parse_scalar(self.text, symbol)?,
+            }),
+        }
+    }
+}
+
+/// Defines the table named by a `[table]` or `[[table]]` header, given as the segments before
+/// the last one and the last one.
+pub(crate) fn define_header(
+    root: &mut TableNode,
+    prefix: &[(String, Span)],
+    last: &(String, Span),
+    is_array: bool,
+) -> Result<()> {
+    let mut table = root;
+    for (key, span) in prefix {
+        table = descend_header(table, key, *span)?;
+    }
+
+    let (key, span) = last;
+    match table.entries.get_mut(key) {
+        None => {
+            let node = if is_array {
+                Node {
+                    span: *span,
+                    kind: NodeKind::Array(
+                        vec![Node::table(*span, TableKind::Header)],
+                        ArrayKind::Tables,
+                    ),
+                }
+            } else {
+                Node::table(*span, TableKind::Header)
+            };
+            table.entries.insert(
+                key.clone(),
+                Entry {
+                    key_span: *span,
+                    node,
+                },
+            );
+        }
+        Some(entry) => match &mut entry.node.kind {
+            NodeKind::Table(t) if !is_array && t.kind == TableKind::Implicit => {
+                t.kind = TableKind::Header;
+                entry.node.span = *span;
+            }
+            NodeKind::Array(items, ArrayKind::Tables) if is_array => {
+                items.push(Node::table(*span, TableKind::Header));
+            }
+            _ => return Err(duplicate_key(key, *span)),
+        },
+    }
+
+    Ok(())
+}
+
+/// Inserts the value of a `key = value` pair into `table`, given the segments of the key before
+/// the last one and the last one.
+pub(crate) fn insert_keyval(
+    table: &mut TableNode,
+    prefix: &[(String, Span)],
+    last: &(String, Span),
+    node: Node,
+) -> Result<()> {
+    let mut table = table;
+    for (key, span) in prefix {
+        table = descend_dotted(table, key, *span)?;
+    }
+
+    let (key, span) = last;
+    if table.entries.contains_key(key) {
+        return Err(duplicate_key(key, *span));
+    }
+    table.entries.insert(
+        key.clone(),
+        Entry {
+            key_span: *span,
+            node,
+        },
+    );
+
+    Ok(())
+}
+
+fn slice(text: &str, span: Span) -> Result<&str> {
+    match text.get(span.lo()..span.hi()) {
+        Some(s) => Ok(s),
+        None => Err(Error::Lex(lex::Error::Unexpected { pos: span.lo() })),
+    }
+}
+
+/// Decodes a key segment, either bare or quoted.
+pub(crate) fn parse_key(text: &str, symbol: Symbol) -> Result<String> {
+    match symbol.sym() {
+        Sym::String => decode_string(text, symbol.span()),
+        _ => slice(text, symbol.span()).map(str::to_string),
+    }
+}
+
+/// Decodes a string, integer, float, boolean or date-time symbol.
+pub(crate) fn parse_scalar(text: &str, symbol: Symbol) -> Result<NodeKind> {
+    let span = symbol.span();
+    Ok(match symbol.sym() {
+        Sym::String => NodeKind::String(decode_string(text, span)?),
+        Sym::Integer => NodeKind::Integer(parse_integer(slice(text, span)?, span)?),
+        Sym::Float => NodeKind::Float(parse_float(slice(text, span)?, span)?),
+        Sym::Bool => NodeKind::Boolean(slice(text, span)? == "true"),
+        Sym::DateTime => match slice(text, span)?.parse() {
+            Ok(datetime) => NodeKind::Datetime(datetime),
+            Err(_) => return Err(Error::InvalidDatetime { span }),
+        },
+        _ => return Err(Error::Lex(lex::Error::Unexpected { pos: span.lo() })),
+    })
+}
+
+/// Decodes the contents of a string symbol. The kind of string is recovered from the quotes
+/// preceding the span.
+fn decode_string(text: &str, span: Span) -> Result<String> {
+    let bytes = text.as_bytes();
+    let lo = span.lo();
+    let quote = match lo.checked_sub(1).and_then(|i| bytes.get(i)) {
+        Some(&quote @ (b'"' | b'\'')) => quote,
+        _ => return Err(Error::Lex(lex::Error::Unexpected { pos: lo })),
+    };
+    let multiline = lo >= 3 && bytes.get(lo - 3..lo) == Some(&[quote; 3]);
+
+    let mut content = slice(text, span)?;
+    let mut offset = lo;
+    if multiline {
+        // A newline immediately following the opening delimiter is trimmed.
+        for newline in ["\n", "\r\n"] {
+            if let Some(rest) = content.strip_prefix(newline) {
+                content = rest;
+                offset += newline.len();
+                break;
+            }
+        }
+    }
+
+    if quote == b'\'' {
+        check_control_characters(content, offset, multiline)?;
+        Ok(content.to_string())
+    } else {
+        unescape(content, offset, multiline)
+    }
+}
+
+/// Finds the table at `path`, where each segment refers to either a table or the last table in
+/// an array of tables. The path is created by a previous header, so this only returns `None` if
+/// the parser has gone wrong.
+pub(crate) fn lookup<'t>(root: &'t mut TableNode, path: &[String]) -> Option<&'t mut TableNode> {
+    let mut table = root;
+    for key in path {
+        table = match &mut table.entries.get_mut(key)?.node.kind {
+            NodeKind::Table(t) => t,
+            NodeKind::Array(items, ArrayKind::Tables) => match items.last_mut() {
+                Some(Node {
+                    kind: NodeKind::Table(t),
+                    ..
+                }) => t,
+                _ => return None,
+            },
+            _ => return None,
+        };
+    }
+    Some(table)
+}
+
+#[cold]
+fn duplicate_key(key: &str, span: Span) -> Error {
+    Error::DuplicateKey {
+        key: key.to_string(),
+        span,
+    }
+}
+
+/// Descends into a parent table named by a header, creating it if needed.
+fn descend_header<'t>(
+    table: &'t mut TableNode,
+    key: &str,
+    span: Span,
+) -> Result<&'t mut TableNode> {
+    let entry = table
+        .entries
+        .entry(key.to_string())
+        .or_insert_with(|| Entry {
+            key_span: span,
+            node: Node::table(span, TableKind::Implicit),
+        });
+    match &mut entry.node.kind {
+        NodeKind::Table(t) if t.kind != TableKind::Inline => Ok(t),
+        NodeKind::Array(items, ArrayKind::Tables) => match items.last_mut() {
+            Some(Node {
+                kind: NodeKind::Table(t),
+                ..
+            }) => Ok(t),
+            _ => Err(duplicate_key(key, span)),
+        },
+        _ => Err(duplicate_key(key, span)),
+    }
+}
+
+/// Descends into the table named by one segment of a dotted key, creating it if needed. Dotted
+/// keys may not reach into tables defined by headers or inline tables.
+fn descend_dotted<'t>(
+    table: &'t mut TableNode,
+    key: &str,
+    span: Span,
+) -> Result<&'t mut TableNode> {
+    let entry = table
+        .entries
+        .entry(key.to_string())
+        .or_insert_with(|| Entry {
+            key_span: span,
+            node: Node::table(span, TableKind::Dotted),
+        });
+    match &mut entry.node.kind {
+        NodeKind::Table(t) if matches!(t.kind, TableKind::Implicit | TableKind::Dotted) => {
+            t.kind = TableKind::Dotted;
+            Ok(t)
+        }
+        _ => Err(duplicate_key(key, span)),
+    }
+}
+
+fn check_control_characters(s: &str, offset: usize, multiline: bool) -> Result<()> {
+    let bytes = s.as_bytes();
+    for (i, &c) in bytes.iter().enumerate() {
+        let ok = match c {
+            b'\t' => true,
+            b'\n' => multiline,
+            b'\r' => multiline && bytes.get(i + 1) == Some(&b'\n'),
+            0x00..=0x1f | 0x7f => false,
+            _ => true,
+        };
+        if !ok {
+            return Err(Error::ControlCharacter { pos: offset + i });
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the escape sequences in the contents of a basic string.
+fn unescape(s: &str, offset: usize, multiline: bool) -> Result<String> {
+    check_control_characters(s, offset, multiline)?;
+
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while let Some(pos) = memchr::memchr(b'\\', &bytes[i..]) {
+        out.push_str(&s[i..i + pos]);
+        i += pos;
+        let escape_pos = offset + i;
+        let invalid = || Error::InvalidEscape { pos: escape_pos };
+
+        i += 1;
+        let c = match bytes.get(i) {
+            Some(c) => *c,
+            None => return Err(invalid()),
+        };
+        i += 1;
+        match c {
+            b'b' => out.push('\u{8}'),
+            b't' => out.push('\t'),
+            b'n' => out.push('\n'),
+            b'f' => out.push('\u{c}'),
+            b'r' => out.push('\r'),
+            b'"' => out.push('"'),
+            b'\\' => out.push('\\'),
+            b'u' | b'U' => {
+                let len = if c == b'u' { 4 } else { 8 };
+                let hex = s.get(i..i + len).ok_or_else(invalid)?;
+                if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(invalid());
+                }
+                let code = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+                out.push(char::from_u32(code).ok_or_else(invalid)?);
+                i += len;
+            }
+            b' ' | b'\t' | b'\r' | b'\n' if multiline => {
+                // A line ending backslash trims all whitespace up to the next non-whitespace
+                // character, but only when the whitespace contains a newline.
+                let rest = &bytes[i - 1..];
+                let len = rest
+                    .iter()
+                    .position(|c| !matches!(c, b' ' | b'\t' | b'\r' | b'\n'))
+                    .unwrap_or(rest.len());
+                if !rest[..len].contains(&b'\n') {
+                    return Err(invalid());
+                }
+                i += len - 1;
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    out.push_str(&s[i..]);
+    Ok(out)
+}
+
+fn parse_integer(s: &str, span: Span) -> Result<i64> {
+    let invalid = || Error::InvalidNumber { span };
+    let s = s.replace('_', "");
+    let (radix, digits) = match s.get(..2) {
+        Some("0x") => (16, &s[2..]),
+        Some("0o") => (8, &s[2..]),
+        Some("0b") => (2, &s[2..]),
+        _ => (10, &s[..]),
+    };
+    i64::from_str_radix(digits, radix).map_err(|_| invalid())
+}
+
+fn parse_float(s: &str, span: Span) -> Result<f64> {
+    let s = s.replace('_', "");
+    match s.trim_start_matches(['+', '-']) {
+        "inf" | "nan" => {
+            let value = if s.ends_with("inf") {
+                f64::INFINITY
+            } else {
+                f64::NAN
+            };
+            Ok(if s.starts_with('-') { -value } else { value })
+        }
+        _ => s.parse().map_err(|_| Error::InvalidNumber { span }),
+    }
+}
+
+/// Lexes and parses `text` into the spanned tree used internally by the deserializer.
+pub(crate) fn parse_nodes(text: &str) -> Result<TableNode> {
+    let mut lex = Lex::new(text);
+    lex.scan()?;
+    Parser::new(text, &lex.symbols).parse()
+}
+
+/// Lexes and parses `text` as a single value, with spans relative to `text`.
+pub(crate) fn parse_value_node(text: &str) -> Result<Node> {
+    let mut lex = Lex::new(text);
+    lex.scan_lone_value()?;
+    Parser::new(text, &lex.symbols).parse_value()
+}
+
+/// Lexes and parses `text` as a single `key = value` pair, into a table holding it.
+pub(crate) fn parse_keyval_node(text: &str) -> Result<TableNode> {
+    let mut lex = Lex::new(text);
+    lex.scan_lone_keyval()?;
+    let mut table = TableNode::new(TableKind::Implicit);
+    Parser::new(text, &lex.symbols).parse_keyval(&mut table)?;
+    Ok(table)
+}
+
+/// Parses a TOML document into a table of values.
+pub fn parse(text: &str) -> Result<Table> {
+    parse_nodes(text).map(TableNode::into_table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! fail {
+        ($text: expr) => {
+            if let Ok(table) = parse($text) {
+                panic!("expected {:?} to fail, got {:?}", $text, table);
+            }
+        };
+    }
+
+    fn get<'t>(table: &'t Table, path: &str) -> &'t Value {
+        let mut keys = path.split('.');
+        let mut value = &table[keys.next().unwrap()];
+        for key in keys {
+            value = match value {
+                Value::Array(items) => &items[key.parse::<usize>().unwrap()],
+                _ => value.get(key).unwrap(),
+            };
+        }
+        value
+    }
+
+    #[test]
+    fn scalars() {
+        let table = parse(
+            r#"
+            int = 1_000
+            neg = -17
+            hex = 0xdead_beef
+            oct = 0o755
+            bin = 0b1101
+            float = 6.626e-34
+            exp = 5e+22
+            pinf = +inf
+            nnan = -nan
+            yes = true
+            no = false
+            date = 1979-05-27
+            time = 07:32:00
+            odt = 1979-05-27 07:32:00Z
+            "#,
+        )
+        .unwrap();
+        assert_eq!(table["int"], Value::Integer(1000));
+        assert_eq!(table["neg"], Value::Integer(-17));
+        assert_eq!(table["hex"], Value::Integer(0xdeadbeef));
+        assert_eq!(table["oct"], Value::Integer(0o755));
+        assert_eq!(table["bin"], Value::Integer(0b1101));
+        assert_eq!(table["float"], Value::Float(6.626e-34));
+        assert_eq!(table["exp"], Value::Float(5e22));
+        assert_eq!(table["pinf"], Value::Float(f64::INFINITY));
+        assert!(table["nnan"].as_float().unwrap().is_nan());
+        assert_eq!(table["yes"], Value::Boolean(true));
+        assert_eq!(table["no"], Value::Boolean(false));
+        assert_eq!(table["date"].to_string(), "1979-05-27");
+        assert_eq!(table["time"].to_string(), "07:32:00");
+        assert_eq!(table["odt"].to_string(), "1979-05-27T07:32:00Z");
+
+        fail!("a = 9223372036854775808");
+        fail!("a = 0x");
+        fail!("a = 1__0");
+        fail!("a = 01");
+        fail!("a = +0x10");
+        fail!("a = 1.");
+        fail!("a = .1");
+        fail!("a = 1979-02-30");
+    }
+
+    #[test]
+    fn strings() {
+        let table = parse(
+            "basic = \"tab\\there \\u00e9 \\U0001F438\"\n\
+             literal = 'C:\\Users'\n\
+             ml = \"\"\"\nroses\\\n    are red\"\"\"\n\
+             mll = '''\nfirst\nsecond'''\n\
+             quotes = \"\"\"\"two\"\"\"\"\"\n",
+        )
+        .unwrap();
+        assert_eq!(table["basic"].as_str(), Some("tab\there é 🐸"));
+        assert_eq!(table["literal"].as_str(), Some("C:\\Users"));
+        assert_eq!(table["ml"].as_str(), Some("rosesare red"));
+        assert_eq!(table["mll"].as_str(), Some("first\nsecond"));
+        assert_eq!(table["quotes"].as_str(), Some("\"two\"\""));
+
+        fail!("a = \"\\q\"");
+        fail!("a = \"\\uD800\"");
+        fail!("a = \"tab\u{1}\"");
+        fail!("a = \"\"\"trailing \\  x\"\"\"");
+    }
+
+    #[test]
+    fn tables() {
+        let table = parse(
+            r#"
+            top = 1
+            [a.b]
+            c = { d.e = 1 }
+            [a]
+            f = [1, [2], { g = 3 }]
+            [[arr]]
+            x = 1
+            [arr.sub]
+            y = 2
+            [[arr]]
+            x = 3
+            [fruit]
+            apple.color = "red"
+            [fruit.apple.texture]
+            smooth = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(get(&table, "top"), &Value::Integer(1));
+        assert_eq!(get(&table, "a.b.c.d.e"), &Value::Integer(1));
+        assert_eq!(get(&table, "a.f.2.g"), &Value::Integer(3));
+        assert_eq!(get(&table, "arr.0.sub.y"), &Value::Integer(2));
+        assert_eq!(get(&table, "arr.1.x"), &Value::Integer(3));
+        assert_eq!(
+            get(&table, "fruit.apple.texture.smooth"),
+            &Value::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn tables_fail() {
+        fail!("a = 1\na = 2");
+        fail!("[a]\n[a]");
+        fail!("a.b = 1\n[a]");
+        fail!("[a]\nb = 1\n[a.b]");
+        fail!("a = {}\n[a.b]");
+        fail!("a = { b = 1 }\na.c = 2");
+        fail!("a = []\n[[a]]");
+        fail!("[[a]]\n[a]");
+        fail!("[a]\n[[a]]");
+        fail!("[a.b.c]\nz = 9\n[a]\nb.c.t = 1");
+        fail!("a = { b = 1, b = 2 }");
+    }
+}
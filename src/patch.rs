@@ -0,0 +1,517 @@
+//! Declarative edits to a `DocumentMut`, given as a list of operations.
+//!
+//! A patch is built in code or read from JSON shaped like a JSON Patch:
+//!
+//! ```json
+//! [
+//!   { "op": "set", "path": "server.port", "value": 8080 },
+//!   { "op": "set", "path": "release", "toml": "2024-05-01" },
+//!   { "op": "remove", "path": "debug" },
+//!   { "op": "rename", "path": "server.host", "to": "hostname" },
+//!   { "op": "append", "path": "plugins", "value": { "name": "docs" } },
+//!   { "op": "move", "from": "legacy.timeout", "path": "server.timeout" }
+//! ]
+//! ```
+//!
+//! Values are given as JSON with `value`, or as a TOML value with `toml` for types JSON doesn't
+//! have, such as date-times. Paths use the syntax of `Path`.
+//!
+//! Patches are applied all or nothing: if any operation fails, the document is left as it was
+//! and the error says which operation failed and why.
+//!
+//! ```
+//! use toml_rust::patch::Patch;
+//! use toml_rust::DocumentMut;
+//!
+//! let mut doc: DocumentMut = "[server]  # main\nport = 80\ndebug = true\n".parse().unwrap();
+//! let patch = Patch::new()
+//!     .set("server.port", 8080).unwrap()
+//!     .remove("server.debug").unwrap()
+//!     .set("server.tls.enabled", true).unwrap();
+//! patch.apply(&mut doc).unwrap();
+//! assert_eq!(
+//!     doc.to_string(),
+//!     "[server]  # main\nport = 8080\n\n[server.tls]\nenabled = true\n"
+//! );
+//! ```
+
+use crate::document::DocumentMut;
+use crate::error::{Error, Result};
+use crate::path::{Path, Segment};
+use crate::value::{Table, Value};
+use serde_json::Value as Json;
+use std::fmt;
+
+/// One operation of a patch.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    /// Sets the value at `path`, replacing any value that's there. Missing tables on the way are
+    /// created; a path ending in an index replaces an existing array item.
+    Set { path: Path, value: Value },
+    /// Removes the value at `path`, which must exist.
+    Remove { path: Path },
+    /// Renames the key at the end of `path` to `to`, keeping the value and its position.
+    Rename { path: Path, to: String },
+    /// Adds `value` to the end of the array at `path`.
+    Append { path: Path, value: Value },
+    /// Moves the value at `from` to `to`, as a remove followed by a set. A move to another key
+    /// of the same table is a rename.
+    Move { from: Path, to: Path },
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Set { path, .. } => write!(f, "set `{}`", path),
+            Op::Remove { path } => write!(f, "remove `{}`", path),
+            Op::Rename { path, to } => write!(f, "rename `{}` to `{}`", path, to),
+            Op::Append { path, .. } => write!(f, "append to `{}`", path),
+            Op::Move { from, to } => write!(f, "move `{}` to `{}`", from, to),
+        }
+    }
+}
+
+/// A list of operations to apply to a document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Patch {
+    ops: Vec<Op>,
+}
+
+impl Patch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    pub fn push(&mut self, op: Op) {
+        self.ops.push(op);
+    }
+
+    pub fn set(mut self, path: &str, value: impl Into<Value>) -> Result<Self> {
+        let (path, value) = (path.parse()?, value.into());
+        self.push(Op::Set { path, value });
+        Ok(self)
+    }
+
+    pub fn remove(mut self, path: &str) -> Result<Self> {
+        let path = path.parse()?;
+        self.push(Op::Remove { path });
+        Ok(self)
+    }
+
+    pub fn rename(mut self, path: &str, to: &str) -> Result<Self> {
+        let (path, to) = (path.parse()?, to.to_string());
+        self.push(Op::Rename { path, to });
+        Ok(self)
+    }
+
+    pub fn append(mut self, path: &str, value: impl Into<Value>) -> Result<Self> {
+        let (path, value) = (path.parse()?, value.into());
+        self.push(Op::Append { path, value });
+        Ok(self)
+    }
+
+    pub fn move_to(mut self, from: &str, to: &str) -> Result<Self> {
+        let (from, to) = (from.parse()?, to.parse()?);
+        self.push(Op::Move { from, to });
+        Ok(self)
+    }
+
+    /// Reads a patch from a JSON array of operations, as described in the module documentation.
+    pub fn from_json(text: &str) -> Result<Patch> {
+        let json: Json = serde_json::from_str(text).map_err(|e| Error::Message(e.to_string()))?;
+        let Json::Array(ops) = json else {
+            return Err(Error::Message("a patch must be an array".to_string()));
+        };
+        let ops = ops.iter().enumerate().map(|(i, op)| {
+            op_from_json(op).map_err(|e| Error::Message(format!("operation {}: {}", i, e)))
+        });
+        Ok(Patch {
+            ops: ops.collect::<Result<_>>()?,
+        })
+    }
+
+    /// Applies the operations in order. If one fails, `doc` is left unchanged and the error
+    /// names the operation by its index.
+    pub fn apply(&self, doc: &mut DocumentMut) -> Result<()> {
+        let mut edited = doc.clone();
+        for (i, op) in self.ops.iter().enumerate() {
+            apply(&mut edited, op)
+                .map_err(|e| Error::Message(format!("operation {} ({}): {}", i, op, e)))?;
+        }
+        *doc = edited;
+        Ok(())
+    }
+}
+
+fn op_from_json(op: &Json) -> Result<Op> {
+    let field = |name: &str| -> Result<&str> {
+        match op.get(name) {
+            Some(Json::String(s)) => Ok(s),
+            Some(_) => Err(Error::Message(format!("`{}` must be a string", name))),
+            None => Err(Error::Message(format!("missing `{}`", name))),
+        }
+    };
+    let path = |name: &str| -> Result<Path> {
+        let text = field(name)?;
+        Path::parse(text).map_err(|e| Error::Message(format!("invalid path `{}`: {}", text, e)))
+    };
+    let value = || -> Result<Value> {
+        match (op.get("value"), op.get("toml")) {
+            (Some(value), None) => value_from_json(value),
+            (None, Some(_)) => value_from_toml(field("toml")?),
+            (Some(_), Some(_)) => Err(Error::Message("both `value` and `toml`".to_string())),
+            (None, None) => Err(Error::Message("missing `value`".to_string())),
+        }
+    };
+    Ok(match field("op")? {
+        "set" => Op::Set {
+            path: path("path")?,
+            value: value()?,
+        },
+        "remove" => Op::Remove {
+            path: path("path")?,
+        },
+        "rename" => Op::Rename {
+            path: path("path")?,
+            to: field("to")?.to_string(),
+        },
+        "append" => Op::Append {
+            path: path("path")?,
+            value: value()?,
+        },
+        "move" => Op::Move {
+            from: path("from")?,
+            to: path("path")?,
+        },
+        other => return Err(Error::Message(format!("unknown operation `{}`", other))),
+    })
+}
+
+fn value_from_json(json: &Json) -> Result<Value> {
+    Ok(match json {
+        Json::Null => return Err(Error::Message("TOML has no null".to_string())),
+        Json::Bool(b) => Value::Boolean(*b),
+        Json::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => Value::Integer(i),
+            (None, Some(_)) if n.is_u64() => {
+                return Err(Error::Message(format!("integer {} out of range", n)))
+            }
+            (None, Some(f)) => Value::Float(f),
+            (None, None) => return Err(Error::Message(format!("invalid number {}", n))),
+        },
+        Json::String(s) => Value::String(s.clone()),
+        Json::Array(items) => {
+            Value::Array(items.iter().map(value_from_json).collect::<Result<_>>()?)
+        }
+        Json::Object(entries) => Value::Table(
+            entries
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), value_from_json(v)?)))
+                .collect::<Result<_>>()?,
+        ),
+    })
+}
+
+/// Parses a single TOML value, as written after `key =`.
+fn value_from_toml(text: &str) -> Result<Value> {
+    let mut table = crate::parse(&format!("value = {}", text))
+        .map_err(|e| Error::Message(format!("invalid TOML value `{}`: {}", text, e)))?;
+    match (table.remove("value"), table.is_empty()) {
+        (Some(value), true) => Ok(value),
+        _ => Err(Error::Message(format!("`{}` is not a single value", text))),
+    }
+}
+
+/// Looks up `path`, saying which part of it is missing or of the wrong type if it isn't there.
+fn lookup<'a>(doc: &'a DocumentMut, path: &Path) -> Result<&'a Value> {
+    let mut prefix = Path::new();
+    let mut current: Option<&Value> = None;
+    for segment in path {
+        let next = match (segment, current) {
+            (Segment::Key(key), None) => doc.as_table().get(key),
+            (Segment::Key(key), Some(Value::Table(table))) => table.get(key),
+            (Segment::Index(i), Some(Value::Array(items))) => items.get(*i),
+            (Segment::Key(_), Some(value)) => return Err(wrong_type(&prefix, value, "a table")),
+            (Segment::Index(_), Some(value)) => return Err(wrong_type(&prefix, value, "an array")),
+            (Segment::Index(_), None) => {
+                return Err(Error::Message(
+                    "the root is a table, not an array".to_string(),
+                ))
+            }
+        };
+        prefix.push(segment.clone());
+        match next {
+            Some(value) => current = Some(value),
+            None => return Err(Error::Message(format!("`{}` does not exist", prefix))),
+        }
+    }
+    current.ok_or_else(|| Error::Message("the root can't be edited".to_string()))
+}
+
+fn wrong_type(path: &Path, value: &Value, expected: &str) -> Error {
+    Error::Message(format!(
+        "`{}` is {} {}, not {}",
+        path,
+        if value.type_str().starts_with(['a', 'i']) {
+            "an"
+        } else {
+            "a"
+        },
+        value.type_str(),
+        expected
+    ))
+}
+
+/// Splits `path` into its parent and last segment.
+fn split(path: &Path) -> Result<(Path, &Segment)> {
+    match (path.parent(), path.segments().last()) {
+        (Some(parent), Some(last)) => Ok((parent, last)),
+        _ => Err(Error::Message("the root can't be edited".to_string())),
+    }
+}
+
+fn apply(doc: &mut DocumentMut, op: &Op) -> Result<()> {
+    match op {
+        Op::Set { path, value } => set(doc, path, value.clone()),
+        Op::Remove { path } => {
+            lookup(doc, path)?;
+            let (parent, last) = split(path)?;
+            match last {
+                Segment::Key(key) => table(doc, &parent)?.remove(key).map(drop),
+                Segment::Index(i) => array(doc, &parent)?.remove(*i).map(drop),
+            }
+        }
+        Op::Rename { path, to } => {
+            lookup(doc, path)?;
+            match split(path)? {
+                (parent, Segment::Key(key)) => table(doc, &parent)?.rename(key, to),
+                (_, Segment::Index(_)) => Err(Error::Message(
+                    "only keys can be renamed, not array items".to_string(),
+                )),
+            }
+        }
+        Op::Append { path, value } => array(doc, path)?.push(value.clone()),
+        Op::Move { from, to } => {
+            let value = lookup(doc, from)?.clone();
+            if to.starts_with(from) {
+                return Err(Error::Message("can't move a value into itself".to_string()));
+            }
+            match (split(from)?, split(to)?) {
+                ((from_parent, Segment::Key(key)), (to_parent, Segment::Key(new_key)))
+                    if from_parent == to_parent && lookup(doc, to).is_err() =>
+                {
+                    table(doc, &from_parent)?.rename(key, new_key)
+                }
+                _ => {
+                    apply(doc, &Op::Remove { path: from.clone() })?;
+                    set(doc, to, value)
+                }
+            }
+        }
+    }
+}
+
+fn table<'a>(doc: &'a mut DocumentMut, path: &Path) -> Result<crate::TableMut<'a>> {
+    if !path.is_empty() {
+        let value = lookup(doc, path)?;
+        if !value.is_table() {
+            return Err(wrong_type(path, value, "a table"));
+        }
+    }
+    Ok(doc.table_at(path).expect("checked to be a table"))
+}
+
+fn array<'a>(doc: &'a mut DocumentMut, path: &Path) -> Result<crate::ArrayMut<'a>> {
+    let value = lookup(doc, path)?;
+    if !value.is_array() {
+        return Err(wrong_type(path, value, "an array"));
+    }
+    Ok(doc.array_at(path).expect("checked to be an array"))
+}
+
+fn set(doc: &mut DocumentMut, path: &Path, value: Value) -> Result<()> {
+    let (parent, last) = split(path)?;
+    let key = match last {
+        Segment::Index(i) => {
+            let mut items = array(doc, &parent)?;
+            if *i >= items.len() {
+                return Err(Error::Message(format!(
+                    "index {} out of range for `{}` of length {}",
+                    i,
+                    parent,
+                    items.len()
+                )));
+            }
+            return items.replace(*i, value).map(drop);
+        }
+        Segment::Key(key) => key,
+    };
+
+    // Find the deepest table on the way that exists, and wrap the value in the tables missing
+    // below it.
+    let mut existing = parent.clone();
+    let mut missing = vec![key.clone()];
+    while !existing.is_empty() && lookup(doc, &existing).is_err() {
+        match existing.pop() {
+            Some(Segment::Key(key)) => missing.push(key),
+            // Array items aren't created, so report what's missing on the way to one.
+            _ => return lookup(doc, &parent).map(drop),
+        }
+    }
+    let first = missing.pop().expect("the key is missing");
+    let value = missing.into_iter().fold(value, |value, key| {
+        let mut table = Table::new();
+        table.insert(key, value);
+        Value::Table(table)
+    });
+    table(doc, &existing)?.insert(&first, value).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+# Service
+name = \"api\" # the name
+
+[server] # main server
+host = \"localhost\"
+port = 80
+
+[[plugins]]
+name = \"lint\"
+";
+
+    fn patched(patch: &Patch) -> Result<String> {
+        let mut doc: DocumentMut = TEXT.parse().unwrap();
+        patch.apply(&mut doc)?;
+        Ok(doc.to_string())
+    }
+
+    #[test]
+    fn patch_success() {
+        let patch = Patch::new()
+            .set("server.port", 8080)
+            .and_then(|p| p.rename("server.host", "hostname"))
+            .and_then(|p| p.remove("name"))
+            .and_then(|p| p.append("plugins", Table::new()))
+            .and_then(|p| p.set("plugins[1].name", "fmt"))
+            .and_then(|p| p.move_to("server.port", "server.listen"))
+            .unwrap();
+        assert_eq!(
+            patched(&patch).unwrap(),
+            "# Service\n\n[server] # main server\nhostname = \"localhost\"\nlisten = 8080\n\n\
+             [[plugins]]\nname = \"lint\"\n\n[[plugins]]\nname = \"fmt\"\n"
+        );
+
+        let patch = Patch::new()
+            .move_to("server.host", "host")
+            .and_then(|p| p.set("a.b.c", 1))
+            .and_then(|p| p.set("server.tls.on", true))
+            .unwrap();
+        assert_eq!(
+            patched(&patch).unwrap(),
+            "# Service\nname = \"api\" # the name\nhost = \"localhost\"\n\n[server] # main server\n\
+             port = 80\n\n[server.tls]\non = true\n\n[[plugins]]\nname = \"lint\"\n\n[a.b]\nc = 1\n"
+        );
+
+        let json = r#"[
+            { "op": "set", "path": "server.port", "value": 81 },
+            { "op": "set", "path": "released", "toml": "2024-05-01" },
+            { "op": "set", "path": "server.\"my key\"", "value": [1, 2.5, { "x": "y" }] },
+            { "op": "rename", "path": "name", "to": "service" },
+            { "op": "move", "from": "plugins[0].name", "path": "plugins[0].id" },
+            { "op": "remove", "path": "server.host" }
+        ]"#;
+        let patch = Patch::from_json(json).unwrap();
+        assert_eq!(patch.ops().len(), 6);
+        assert_eq!(
+            patched(&patch).unwrap(),
+            "# Service\nservice = \"api\" # the name\nreleased = 2024-05-01\n\n\
+             [server] # main server\nport = 81\n\"my key\" = [1, 2.5, { x = \"y\" }]\n\n\
+             [[plugins]]\nid = \"lint\"\n"
+        );
+    }
+
+    #[test]
+    fn patch_fail() {
+        let fail = |patch: Result<Patch>| patched(&patch.unwrap()).unwrap_err().to_string();
+        assert_eq!(
+            fail(
+                Patch::new()
+                    .set("server.port", 1)
+                    .and_then(|p| p.remove("missing"))
+            ),
+            "operation 1 (remove `missing`): `missing` does not exist"
+        );
+        assert_eq!(
+            fail(Patch::new().set("name.first", "a")),
+            "operation 0 (set `name.first`): `name` is a string, not a table"
+        );
+        assert_eq!(
+            fail(Patch::new().append("server", 1)),
+            "operation 0 (append to `server`): `server` is a table, not an array"
+        );
+        assert_eq!(
+            fail(Patch::new().set("plugins[3]", 1)),
+            "operation 0 (set `plugins[3]`): index 3 out of range for `plugins` of length 1"
+        );
+        assert_eq!(
+            fail(Patch::new().set("plugins[0].x[1]", 1)),
+            "operation 0 (set `plugins[0].x[1]`): `plugins[0].x` does not exist"
+        );
+        assert_eq!(
+            fail(Patch::new().rename("name", "server")),
+            "operation 0 (rename `name` to `server`): key `server` already exists"
+        );
+        assert_eq!(
+            fail(Patch::new().move_to("server", "server.inner")),
+            "operation 0 (move `server` to `server.inner`): can't move a value into itself"
+        );
+        assert_eq!(
+            fail(Patch::new().remove("")),
+            "operation 0 (remove ``): the root can't be edited"
+        );
+
+        // Nothing is applied when a later operation fails.
+        let mut doc: DocumentMut = TEXT.parse().unwrap();
+        let patch = Patch::new()
+            .set("name", "x")
+            .and_then(|p| p.remove("nope"))
+            .unwrap();
+        assert!(patch.apply(&mut doc).is_err());
+        assert_eq!(doc.as_str(), TEXT);
+
+        for (json, message) in [
+            ("{}", "a patch must be an array"),
+            (r#"[{ "path": "a" }]"#, "operation 0: missing `op`"),
+            (
+                r#"[{ "op": "copy", "path": "a" }]"#,
+                "operation 0: unknown operation `copy`",
+            ),
+            (
+                r#"[{ "op": "set", "path": "a" }]"#,
+                "operation 0: missing `value`",
+            ),
+            (
+                r#"[{ "op": "set", "path": "a", "value": null }]"#,
+                "operation 0: TOML has no null",
+            ),
+            (
+                r#"[{ "op": "set", "path": "a", "toml": "1\nb = 2" }]"#,
+                "operation 0: `1\nb = 2` is not a single value",
+            ),
+            (
+                r#"[{ "op": "remove", "path": "a." }]"#,
+                "operation 0: invalid path `a.`: Unexpected { pos: 2 }",
+            ),
+        ] {
+            assert_eq!(Patch::from_json(json).unwrap_err().to_string(), message);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! Paths that name a value inside a document, such as `servers."my.host".ip` or
+//! `products[2].name`.
+
+use crate::error::{Error, Result};
+use crate::lex::{Lex, Sym};
+use crate::parse;
+use crate::value::{Table, Value};
+use std::fmt;
+use std::str::FromStr;
+
+/// One step of a `Path`: a key in a table or an index into an array.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl From<String> for Segment {
+    fn from(key: String) -> Self {
+        Segment::Key(key)
+    }
+}
+
+impl From<&str> for Segment {
+    fn from(key: &str) -> Self {
+        Segment::Key(key.to_string())
+    }
+}
+
+impl From<usize> for Segment {
+    fn from(index: usize) -> Self {
+        Segment::Index(index)
+    }
+}
+
+/// A path to a value, made of keys and array indices.
+///
+/// Keys are written exactly like the segments of a dotted key, so they may be bare or quoted and
+/// may have whitespace around the dots. Indices follow a key or another index in brackets. The
+/// empty path names the root.
+///
+/// ```
+/// use toml_rust::{Path, Segment};
+///
+/// let path: Path = r#"servers."my.host".ports[1]"#.parse().unwrap();
+/// assert_eq!(path.segments()[1], Segment::Key("my.host".to_string()));
+/// assert_eq!(path.to_string(), r#"servers."my.host".ports[1]"#);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a path. Errors carry the byte offset in `text` where it stopped making sense.
+    pub fn parse(text: &str) -> Result<Path> {
+        Path::parse_with(text, false)
+    }
+
+    /// Parses a path, where unquoted segments of digits are indices if `digit_indices` is set,
+    /// as in `servers.0.host`.
+    pub(crate) fn parse_with(text: &str, digit_indices: bool) -> Result<Path> {
+        let mut lex = Lex::new(text);
+        lex.scan_path()?;
+        let mut segments = Vec::new();
+        for symbol in lex.symbols {
+            let index = || {
+                text[symbol.span().lo()..symbol.span().hi()]
+                    .parse()
+                    .map_err(|_| Error::InvalidNumber {
+                        span: symbol.span(),
+                    })
+            };
+            segments.push(match symbol.sym() {
+                Sym::Key if digit_indices && index().is_ok() => Segment::Index(index()?),
+                Sym::Key | Sym::String => Segment::Key(parse::parse_key(text, symbol)?),
+                Sym::Integer => Segment::Index(index()?),
+                _ => break,
+            });
+        }
+        Ok(Path { segments })
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn push(&mut self, segment: impl Into<Segment>) {
+        self.segments.push(segment.into());
+    }
+
+    pub fn pop(&mut self) -> Option<Segment> {
+        self.segments.pop()
+    }
+
+    /// Returns the path with `segment` added to the end.
+    pub fn join(&self, segment: impl Into<Segment>) -> Path {
+        let mut path = self.clone();
+        path.push(segment);
+        path
+    }
+
+    /// Returns the path without its last segment, or `None` for the empty path.
+    pub fn parent(&self) -> Option<Path> {
+        let (_, parent) = self.segments.split_last()?;
+        Some(Path {
+            segments: parent.to_vec(),
+        })
+    }
+
+    /// Returns the path without its indices, so `products[2].name` gives `products.name`.
+    pub fn keys(&self) -> Path {
+        let keys = self
+            .segments
+            .iter()
+            .filter(|s| matches!(s, Segment::Key(_)));
+        keys.cloned().collect()
+    }
+
+    /// Returns true if `self` is `other` or lies below it.
+    pub fn starts_with(&self, other: &Path) -> bool {
+        self.segments.starts_with(&other.segments)
+    }
+
+    /// Looks up the value this path names below `value`.
+    pub fn get<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        get(&self.segments, value)
+    }
+
+    pub fn get_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        get_mut(&self.segments, value)
+    }
+
+    /// Looks up the value this path names below the table `table`, such as a parsed document.
+    /// The empty path names no value here.
+    pub fn get_in<'v>(&self, table: &'v Table) -> Option<&'v Value> {
+        get_in(&self.segments, table)
+    }
+
+    pub fn get_in_mut<'v>(&self, table: &'v mut Table) -> Option<&'v mut Value> {
+        match self.segments.split_first()? {
+            (Segment::Key(key), rest) => get_mut(rest, table.get_mut(key)?),
+            (Segment::Index(_), _) => None,
+        }
+    }
+}
+
+/// Settings for values whatever the indices on the way to them, so one for `products.tags` is
+/// for the `tags` of every item of `products`. Paths are looked up by their `keys`, and the
+/// setting added last wins.
+#[derive(Clone, Debug)]
+pub(crate) struct ByKeys<T>(Vec<(Path, T)>);
+
+impl<T> Default for ByKeys<T> {
+    fn default() -> Self {
+        ByKeys(Vec::new())
+    }
+}
+
+impl<T> ByKeys<T> {
+    pub fn insert(&mut self, path: &Path, value: T) {
+        self.0.push((path.keys(), value));
+    }
+
+    /// Returns the setting for `keys`, a path without indices.
+    pub fn get(&self, keys: &Path) -> Option<&T> {
+        let mut settings = self.0.iter().rev();
+        settings
+            .find(|(path, _)| path == keys)
+            .map(|(_, value)| value)
+    }
+}
+
+pub(crate) fn get_in<'v>(segments: &[Segment], table: &'v Table) -> Option<&'v Value> {
+    match segments.split_first()? {
+        (Segment::Key(key), rest) => get(rest, table.get(key)?),
+        (Segment::Index(_), _) => None,
+    }
+}
+
+fn get<'v>(segments: &[Segment], value: &'v Value) -> Option<&'v Value> {
+    segments
+        .iter()
+        .try_fold(value, |value, segment| match segment {
+            Segment::Key(key) => value.as_table()?.get(key),
+            Segment::Index(i) => value.as_array()?.get(*i),
+        })
+}
+
+fn get_mut<'v>(segments: &[Segment], value: &'v mut Value) -> Option<&'v mut Value> {
+    segments
+        .iter()
+        .try_fold(value, |value, segment| match segment {
+            Segment::Key(key) => value.as_table_mut()?.get_mut(key),
+            Segment::Index(i) => value.as_array_mut()?.get_mut(*i),
+        })
+}
+
+impl FromStr for Path {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Path> {
+        Path::parse(s)
+    }
+}
+
+impl From<Vec<Segment>> for Path {
+    fn from(segments: Vec<Segment>) -> Self {
+        Path { segments }
+    }
+}
+
+impl<S: Into<Segment>> FromIterator<S> for Path {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Path {
+            segments: iter.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Path {
+    type Item = &'a Segment;
+    type IntoIter = std::slice::Iter<'a, Segment>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.segments.iter()
+    }
+}
+
+impl fmt::Display for Path {
+    /// Writes the path so that it parses back to itself, quoting keys only where needed.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = String::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Key(key) => {
+                    if i > 0 {
+                        s.push('.');
+                    }
+                    crate::ser::write_key(&mut s, key);
+                }
+                Segment::Index(index) => {
+                    s.push('[');
+                    s.push_str(&index.to_string());
+                    s.push(']');
+                }
+            }
+        }
+        f.write_str(&s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(path: &str) -> Vec<Segment> {
+        Path::parse(path).unwrap().segments
+    }
+
+    #[test]
+    fn parse_success() {
+        use Segment::{Index, Key};
+        let k = |s: &str| Key(s.to_string());
+
+        assert_eq!(keys(""), vec![]);
+        assert_eq!(keys("a"), vec![k("a")]);
+        assert_eq!(
+            keys("servers.alpha.ip"),
+            vec![k("servers"), k("alpha"), k("ip")]
+        );
+        assert_eq!(
+            keys(r#"servers."my.host".ip"#),
+            vec![k("servers"), k("my.host"), k("ip")]
+        );
+        assert_eq!(
+            keys(r#" a . 'b\c' . "\u00e9" "#),
+            vec![k("a"), k(r"b\c"), k("é")]
+        );
+        assert_eq!(keys(r#""""#), vec![k("")]);
+        assert_eq!(keys("1.2"), vec![k("1"), k("2")]);
+        assert_eq!(
+            keys("products[2].name"),
+            vec![k("products"), Index(2), k("name")]
+        );
+        assert_eq!(keys("a[ 0 ][1]"), vec![k("a"), Index(0), Index(1)]);
+        assert_eq!(keys("[3]"), vec![Index(3)]);
+
+        for text in [
+            "",
+            "a",
+            r#"servers."my.host".ip"#,
+            "a[0][1].b",
+            "[3]",
+            r#""""#,
+        ] {
+            assert_eq!(Path::parse(text).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn parse_fail() {
+        for (text, pos) in [
+            ("a.", 2),
+            (".a", 0),
+            ("a..b", 2),
+            ("a b", 2),
+            ("a.[0]", 2),
+            ("a[]", 2),
+            ("a[-1]", 2),
+            ("a[0", 3),
+            ("a=1", 1),
+            ("'''a'''", 0),
+            ("\"a", 2),
+            ("a.\"\\q\"", 3),
+        ] {
+            let err = Path::parse(text).unwrap_err();
+            assert_eq!(err.pos(), Some(pos), "{:?}: {}", text, err);
+        }
+        assert!(Path::parse("a[99999999999999999999999]").is_err());
+    }
+
+    #[test]
+    fn get_success() {
+        let table = crate::parse(
+            r#"
+            title = "x"
+            [servers.alpha]
+            ip = "10.0.0.1"
+            [servers."my.host"]
+            ip = "10.0.0.2"
+            [[products]]
+            name = "a"
+            [[products]]
+            name = "b"
+            tags = [[1, 2], [3]]
+            "#,
+        )
+        .unwrap();
+        let get = |path: &str| Path::parse(path).unwrap().get_in(&table).cloned();
+
+        assert_eq!(get("title"), Some(Value::from("x")));
+        assert_eq!(get("servers.alpha.ip"), Some(Value::from("10.0.0.1")));
+        assert_eq!(
+            get(r#"servers."my.host".ip"#),
+            Some(Value::from("10.0.0.2"))
+        );
+        assert_eq!(get("products[1].name"), Some(Value::from("b")));
+        assert_eq!(get("products[1].tags[0][1]"), Some(Value::from(2)));
+        assert_eq!(get("products[2].name"), None);
+        assert_eq!(get("title.x"), None);
+        assert_eq!(get("title[0]"), None);
+        assert_eq!(get(""), None);
+
+        let mut value = Value::Table(table);
+        assert_eq!(
+            value.get_path("servers.alpha.ip"),
+            Some(&Value::from("10.0.0.1"))
+        );
+        assert_eq!(value.get_path("").map(Value::is_table), Some(true));
+        assert_eq!(value.get_path("servers.alpha."), None);
+        *value.get_path_mut("products[0].name").unwrap() = Value::from("c");
+        assert_eq!(value.get_path("products[0].name"), Some(&Value::from("c")));
+
+        let path: Path = ["products"].into_iter().collect();
+        let path = path.join(0).join("name");
+        assert_eq!(path.to_string(), "products[0].name");
+        assert_eq!(path.parent().unwrap().to_string(), "products[0]");
+        assert_eq!(path.keys().to_string(), "products.name");
+        assert!(path.starts_with(&"products".parse().unwrap()));
+        assert_eq!(path.get(&value), Some(&Value::from("c")));
+    }
+}
@@ -0,0 +1,572 @@
+//! Queries that select several values from a document at once, along with where they were
+//! written.
+//!
+//! A query extends the syntax of a `Path`:
+//!
+//! - `servers.alpha.ip` and `products[2]` select a key or an index, as in a path. Negative
+//!   indices count from the end of an array.
+//! - `*` or `[*]` selects every value in a table or array: `servers.*.ip`.
+//! - `..` selects a value and everything below it, so `..ip` finds every key `ip` in the
+//!   document and `servers..ip` every one below `servers`.
+//! - `[start:end]` selects a range of array items. Either end may be left out or negative.
+//! - `[?key.path op literal]` selects the items of an array, or the values of a table, whose
+//!   `key.path` compares to the TOML literal: `plugins[?enabled == true].name`. The operators
+//!   are `==`, `!=`, `<`, `<=`, `>` and `>=`; without one, an item is selected if it has the key.
+//!   Items without the key are never selected, not even by `!=`. `@` refers to the item itself,
+//!   as in `ports[?@ >= 8000]`.
+//!
+//! Keys and literals are written exactly as in TOML, so `servers."my.host"` and
+//! `[?date < 2024-01-01]` work as expected.
+//!
+//! ```
+//! use toml_rust::query::Query;
+//!
+//! let text = r#"
+//! [[plugins]]
+//! name = "lint"
+//! enabled = true
+//!
+//! [[plugins]]
+//! name = "fmt"
+//! enabled = false
+//! "#;
+//! let query: Query = "plugins[?enabled == true].name".parse().unwrap();
+//! let matches = query.run(text).unwrap();
+//! assert_eq!(matches.len(), 1);
+//! assert_eq!(matches[0].path().to_string(), "plugins[0].name");
+//! assert_eq!(matches[0].value().as_str(), Some("lint"));
+//! assert_eq!(matches[0].line_col(text), (3, 9));
+//! ```
+
+use crate::error::{Error, Result};
+use crate::lex::{self, Lex, Span};
+use crate::parse::{self, Node, NodeKind};
+use crate::path::{Path, Segment};
+use crate::value::Value;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Key(String),
+    Index(i64),
+    Slice(Option<i64>, Option<i64>),
+    /// Every value of a table or array.
+    All,
+    /// A value and everything below it.
+    Descend,
+    Filter(Filter),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Filter {
+    /// Where to look below each item; empty for the item itself.
+    path: Path,
+    test: Option<(Op, Value)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A parsed query. See the module documentation for the syntax.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+/// A value selected by a query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    path: Path,
+    span: Span,
+    value: Value,
+}
+
+impl Match {
+    /// Returns the concrete path to the value, with every wildcard resolved.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the span of the value in the document. Tables defined by a `[header]` are spanned
+    /// by the last key of the header, and the document itself by the whole text.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Returns the 1-based line and column of the start of the value in `text`, which must be
+    /// the document the query was run on.
+    pub fn line_col(&self, text: &str) -> (usize, usize) {
+        self.span.line_col(text)
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+}
+
+impl Query {
+    /// Parses a query. Errors carry the byte offset in `text` where it stopped making sense.
+    pub fn parse(text: &str) -> Result<Query> {
+        QueryParser {
+            text,
+            lex: Lex::new(text),
+            pos: 0,
+        }
+        .parse()
+    }
+
+    /// Parses `document` and returns the values the query selects, in document order for
+    /// arrays and key order for tables.
+    pub fn run(&self, document: &str) -> Result<Vec<Match>> {
+        let root = Node {
+            span: Span::new(0, document.len()),
+            kind: NodeKind::Table(parse::parse_nodes(document)?),
+        };
+        let mut current = vec![(Path::new(), &root)];
+        for step in &self.steps {
+            let mut next = Vec::new();
+            for (path, node) in current {
+                apply(step, path, node, &mut next);
+            }
+            current = next;
+        }
+        Ok(current
+            .into_iter()
+            .map(|(path, node)| Match {
+                path,
+                span: node.span,
+                value: node.clone().into_value(),
+            })
+            .collect())
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Query> {
+        Query::parse(s)
+    }
+}
+
+/// The values of a table or the items of an array, with their paths.
+fn children<'a>(path: &Path, node: &'a Node) -> Vec<(Path, &'a Node)> {
+    match &node.kind {
+        NodeKind::Table(table) => table
+            .entries
+            .iter()
+            .map(|(key, entry)| (path.join(key.as_str()), &entry.node))
+            .collect(),
+        NodeKind::Array(items, _) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (path.join(i), item))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn descend<'a>(path: Path, node: &'a Node, out: &mut Vec<(Path, &'a Node)>) {
+    let below = children(&path, node);
+    out.push((path, node));
+    for (path, node) in below {
+        descend(path, node, out);
+    }
+}
+
+/// Resolves a possibly negative slice bound against an array of length `len`, clamping it to
+/// `0..=len`.
+fn resolve(index: i64, len: usize) -> usize {
+    if index < 0 {
+        len.saturating_sub(index.unsigned_abs() as usize)
+    } else {
+        (index as usize).min(len)
+    }
+}
+
+fn apply<'a>(step: &Step, path: Path, node: &'a Node, out: &mut Vec<(Path, &'a Node)>) {
+    match (step, &node.kind) {
+        (Step::Key(key), NodeKind::Table(table)) => {
+            if let Some(entry) = table.entries.get(key) {
+                out.push((path.join(key.as_str()), &entry.node));
+            }
+        }
+        (Step::Index(index), NodeKind::Array(items, _)) => {
+            let i = if *index < 0 {
+                items.len() as i64 + index
+            } else {
+                *index
+            };
+            if let Some(item) = usize::try_from(i).ok().and_then(|i| items.get(i)) {
+                out.push((path.join(i as usize), item));
+            }
+        }
+        (Step::Slice(start, end), NodeKind::Array(items, _)) => {
+            let start = start.map_or(0, |i| resolve(i, items.len()));
+            let end = end.map_or(items.len(), |i| resolve(i, items.len()));
+            let items = items.iter().enumerate().take(end).skip(start);
+            out.extend(items.map(|(i, item)| (path.join(i), item)));
+        }
+        (Step::All, _) => out.extend(children(&path, node)),
+        (Step::Descend, _) => descend(path, node, out),
+        (Step::Filter(filter), _) => out.extend(
+            children(&path, node)
+                .into_iter()
+                .filter(|(_, node)| filter.matches(node)),
+        ),
+        _ => {}
+    }
+}
+
+impl Filter {
+    fn matches(&self, node: &Node) -> bool {
+        let target = self.path.segments().iter().try_fold(node, |node, segment| {
+            match (segment, &node.kind) {
+                (Segment::Key(key), NodeKind::Table(table)) => {
+                    table.entries.get(key).map(|e| &e.node)
+                }
+                (Segment::Index(i), NodeKind::Array(items, _)) => items.get(*i),
+                _ => None,
+            }
+        });
+        match (target, &self.test) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(node), Some((op, literal))) => {
+                let ordering = compare(&node.clone().into_value(), literal);
+                match op {
+                    Op::Eq => ordering == Some(Ordering::Equal),
+                    Op::Ne => ordering != Some(Ordering::Equal),
+                    Op::Lt => ordering == Some(Ordering::Less),
+                    Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    Op::Gt => ordering == Some(Ordering::Greater),
+                    Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                }
+            }
+        }
+    }
+}
+
+/// Compares two values of the same type. Integers and floats compare by value, date-times only
+/// with others of the same kind and offset, and arrays and tables only for equality.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::Integer(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+        (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)),
+        (Value::Datetime(a), Value::Datetime(b))
+            if a.kind() == b.kind() && a.offset == b.offset =>
+        {
+            Some((a.date, a.time).cmp(&(b.date, b.time)))
+        }
+        _ if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+struct QueryParser<'a> {
+    text: &'a str,
+    lex: Lex<'a>,
+    pos: usize,
+}
+
+impl QueryParser<'_> {
+    fn parse(mut self) -> Result<Query> {
+        let mut steps = Vec::new();
+        self.skip_whitespace();
+        // A leading dot is optional, and a query of just `.` selects the whole document.
+        if !self.peek("..") && self.eat(".") {
+            self.skip_whitespace();
+        }
+        if !self.peek("..") && !self.peek("[") && !self.at_end() {
+            steps.push(self.name()?);
+        }
+        loop {
+            self.skip_whitespace();
+            if self.at_end() {
+                break;
+            }
+            if self.eat("..") {
+                steps.push(Step::Descend);
+                match self.current() {
+                    b'[' => {}
+                    _ => steps.push(self.name()?),
+                }
+            } else if self.eat(".") {
+                self.skip_whitespace();
+                steps.push(self.name()?);
+            } else if self.eat("[") {
+                steps.push(self.bracket()?);
+            } else {
+                return Err(self.unexpected());
+            }
+        }
+        Ok(Query { steps })
+    }
+
+    fn current(&self) -> u8 {
+        *self.text.as_bytes().get(self.pos).unwrap_or(&0)
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.text.len()
+    }
+
+    fn peek(&self, s: &str) -> bool {
+        self.text[self.pos..].starts_with(s)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let found = self.peek(s);
+        if found {
+            self.pos += s.len();
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let b' ' | b'\t' = self.current() {
+            self.pos += 1;
+        }
+    }
+
+    fn unexpected(&self) -> Error {
+        Error::Lex(lex::Error::Unexpected { pos: self.pos })
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        self.skip_whitespace();
+        if !self.eat(c.encode_utf8(&mut [0; 4])) {
+            return Err(Error::Lex(lex::Error::Expected { pos: self.pos, c }));
+        }
+        Ok(())
+    }
+
+    /// A key segment or `*`.
+    fn name(&mut self) -> Result<Step> {
+        if self.eat("*") {
+            return Ok(Step::All);
+        }
+        Ok(Step::Key(self.key()?))
+    }
+
+    fn key(&mut self) -> Result<String> {
+        self.pos = self.lex.scan_key_segment_at(self.pos)?;
+        let symbol = self.lex.symbols.pop().expect("a key was scanned");
+        parse::parse_key(self.text, symbol)
+    }
+
+    /// The inside of `[...]`, after the opening bracket.
+    fn bracket(&mut self) -> Result<Step> {
+        self.skip_whitespace();
+        let step = if self.eat("*") {
+            Step::All
+        } else if self.eat("?") {
+            Step::Filter(self.filter()?)
+        } else {
+            let start = self.integer()?;
+            self.skip_whitespace();
+            if self.eat(":") {
+                self.skip_whitespace();
+                let end = self.integer()?;
+                Step::Slice(start, end)
+            } else {
+                Step::Index(start.ok_or_else(|| self.unexpected())?)
+            }
+        };
+        self.expect(']')?;
+        Ok(step)
+    }
+
+    /// An optional integer, which may be negative.
+    fn integer(&mut self) -> Result<Option<i64>> {
+        let start = self.pos;
+        self.eat("-");
+        let digits = self.pos;
+        while self.current().is_ascii_digit() {
+            self.pos += 1;
+        }
+        if self.pos == digits {
+            if self.pos != start {
+                return Err(self.unexpected());
+            }
+            return Ok(None);
+        }
+        let span = Span::new(start, self.pos);
+        self.text[start..self.pos]
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::InvalidNumber { span })
+    }
+
+    /// The inside of `[?...]`, after the question mark.
+    fn filter(&mut self) -> Result<Filter> {
+        self.skip_whitespace();
+        let mut path = Path::new();
+        if !self.eat("@") {
+            path.push(self.key()?);
+        }
+        loop {
+            self.skip_whitespace();
+            if !self.eat(".") {
+                break;
+            }
+            self.skip_whitespace();
+            path.push(self.key()?);
+        }
+        let op = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ]
+        .into_iter()
+        .find(|(s, _)| self.eat(s));
+        let test = match op {
+            Some((_, op)) => {
+                self.skip_whitespace();
+                Some((op, self.literal()?))
+            }
+            None => None,
+        };
+        Ok(Filter { path, test })
+    }
+
+    fn literal(&mut self) -> Result<Value> {
+        self.pos = self.lex.scan_scalar_at(self.pos)?;
+        let symbol = self.lex.symbols.pop().expect("a value was scanned");
+        Ok(Node {
+            span: symbol.span(),
+            kind: parse::parse_scalar(self.text, symbol)?,
+        }
+        .into_value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"
+title = "example"
+ports = [8000, 8001, 9000]
+
+[servers.alpha]
+ip = "10.0.0.1"
+role = "frontend"
+
+[servers."my.host"]
+ip = "10.0.0.2"
+
+[[plugins]]
+name = "lint"
+enabled = true
+since = 2023-05-01
+
+[[plugins]]
+name = "fmt"
+enabled = false
+since = 2024-02-01
+
+[[plugins]]
+name = "docs"
+"#;
+
+    fn paths(query: &str) -> Vec<String> {
+        Query::parse(query)
+            .unwrap()
+            .run(TEXT)
+            .unwrap()
+            .iter()
+            .map(|m| m.path().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn query_success() {
+        assert_eq!(paths("servers.alpha.ip"), ["servers.alpha.ip"]);
+        assert_eq!(paths(".title"), ["title"]);
+        assert_eq!(paths("."), [""]);
+        assert_eq!(paths(""), [""]);
+        assert_eq!(
+            paths("servers.*.ip"),
+            ["servers.alpha.ip", r#"servers."my.host".ip"#]
+        );
+        assert_eq!(paths(r#"servers."my.host""#), [r#"servers."my.host""#]);
+        assert_eq!(paths("ports[-1]"), ["ports[2]"]);
+        assert_eq!(paths("ports[3]"), Vec::<String>::new());
+        assert_eq!(paths("ports[-4]"), Vec::<String>::new());
+        assert_eq!(paths("ports[1:]"), ["ports[1]", "ports[2]"]);
+        assert_eq!(paths("ports[:-2]"), ["ports[0]"]);
+        assert_eq!(paths("ports[2:1]"), Vec::<String>::new());
+        assert_eq!(paths("plugins[*].name").len(), 3);
+        assert_eq!(
+            paths("..ip"),
+            ["servers.alpha.ip", r#"servers."my.host".ip"#]
+        );
+        assert_eq!(paths("servers..ip").len(), 2);
+        assert_eq!(paths("..[1]"), ["plugins[1]", "ports[1]"]);
+        assert_eq!(paths("plugins[?enabled == true].name"), ["plugins[0].name"]);
+        assert_eq!(paths("plugins[?enabled].name").len(), 2);
+        assert_eq!(paths("plugins[?enabled != true].name"), ["plugins[1].name"]);
+        assert_eq!(paths(r#"plugins[? name == "fmt" ]"#), ["plugins[1]"]);
+        assert_eq!(
+            paths("plugins[?since < 2024-01-01].name"),
+            ["plugins[0].name"]
+        );
+        assert_eq!(paths("ports[?@ >= 8001]"), ["ports[1]", "ports[2]"]);
+        assert_eq!(paths("ports[?@ == 8000.0]"), ["ports[0]"]);
+        assert_eq!(paths(r#"servers[?role == "frontend"]"#), ["servers.alpha"]);
+        assert_eq!(paths("title.*"), Vec::<String>::new());
+
+        let matches = Query::parse("servers.alpha.ip").unwrap().run(TEXT).unwrap();
+        let span = matches[0].span();
+        assert_eq!(&TEXT[span.lo()..span.hi()], "10.0.0.1");
+        assert_eq!(matches[0].line_col(TEXT), (6, 7));
+        assert_eq!(matches[0].value(), &Value::from("10.0.0.1"));
+
+        let matches = Query::parse("plugins[?name == 'docs']")
+            .unwrap()
+            .run(TEXT)
+            .unwrap();
+        assert_eq!(matches[0].value().get("name"), Some(&Value::from("docs")));
+    }
+
+    #[test]
+    fn query_fail() {
+        for (text, pos) in [
+            ("a.", 2),
+            ("a b", 2),
+            ("a[", 2),
+            ("a[x]", 2),
+            ("a[-]", 3),
+            ("a[1", 3),
+            ("a[1:2:3]", 5),
+            ("a[?b == ]", 8),
+            ("a[?b == [1]]", 8),
+            ("a[?b = 1]", 5),
+            ("a.'''b'''", 2),
+            ("...a", 2),
+        ] {
+            let err = Query::parse(text).unwrap_err();
+            assert_eq!(err.pos(), Some(pos), "{:?}: {}", text, err);
+        }
+        assert!(Query::parse("a").unwrap().run("a = ").is_err());
+    }
+}
@@ -0,0 +1,884 @@
+//! Validation of documents against a [JSON Schema](https://json-schema.org), and inference of
+//! schemas from sample documents.
+//!
+//! Tables are checked as objects, and every violation is reported with the span of the value
+//! at fault, the key of a property that isn't allowed, or the table that lacks a required key.
+//!
+//! The keywords understood are `type`, `enum`, `const`, `minimum`, `maximum`,
+//! `exclusiveMinimum`, `exclusiveMaximum`, `multipleOf`, `minLength`, `maxLength`, `pattern`,
+//! `format`, `properties`, `required`, `additionalProperties`, `patternProperties`,
+//! `minProperties`, `maxProperties`, `items`, `prefixItems`, `minItems`, `maxItems`,
+//! `uniqueItems`, `allOf`, `anyOf`, `oneOf`, `not` and `$ref` within the schema; others are
+//! ignored.
+//!
+//! TOML has no null, and has date-times JSON lacks. Date-times are strings to a schema, written
+//! as in TOML, and the formats `date-time`, `date` and `time` take offset date-times, local dates
+//! and local times respectively. `local-date-time` takes local date-times. A string with one of
+//! these formats must hold that kind of date-time, and a date-time value must be of its format.
+//!
+//! ```
+//! use toml_rust::schema::Schema;
+//!
+//! let schema = Schema::from_json(r#"{
+//!     "type": "object",
+//!     "properties": { "port": { "type": "integer", "maximum": 65535 } },
+//!     "required": ["port", "host"]
+//! }"#).unwrap();
+//! let text = "port = 80800\n";
+//! let violations = schema.validate(text).unwrap();
+//! let messages: Vec<_> = violations.iter().map(|v| (v.line_col(text), v.to_string())).collect();
+//! assert_eq!(messages, [
+//!     ((1, 1), "missing required key `host`".to_string()),
+//!     ((1, 8), "port: must be at most 65535".to_string()),
+//! ]);
+//! ```
+
+use crate::datetime::{Datetime, DatetimeKind};
+use crate::error::{Error, Result};
+use crate::lex::Span;
+use crate::parse::{self, Node, NodeKind};
+use crate::path::Path;
+use crate::shape::Shape;
+use crate::value::Value;
+use regex::Regex;
+use serde_json::{Map, Value as Json};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A keyword bounding numbers, a test of a number against its value, and how to say it.
+type Bound = (&'static str, fn(f64, f64) -> bool, &'static str);
+
+/// How many `$ref`s may be followed without moving into a value, to stop loops.
+const MAX_REFS: usize = 32;
+
+/// A JSON Schema, ready to validate documents.
+#[derive(Clone, Debug)]
+pub struct Schema {
+    json: Json,
+    patterns: HashMap<String, Regex>,
+}
+
+/// A way in which a document doesn't match a schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    path: Path,
+    span: Span,
+    message: String,
+}
+
+impl Violation {
+    /// Returns the path of the value at fault.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the span of the value at fault, of the key of a property that isn't allowed, or
+    /// of the table that lacks a required key. Tables defined by a `[header]` are spanned by the
+    /// last key of the header, and the root by the whole document.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Returns what is wrong with the value, such as "must be at least 1".
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the 1-based line and column of the start of the span in `text`.
+    pub fn line_col(&self, text: &str) -> (usize, usize) {
+        self.span.line_col(text)
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl Schema {
+    /// Parses a schema from JSON text.
+    pub fn from_json(text: &str) -> Result<Schema> {
+        let json = serde_json::from_str(text).map_err(|e| Error::Message(e.to_string()))?;
+        Schema::new(json)
+    }
+
+    /// Prepares `json` as a schema, failing if it has an invalid pattern or a `$ref` that isn't
+    /// a pointer into it, such as `#/$defs/server`.
+    pub fn new(json: Json) -> Result<Schema> {
+        let mut schema = Schema {
+            json,
+            patterns: HashMap::new(),
+        };
+        let mut patterns = HashMap::new();
+        schema.prepare(&schema.json, &mut patterns, &mut HashSet::new())?;
+        schema.patterns = patterns;
+        Ok(schema)
+    }
+
+    /// Compiles the patterns in `json` and in what its `$ref`s point to, which may be anywhere
+    /// in the schema. `refs` holds the `$ref`s followed so far.
+    fn prepare<'a>(
+        &'a self,
+        json: &'a Json,
+        patterns: &mut HashMap<String, Regex>,
+        refs: &mut HashSet<&'a str>,
+    ) -> Result<()> {
+        let mut compile = |pattern: &str| {
+            if !patterns.contains_key(pattern) {
+                let regex = Regex::new(pattern)
+                    .map_err(|e| Error::Message(format!("invalid pattern `{}`: {}", pattern, e)))?;
+                patterns.insert(pattern.to_string(), regex);
+            }
+            Ok::<_, Error>(())
+        };
+        match json {
+            Json::Object(map) => {
+                if let Some(Json::String(pattern)) = map.get("pattern") {
+                    compile(pattern)?;
+                }
+                if let Some(Json::Object(properties)) = map.get("patternProperties") {
+                    for pattern in properties.keys() {
+                        compile(pattern)?;
+                    }
+                }
+                if let Some(Json::String(reference)) = map.get("$ref") {
+                    match self.resolve(reference) {
+                        Some(target) => {
+                            if refs.insert(reference) {
+                                self.prepare(target, patterns, refs)?;
+                            }
+                        }
+                        None => {
+                            let message = format!("unresolved `$ref` `{}`", reference);
+                            return Err(Error::Message(message));
+                        }
+                    }
+                }
+                for (key, value) in map {
+                    match (key.as_str(), value) {
+                        // Keywords whose values are data rather than schemas.
+                        ("enum" | "const" | "default" | "examples", _) => {}
+                        // Keywords whose values are schemas by names, which may be anything.
+                        (
+                            "properties" | "patternProperties" | "$defs" | "definitions"
+                            | "dependentSchemas",
+                            Json::Object(schemas),
+                        ) => {
+                            for schema in schemas.values() {
+                                self.prepare(schema, patterns, refs)?;
+                            }
+                        }
+                        _ => self.prepare(value, patterns, refs)?,
+                    }
+                }
+            }
+            Json::Array(items) => {
+                for item in items {
+                    self.prepare(item, patterns, refs)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, reference: &str) -> Option<&Json> {
+        self.json.pointer(reference.strip_prefix('#')?)
+    }
+
+    /// Parses `text` and returns the ways it doesn't match the schema, in document order.
+    pub fn validate(&self, text: &str) -> Result<Vec<Violation>> {
+        let root = Node {
+            span: Span::new(0, text.len()),
+            kind: NodeKind::Table(parse::parse_nodes(text)?),
+        };
+        let mut violations = Vec::new();
+        self.check(&self.json, &mut Path::new(), &root, 0, &mut violations);
+        // The sort is stable, so the violations of a value stay in the order of its keywords.
+        violations.sort_by_key(|v| v.span.lo());
+        Ok(violations)
+    }
+
+    fn check(
+        &self,
+        schema: &Json,
+        path: &mut Path,
+        node: &Node,
+        refs: usize,
+        out: &mut Vec<Violation>,
+    ) {
+        let mut fail = |span: Span, message: String| {
+            out.push(Violation {
+                path: path.clone(),
+                span,
+                message,
+            })
+        };
+        let schema = match schema {
+            Json::Bool(true) => return,
+            Json::Bool(false) => return fail(node.span, "no value is allowed here".to_string()),
+            Json::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(Json::String(reference)) = schema.get("$ref") {
+            if refs == MAX_REFS {
+                return fail(node.span, format!("`$ref` `{}` loops", reference));
+            }
+            if let Some(target) = self.resolve(reference) {
+                self.check(target, path, node, refs + 1, out);
+            }
+        }
+        self.check_rest(schema, path, node, refs, out)
+    }
+
+    /// Checks the keywords of `schema` other than `$ref`.
+    fn check_rest(
+        &self,
+        schema: &Map<String, Json>,
+        path: &mut Path,
+        node: &Node,
+        refs: usize,
+        out: &mut Vec<Violation>,
+    ) {
+        let span = node.span;
+        let fail = |out: &mut Vec<Violation>, path: &Path, span: Span, message: String| {
+            out.push(Violation {
+                path: path.clone(),
+                span,
+                message,
+            })
+        };
+
+        if let Some(ty) = schema.get("type") {
+            let types: Vec<&str> = match ty {
+                Json::String(ty) => vec![ty],
+                Json::Array(types) => types.iter().filter_map(Json::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !types.is_empty() && !types.iter().any(|ty| has_type(node, ty)) {
+                let message = format!("expected {}, found {}", types.join(" or "), type_name(node));
+                // Nothing else about a value of the wrong type is worth saying.
+                return fail(out, path, span, message);
+            }
+        }
+        if let Some(Json::Array(values)) = schema.get("enum") {
+            if !values.iter().any(|v| equal(node, v)) {
+                let values: Vec<String> = values.iter().map(Json::to_string).collect();
+                let message = format!("must be one of {}", values.join(", "));
+                fail(out, path, span, message);
+            }
+        }
+        if let Some(value) = schema.get("const") {
+            if !equal(node, value) {
+                fail(out, path, span, format!("must be {}", value));
+            }
+        }
+
+        match &node.kind {
+            NodeKind::Integer(_) | NodeKind::Float(_) => {
+                let n = match node.kind {
+                    NodeKind::Integer(i) => i as f64,
+                    NodeKind::Float(f) => f,
+                    _ => unreachable!(),
+                };
+                let bound = |key| schema.get(key).and_then(Json::as_f64);
+                let checks: [Bound; 4] = [
+                    ("minimum", |n, b| n >= b, "at least"),
+                    ("maximum", |n, b| n <= b, "at most"),
+                    ("exclusiveMinimum", |n, b| n > b, "more than"),
+                    ("exclusiveMaximum", |n, b| n < b, "less than"),
+                ];
+                for (key, ok, words) in checks {
+                    if let Some(b) = bound(key) {
+                        if !ok(n, b) {
+                            let message = format!("must be {} {}", words, schema[key]);
+                            fail(out, path, span, message);
+                        }
+                    }
+                }
+                if let Some(m) = bound("multipleOf") {
+                    let multiple = match (&node.kind, schema["multipleOf"].as_i64()) {
+                        (NodeKind::Integer(i), Some(m)) if m > 0 => i % m == 0,
+                        // Decimals like 0.1 aren't exact, so allow for rounding.
+                        _ => (n - (n / m).round() * m).abs() <= 1e-9 * n.abs().max(m),
+                    };
+                    if m > 0.0 && !multiple {
+                        let message = format!("must be a multiple of {}", schema["multipleOf"]);
+                        fail(out, path, span, message);
+                    }
+                }
+            }
+            NodeKind::String(s) => {
+                let len = s.chars().count() as u64;
+                if let Some(min) = schema.get("minLength").and_then(Json::as_u64) {
+                    if len < min {
+                        let message = format!("must be at least {} characters long", min);
+                        fail(out, path, span, message);
+                    }
+                }
+                if let Some(max) = schema.get("maxLength").and_then(Json::as_u64) {
+                    if len > max {
+                        let message = format!("must be at most {} characters long", max);
+                        fail(out, path, span, message);
+                    }
+                }
+                if let Some(Json::String(pattern)) = schema.get("pattern") {
+                    if self.patterns.get(pattern).is_some_and(|r| !r.is_match(s)) {
+                        fail(out, path, span, format!("must match `{}`", pattern));
+                    }
+                }
+                if let Some(format) = schema.get("format").and_then(Json::as_str) {
+                    if let Some(kind) = datetime_format(format) {
+                        let parsed = s.parse::<Datetime>().ok();
+                        if parsed.map(|d| d.kind()) != Some(kind) {
+                            fail(out, path, span, format!("must be a {}", format));
+                        }
+                    }
+                }
+            }
+            NodeKind::Datetime(d) => {
+                if let Some(format) = schema.get("format").and_then(Json::as_str) {
+                    if datetime_format(format).is_some_and(|kind| kind != d.kind()) {
+                        fail(out, path, span, format!("must be a {}", format));
+                    }
+                }
+            }
+            NodeKind::Table(table) => {
+                let count = table.entries.len() as u64;
+                if let Some(min) = schema.get("minProperties").and_then(Json::as_u64) {
+                    if count < min {
+                        fail(out, path, span, format!("must have at least {} keys", min));
+                    }
+                }
+                if let Some(max) = schema.get("maxProperties").and_then(Json::as_u64) {
+                    if count > max {
+                        fail(out, path, span, format!("must have at most {} keys", max));
+                    }
+                }
+                if let Some(Json::Array(required)) = schema.get("required") {
+                    for key in required.iter().filter_map(Json::as_str) {
+                        if !table.entries.contains_key(key) {
+                            let message = format!("missing required key `{}`", key);
+                            fail(out, path, span, message);
+                        }
+                    }
+                }
+                let properties = schema.get("properties").and_then(Json::as_object);
+                let pattern_properties = schema.get("patternProperties").and_then(Json::as_object);
+                let additional = schema.get("additionalProperties");
+                for (key, entry) in &table.entries {
+                    path.push(key.as_str());
+                    let mut matched = false;
+                    if let Some(property) = properties.and_then(|p| p.get(key)) {
+                        matched = true;
+                        self.check(property, path, &entry.node, 0, out);
+                    }
+                    for (pattern, property) in pattern_properties.into_iter().flatten() {
+                        if self.patterns.get(pattern).is_some_and(|r| r.is_match(key)) {
+                            matched = true;
+                            self.check(property, path, &entry.node, 0, out);
+                        }
+                    }
+                    match additional {
+                        Some(Json::Bool(false)) if !matched => {
+                            fail(out, path, entry.key_span, "key is not allowed".to_string())
+                        }
+                        Some(additional) if !matched => {
+                            self.check(additional, path, &entry.node, 0, out)
+                        }
+                        _ => {}
+                    }
+                    path.pop();
+                }
+            }
+            NodeKind::Array(items, _) => {
+                let count = items.len() as u64;
+                if let Some(min) = schema.get("minItems").and_then(Json::as_u64) {
+                    if count < min {
+                        fail(out, path, span, format!("must have at least {} items", min));
+                    }
+                }
+                if let Some(max) = schema.get("maxItems").and_then(Json::as_u64) {
+                    if count > max {
+                        fail(out, path, span, format!("must have at most {} items", max));
+                    }
+                }
+                if schema.get("uniqueItems") == Some(&Json::Bool(true)) {
+                    let values: Vec<_> =
+                        items.iter().map(|item| item.clone().into_value()).collect();
+                    for (i, item) in items.iter().enumerate() {
+                        if values[..i].contains(&values[i]) {
+                            path.push(i);
+                            fail(
+                                out,
+                                path,
+                                item.span,
+                                "duplicates an earlier item".to_string(),
+                            );
+                            path.pop();
+                        }
+                    }
+                }
+                let prefix = schema.get("prefixItems").and_then(Json::as_array);
+                let prefix = prefix.map_or(&[][..], Vec::as_slice);
+                for (i, item) in items.iter().enumerate() {
+                    let item_schema = prefix.get(i).or_else(|| schema.get("items"));
+                    if let Some(item_schema) = item_schema {
+                        path.push(i);
+                        self.check(item_schema, path, item, 0, out);
+                        path.pop();
+                    }
+                }
+            }
+            NodeKind::Boolean(_) => {}
+        }
+
+        let errors = |schema: &Json, path: &mut Path| {
+            let mut errors = Vec::new();
+            self.check(schema, path, node, refs, &mut errors);
+            errors
+        };
+        if let Some(Json::Array(schemas)) = schema.get("allOf") {
+            for schema in schemas {
+                out.extend(errors(schema, path));
+            }
+        }
+        if let Some(Json::Array(schemas)) = schema.get("anyOf") {
+            if !schemas.iter().any(|schema| errors(schema, path).is_empty()) {
+                let message = "doesn't match any of the schemas in `anyOf`".to_string();
+                fail(out, path, span, message);
+            }
+        }
+        if let Some(Json::Array(schemas)) = schema.get("oneOf") {
+            let matching = schemas
+                .iter()
+                .filter(|schema| errors(schema, path).is_empty())
+                .count();
+            if matching != 1 {
+                let message = format!(
+                    "matches {} of the schemas in `oneOf` instead of exactly one",
+                    matching
+                );
+                fail(out, path, span, message);
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if errors(not, path).is_empty() {
+                fail(out, path, span, "matches the schema in `not`".to_string());
+            }
+        }
+    }
+}
+
+fn datetime_format(format: &str) -> Option<DatetimeKind> {
+    Some(match format {
+        "date-time" => DatetimeKind::OffsetDatetime,
+        "local-date-time" => DatetimeKind::LocalDatetime,
+        "date" => DatetimeKind::LocalDate,
+        "time" => DatetimeKind::LocalTime,
+        _ => return None,
+    })
+}
+
+fn has_type(node: &Node, ty: &str) -> bool {
+    matches!(
+        (ty, &node.kind),
+        ("string", NodeKind::String(_) | NodeKind::Datetime(_))
+            | ("integer", NodeKind::Integer(_))
+            | ("number", NodeKind::Integer(_) | NodeKind::Float(_))
+            | ("boolean", NodeKind::Boolean(_))
+            | ("array", NodeKind::Array(..))
+            | ("object", NodeKind::Table(_))
+    )
+}
+
+fn type_name(node: &Node) -> &'static str {
+    match node.kind {
+        NodeKind::String(_) => "string",
+        NodeKind::Integer(_) => "integer",
+        NodeKind::Float(_) => "float",
+        NodeKind::Boolean(_) => "boolean",
+        NodeKind::Datetime(_) => "datetime",
+        NodeKind::Array(..) => "array",
+        NodeKind::Table(_) => "table",
+    }
+}
+
+/// Compares a value with JSON, where numbers are equal if their values are and date-times are
+/// strings.
+fn equal(node: &Node, json: &Json) -> bool {
+    match (&node.kind, json) {
+        (NodeKind::String(s), Json::String(j)) => s == j,
+        (NodeKind::Datetime(d), Json::String(j)) => d.to_string() == *j,
+        (NodeKind::Integer(i), Json::Number(n)) => match n.as_i64() {
+            Some(j) => *i == j,
+            None => n.as_f64() == Some(*i as f64),
+        },
+        (NodeKind::Float(f), Json::Number(n)) => n.as_f64() == Some(*f),
+        (NodeKind::Boolean(b), Json::Bool(j)) => b == j,
+        (NodeKind::Array(items, _), Json::Array(j)) => {
+            items.len() == j.len() && items.iter().zip(j).all(|(a, b)| equal(a, b))
+        }
+        (NodeKind::Table(table), Json::Object(j)) => {
+            table.entries.len() == j.len()
+                && table
+                    .entries
+                    .iter()
+                    .all(|(k, e)| j.get(k).is_some_and(|v| equal(&e.node, v)))
+        }
+        _ => false,
+    }
+}
+
+/// Infers a schema that `samples` match, such as to bootstrap the validation of documents nobody
+/// has written one for. Errors in the samples are reported as in the source `sample N`,
+/// counting from 1.
+///
+/// Keys found in every table at a place are required, and the others optional. The items of an
+/// array share one schema, so the keys of arrays of tables are unified. Integers and floats
+/// found at the same place are numbers, and date-times are strings with the format of their
+/// kind. Properties not found in any sample aren't forbidden, so that the schema can be
+/// tightened by hand.
+///
+/// ```
+/// use toml_rust::schema;
+///
+/// let schema = schema::infer(["port = 80\n", "port = 8080\nhost = 'a'\n"]).unwrap();
+/// assert_eq!(schema["required"], serde_json::json!(["port"]));
+/// assert_eq!(schema["properties"]["host"]["type"], "string");
+/// ```
+pub fn infer<'a>(samples: impl IntoIterator<Item = &'a str>) -> Result<Json> {
+    let mut shape = Shape::default();
+    for (i, sample) in samples.into_iter().enumerate() {
+        let table = parse::parse(sample).map_err(|e| e.in_source(format!("sample {}", i + 1)))?;
+        shape.add(&Value::Table(table));
+    }
+    let mut schema = match shape_to_json(&shape) {
+        Json::Object(schema) => schema,
+        _ => unreachable!(),
+    };
+    let draft = "https://json-schema.org/draft/2020-12/schema";
+    schema.insert("$schema".to_string(), Json::String(draft.to_string()));
+    Ok(Json::Object(schema))
+}
+
+/// Returns the schema of the values `shape` describes.
+fn shape_to_json(shape: &Shape) -> Json {
+    let mut schema = Map::new();
+    let mut types = Vec::new();
+    if shape.strings || !shape.datetimes.is_empty() {
+        types.push("string");
+        if let ([kind], false) = (shape.datetimes.as_slice(), shape.strings) {
+            let format = match kind {
+                DatetimeKind::OffsetDatetime => "date-time",
+                DatetimeKind::LocalDatetime => "local-date-time",
+                DatetimeKind::LocalDate => "date",
+                DatetimeKind::LocalTime => "time",
+            };
+            schema.insert("format".to_string(), format.into());
+        }
+    }
+    match (shape.integers, shape.floats) {
+        (true, false) => types.push("integer"),
+        (_, true) => types.push("number"),
+        _ => {}
+    }
+    if shape.booleans {
+        types.push("boolean");
+    }
+    if shape.arrays {
+        types.push("array");
+        if let Some(items) = &shape.items {
+            schema.insert("items".to_string(), shape_to_json(items));
+        }
+    }
+    if shape.tables > 0 {
+        types.push("object");
+        let properties = shape.properties.iter();
+        let required: Vec<Json> = properties
+            .clone()
+            .filter(|(_, (count, _))| *count == shape.tables)
+            .map(|(key, _)| key.as_str().into())
+            .collect();
+        let properties: Map<String, Json> = properties
+            .map(|(key, (_, shape))| (key.clone(), shape_to_json(shape)))
+            .collect();
+        if !properties.is_empty() {
+            schema.insert("properties".to_string(), properties.into());
+        }
+        if !required.is_empty() {
+            schema.insert("required".to_string(), required.into());
+        }
+    }
+    match types.as_slice() {
+        [] => {}
+        [ty] => {
+            schema.insert("type".to_string(), (*ty).into());
+        }
+        types => {
+            schema.insert("type".to_string(), types.to_vec().into());
+        }
+    }
+    schema.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violations(schema: &str, text: &str) -> Vec<(String, (usize, usize))> {
+        let schema = Schema::from_json(schema).unwrap();
+        let violations = schema.validate(text).unwrap();
+        violations
+            .iter()
+            .map(|v| (v.to_string(), v.line_col(text)))
+            .collect()
+    }
+
+    #[test]
+    fn schema_success() {
+        let schema = r##"{
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "pattern": "^[a-z]+$", "maxLength": 8 },
+                "ratio": { "type": "number", "exclusiveMinimum": 0 },
+                "mode": { "enum": ["fast", "slow"] },
+                "born": { "type": "string", "format": "date" },
+                "at": { "type": "string", "format": "date-time" },
+                "servers": { "type": "array", "items": { "$ref": "#/$defs/server" }, "uniqueItems": true },
+                "tags": { "type": "array", "prefixItems": [{ "const": 1 }], "items": { "type": "string" } }
+            },
+            "patternProperties": { "^x-": { "type": "boolean" } },
+            "additionalProperties": false,
+            "$defs": {
+                "server": {
+                    "type": "object",
+                    "required": ["host"],
+                    "properties": { "port": { "type": "integer", "minimum": 1, "maximum": 65535 } }
+                }
+            }
+        }"##;
+        let text = r#"
+name = "app"
+ratio = 2
+mode = "fast"
+born = 1979-05-27
+at = "1979-05-27T07:32:00Z"
+tags = [1.0, "a"]
+x-debug = true
+
+[[servers]]
+host = "a"
+port = 80
+"#;
+        assert_eq!(violations(schema, text), []);
+
+        let schema = r#"{ "properties": { "a": { "anyOf": [{ "type": "integer" }, { "minLength": 2 }] } } }"#;
+        assert_eq!(violations(schema, "a = 'xy'"), []);
+        let schema =
+            r#"{ "properties": { "a": { "oneOf": [{ "multipleOf": 2 }, { "multipleOf": 3 }] } } }"#;
+        assert_eq!(violations(schema, "a = 4"), []);
+        let schema =
+            r#"{ "properties": { "a": { "multipleOf": 0.1 }, "b": { "multipleOf": 0.01 } } }"#;
+        assert_eq!(violations(schema, "a = 0.3\nb = 19.99"), []);
+
+        // Patterns are compiled wherever a `$ref` points, even inside data.
+        let schema = r##"{ "properties": { "a": { "$ref": "#/properties/b/default" }, "b": { "default": { "pattern": "x" } } } }"##;
+        assert_eq!(violations(schema, "a = 'x'"), []);
+        let schema = r##"{ "$defs": { "s": { "properties": { "a": { "$ref": "#/$defs/s" } } } }, "$ref": "#/$defs/s" }"##;
+        assert_eq!(violations(schema, "a.a.a = {}"), []);
+    }
+
+    #[test]
+    fn schema_fail() {
+        let schema = r##"{
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "name": { "type": "string", "pattern": "^[a-z]+$", "maxLength": 3 },
+                "ratio": { "type": "number", "exclusiveMinimum": 0 },
+                "mode": { "enum": ["fast", "slow"] },
+                "born": { "type": "string", "format": "date" },
+                "at": { "type": ["string"], "format": "date-time" },
+                "servers": { "type": "array", "items": { "$ref": "#/$defs/server" }, "uniqueItems": true },
+                "count": { "oneOf": [{ "multipleOf": 2 }, { "multipleOf": 3 }] }
+            },
+            "patternProperties": { "^x-": { "type": "boolean" } },
+            "additionalProperties": false,
+            "$defs": {
+                "server": {
+                    "type": "object",
+                    "required": ["host"],
+                    "properties": { "port": { "type": "integer", "minimum": 1, "maximum": 65535 } }
+                }
+            }
+        }"##;
+        let text = r#"ratio = 0.0
+mode = "medium"
+born = 1979-05-27T00:00:00
+at = "yesterday"
+x-debug = 1
+other = 1
+count = 6
+
+[[servers]]
+port = 0
+
+[[servers]]
+port = 0
+
+[[servers]]
+host = 1
+port = 1.5
+"#;
+        assert_eq!(
+            violations(schema, text),
+            [
+                ("missing required key `name`".to_string(), (1, 1)),
+                ("ratio: must be more than 0".to_string(), (1, 9)),
+                (
+                    "mode: must be one of \"fast\", \"slow\"".to_string(),
+                    (2, 9)
+                ),
+                ("born: must be a date".to_string(), (3, 8)),
+                ("at: must be a date-time".to_string(), (4, 7)),
+                (
+                    "x-debug: expected boolean, found integer".to_string(),
+                    (5, 11)
+                ),
+                ("other: key is not allowed".to_string(), (6, 1)),
+                (
+                    "count: matches 2 of the schemas in `oneOf` instead of exactly one".to_string(),
+                    (7, 9)
+                ),
+                (
+                    "servers[0]: missing required key `host`".to_string(),
+                    (9, 3)
+                ),
+                ("servers[0].port: must be at least 1".to_string(), (10, 8)),
+                (
+                    "servers[1]: duplicates an earlier item".to_string(),
+                    (12, 3)
+                ),
+                (
+                    "servers[1]: missing required key `host`".to_string(),
+                    (12, 3)
+                ),
+                ("servers[1].port: must be at least 1".to_string(), (13, 8)),
+                (
+                    "servers[2].port: expected integer, found float".to_string(),
+                    (17, 8)
+                ),
+            ]
+        );
+        assert_eq!(
+            violations(
+                r#"{ "properties": { "a": { "pattern": "^b", "minLength": 3 } } }"#,
+                "a = 'é'"
+            ),
+            [
+                ("a: must be at least 3 characters long".to_string(), (1, 6)),
+                ("a: must match `^b`".to_string(), (1, 6)),
+            ]
+        );
+        assert_eq!(
+            violations(
+                r#"{ "properties": { "enum": { "pattern": "^a" }, "default": { "pattern": "^b" } } }"#,
+                "enum = 'b'\ndefault = 'a'"
+            ),
+            [
+                ("enum: must match `^a`".to_string(), (1, 9)),
+                ("default: must match `^b`".to_string(), (2, 12)),
+            ]
+        );
+        assert_eq!(violations("false", "")[0].0, "no value is allowed here");
+        assert_eq!(
+            violations(r##"{ "$ref": "#" }"##, "")[0].0,
+            "`$ref` `#` loops"
+        );
+
+        let err = Schema::from_json(r#"{ "pattern": "(" }"#).unwrap_err();
+        assert!(
+            err.to_string().starts_with("invalid pattern `(`"),
+            "{}",
+            err
+        );
+        let err = Schema::from_json(r##"{ "$ref": "#/nope" }"##).unwrap_err();
+        assert_eq!(err.to_string(), "unresolved `$ref` `#/nope`");
+    }
+
+    #[test]
+    fn infer_success() {
+        let samples = [
+            r#"
+name = "a"
+ratio = 1
+born = 1979-05-27
+tags = ["x"]
+
+[[servers]]
+host = "h"
+port = 80
+"#,
+            r#"
+name = "b"
+ratio = 0.5
+born = 1979-05-28
+empty = []
+
+[[servers]]
+host = "h"
+
+[[servers]]
+host = "i"
+tls = true
+"#,
+        ];
+        let schema = infer(samples).unwrap();
+        assert_eq!(
+            schema,
+            serde_json::json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "required": ["born", "name", "ratio", "servers"],
+                "properties": {
+                    "born": { "type": "string", "format": "date" },
+                    "empty": { "type": "array" },
+                    "name": { "type": "string" },
+                    "ratio": { "type": "number" },
+                    "servers": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["host"],
+                            "properties": {
+                                "host": { "type": "string" },
+                                "port": { "type": "integer" },
+                                "tls": { "type": "boolean" }
+                            }
+                        }
+                    },
+                    "tags": { "type": "array", "items": { "type": "string" } }
+                }
+            })
+        );
+        let schema = Schema::new(schema).unwrap();
+        for sample in samples {
+            assert_eq!(schema.validate(sample).unwrap(), []);
+        }
+
+        let schema = infer(["a = 1", "a = 'x'", "a = 1979-05-27T00:00:00"]).unwrap();
+        assert_eq!(
+            schema["properties"]["a"],
+            serde_json::json!({ "type": ["string", "integer"] })
+        );
+        let err = infer(["a = 1", "a = "]).unwrap_err();
+        assert_eq!(err.to_string(), "sample 2: Unexpected { pos: 4 }");
+    }
+}
@@ -1,16 +1,655 @@
-use crate::Result;
+use crate::datetime;
+use crate::value::{Array, Table, Value};
+use crate::{Error, Result};
 use serde::{ser, Serialize};
+use std::fmt::Write;
 
-pub struct Serializer {}
-
+/// Serializes `value` as a TOML document. The value must serialize to a table.
 pub fn to_string<T>(value: &T) -> Result<String>
 where
-    T: Serialize,
+    T: ?Sized + Serialize,
 {
-    let mut serializer = Serializer {};
-    value.serialize(&mut serializer)?;
-    Ok(serializer.output)
+    match to_value(value)? {
+        Value::Table(table) => {
+            let mut output = String::new();
+            write_document(&mut output, &table);
+            Ok(output)
+        }
+        value => Err(Error::Message(format!(
+            "expected a table at the top level, found {}",
+            value.type_str()
+        ))),
+    }
+}
+
+/// Converts `value` into a `Value`.
+pub fn to_value<T>(value: &T) -> Result<Value>
+where
+    T: ?Sized + Serialize,
+{
+    value.serialize(ValueSerializer)
+}
+
+/// Returns true if `key` can be written without quotes.
+pub(crate) fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|c| matches!(c, b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-'))
+}
+
+pub(crate) fn write_key(output: &mut String, key: &str) {
+    if is_bare_key(key) {
+        output.push_str(key);
+    } else {
+        write_string(output, key);
+    }
+}
+
+pub(crate) fn write_string(output: &mut String, s: &str) {
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '\u{8}' => output.push_str("\\b"),
+            '\t' => output.push_str("\\t"),
+            '\n' => output.push_str("\\n"),
+            '\u{c}' => output.push_str("\\f"),
+            '\r' => output.push_str("\\r"),
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\u{0}'..='\u{1f}' | '\u{7f}' => {
+                let _ = write!(output, "\\u{:04X}", c as u32);
+            }
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+fn write_float(output: &mut String, f: f64) {
+    if f.is_nan() {
+        output.push_str(if f.is_sign_negative() { "-nan" } else { "nan" });
+    } else if f.is_infinite() {
+        output.push_str(if f < 0.0 { "-inf" } else { "inf" });
+    } else {
+        // Debug formatting always includes a fraction or exponent, so the value reads back as a
+        // float.
+        let _ = write!(output, "{:?}", f);
+    }
+}
+
+/// Writes a value in its inline form, so tables become inline tables.
+pub(crate) fn write_value(output: &mut String, value: &Value) {
+    match value {
+        Value::String(s) => write_string(output, s),
+        Value::Integer(i) => {
+            let _ = write!(output, "{}", i);
+        }
+        Value::Float(f) => write_float(output, *f),
+        Value::Boolean(b) => output.push_str(if *b { "true" } else { "false" }),
+        Value::Datetime(d) => {
+            let _ = write!(output, "{}", d);
+        }
+        Value::Array(items) => {
+            output.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(", ");
+                }
+                write_value(output, item);
+            }
+            output.push(']');
+        }
+        Value::Table(table) => {
+            if table.is_empty() {
+                output.push_str("{}");
+                return;
+            }
+            output.push_str("{ ");
+            for (i, (key, value)) in table.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(", ");
+                }
+                write_key(output, key);
+                output.push_str(" = ");
+                write_value(output, value);
+            }
+            output.push_str(" }");
+        }
+    }
+}
+
+fn is_array_of_tables(items: &Array) -> bool {
+    !items.is_empty() && items.iter().all(Value::is_table)
+}
+
+/// Values that are written under their own header rather than as `key = value`.
+pub(crate) fn is_section(value: &Value) -> bool {
+    match value {
+        Value::Table(_) => true,
+        Value::Array(items) => is_array_of_tables(items),
+        _ => false,
+    }
+}
+
+fn write_header(output: &mut String, path: &[&str], is_array: bool) {
+    if !output.is_empty() {
+        output.push('\n');
+    }
+    output.push_str(if is_array { "[[" } else { "[" });
+    for (i, key) in path.iter().enumerate() {
+        if i > 0 {
+            output.push('.');
+        }
+        write_key(output, key);
+    }
+    output.push_str(if is_array { "]]\n" } else { "]\n" });
+}
+
+fn write_table<'a>(output: &mut String, path: &mut Vec<&'a str>, table: &'a Table) {
+    for (key, value) in table {
+        if !is_section(value) {
+            write_key(output, key);
+            output.push_str(" = ");
+            write_value(output, value);
+            output.push('\n');
+        }
+    }
+
+    for (key, value) in table {
+        path.push(key);
+        write_section(output, path, value);
+        path.pop();
+    }
+}
+
+/// Writes a table or an array of tables named by `path` under its own headers. Other values
+/// are skipped.
+pub(crate) fn write_section<'a>(output: &mut String, path: &mut Vec<&'a str>, value: &'a Value) {
+    match value {
+        Value::Table(child) => {
+            // Tables holding nothing but other sections are implied by their children.
+            if child.is_empty() || !child.values().all(is_section) {
+                write_header(output, path, false);
+            }
+            write_table(output, path, child);
+        }
+        Value::Array(items) if is_array_of_tables(items) => {
+            for item in items {
+                write_header(output, path, true);
+                if let Value::Table(child) = item {
+                    write_table(output, path, child);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Writes `table` as a TOML document.
+pub(crate) fn write_document(output: &mut String, table: &Table) {
+    write_table(output, &mut Vec::new(), table)
+}
+
+/// A serde serializer which produces a `Value`.
+pub struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeVariant<SerializeArray>;
+    type SerializeMap = SerializeTable;
+    type SerializeStruct = SerializeTable;
+    type SerializeStructVariant = SerializeVariant<SerializeTable>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value> {
+        Ok(Value::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        match i64::try_from(v) {
+            Ok(v) => self.serialize_i64(v),
+            Err(_) => Err(Error::Message(format!("u64 value {} is out of range", v))),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        Ok(Value::Array(
+            v.iter().map(|&b| Value::Integer(b.into())).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Value> {
+        Err(Error::UnsupportedNone)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value> {
+        Err(Error::Message("unsupported unit value".to_string()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut table = Table::new();
+        table.insert(variant.to_string(), value.serialize(self)?);
+        Ok(Value::Table(table))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray> {
+        Ok(SerializeArray {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeArray> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeArray>> {
+        Ok(SerializeVariant {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeTable> {
+        Ok(SerializeTable {
+            table: Table::new(),
+            key: None,
+            datetime: false,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<SerializeTable> {
+        Ok(SerializeTable {
+            table: Table::new(),
+            key: None,
+            datetime: name == datetime::NAME,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeTable>> {
+        Ok(SerializeVariant {
+            variant,
+            inner: self.serialize_struct(name, len)?,
+        })
+    }
+}
+
+pub struct SerializeArray {
+    items: Array,
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Array(self.items))
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeTable {
+    table: Table,
+    key: Option<String>,
+    datetime: bool,
+}
+
+impl SerializeTable {
+    fn insert<T>(&mut self, key: String, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        match value.serialize(ValueSerializer) {
+            Ok(value) => {
+                self.table.insert(key, value);
+                Ok(())
+            }
+            // Absent optional values are simply left out of the table.
+            Err(Error::UnsupportedNone) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl ser::SerializeMap for SerializeTable {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        match key.serialize(ValueSerializer)? {
+            Value::String(s) => self.key = Some(s),
+            Value::Integer(i) => self.key = Some(i.to_string()),
+            Value::Boolean(b) => self.key = Some(b.to_string()),
+            value => {
+                return Err(Error::Message(format!(
+                    "table keys must be strings, found {}",
+                    value.type_str()
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        match self.key.take() {
+            Some(key) => self.insert(key, value),
+            None => Err(Error::Message(
+                "serialize_value called before serialize_key".to_string(),
+            )),
+        }
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+impl ser::SerializeStruct for SerializeTable {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(mut self) -> Result<Value> {
+        if self.datetime {
+            if let Some(Value::String(s)) = self.table.remove(datetime::FIELD) {
+                return s
+                    .parse()
+                    .map(Value::Datetime)
+                    .map_err(|e| Error::Message(e.to_string()));
+            }
+        }
+        Ok(Value::Table(self.table))
+    }
+}
+
+/// Wraps the serialized content of an enum variant in a single entry table.
+pub struct SerializeVariant<T> {
+    variant: &'static str,
+    inner: T,
+}
+
+impl<T> SerializeVariant<T> {
+    fn wrap(variant: &'static str, value: Value) -> Value {
+        let mut table = Table::new();
+        table.insert(variant.to_string(), value);
+        Value::Table(table)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeArray> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        let value = ser::SerializeSeq::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
-}
\ No newline at end of file
+impl ser::SerializeStructVariant for SerializeVariant<SerializeTable> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        let value = ser::SerializeStruct::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_derive::Serialize;
+
+    #[test]
+    fn document_roundtrip() {
+        let text = r#"
+            title = "TOML \"example\""
+            ratio = 0.5
+            big = 1e300
+            nan = nan
+            when = 1979-05-27T07:32:00Z
+            list = [1, [2, 3], { inline = true }]
+            empty = {}
+            "key with spaces" = 1
+
+            [owner]
+            name = "Tom"
+
+            [a.b.c]
+            d = 1
+
+            [[products]]
+            name = "Hammer"
+
+            [[products]]
+            name = "Nail"
+            [products.dims]
+            len = 3
+        "#;
+        let table = parse(text).unwrap();
+        let mut output = String::new();
+        write_document(&mut output, &table);
+        let reparsed = parse(&output).unwrap();
+        let strip_nan = |mut t: Table| {
+            assert!(t.remove("nan").unwrap().as_float().unwrap().is_nan());
+            t
+        };
+        assert_eq!(strip_nan(table), strip_nan(reparsed));
+        assert!(output.contains("[a.b.c]\n"));
+        assert!(!output.contains("[a]\n"));
+        assert!(output.contains("\"key with spaces\" = 1\n"));
+    }
+
+    #[test]
+    fn serialize_struct() {
+        #[derive(Serialize)]
+        struct Server {
+            host: String,
+            port: u16,
+            tls: Option<bool>,
+        }
+
+        #[derive(Serialize)]
+        struct Config {
+            name: &'static str,
+            servers: Vec<Server>,
+        }
+
+        let config = Config {
+            name: "demo",
+            servers: vec![
+                Server {
+                    host: "alpha".into(),
+                    port: 80,
+                    tls: None,
+                },
+                Server {
+                    host: "beta".into(),
+                    port: 443,
+                    tls: Some(true),
+                },
+            ],
+        };
+        assert_eq!(
+            to_string(&config).unwrap(),
+            "name = \"demo\"\n\
+             \n\
+             [[servers]]\n\
+             host = \"alpha\"\n\
+             port = 80\n\
+             \n\
+             [[servers]]\n\
+             host = \"beta\"\n\
+             port = 443\n\
+             tls = true\n"
+        );
+        assert!(to_string(&1).is_err());
+    }
+}
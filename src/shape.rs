@@ -0,0 +1,52 @@
+//! What the values at each place of several sample documents have in common, for inferring
+//! schemas and types from them.
+
+use crate::datetime::DatetimeKind;
+use crate::value::Value;
+use std::collections::BTreeMap;
+
+/// What the values found at one place of the samples have in common.
+#[derive(Default)]
+pub(crate) struct Shape {
+    pub strings: bool,
+    pub integers: bool,
+    pub floats: bool,
+    pub booleans: bool,
+    pub datetimes: Vec<DatetimeKind>,
+    pub arrays: bool,
+    pub items: Option<Box<Shape>>,
+    /// How many tables were found, and the keys they have.
+    pub tables: usize,
+    pub properties: BTreeMap<String, (usize, Shape)>,
+}
+
+impl Shape {
+    /// Adds a value found at this place.
+    pub fn add(&mut self, value: &Value) {
+        match value {
+            Value::String(_) => self.strings = true,
+            Value::Integer(_) => self.integers = true,
+            Value::Float(_) => self.floats = true,
+            Value::Boolean(_) => self.booleans = true,
+            Value::Datetime(d) => {
+                if !self.datetimes.contains(&d.kind()) {
+                    self.datetimes.push(d.kind());
+                }
+            }
+            Value::Array(items) => {
+                self.arrays = true;
+                for item in items {
+                    self.items.get_or_insert_with(Default::default).add(item);
+                }
+            }
+            Value::Table(table) => {
+                self.tables += 1;
+                for (key, value) in table {
+                    let (count, shape) = self.properties.entry(key.clone()).or_default();
+                    *count += 1;
+                    shape.add(value);
+                }
+            }
+        }
+    }
+}
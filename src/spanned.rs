@@ -0,0 +1,146 @@
+//! A wrapper that keeps the location of a deserialized value in the source text.
+
+use crate::lex::Span;
+use serde::{de, ser};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+// Like datetimes, spanned values pass through serde as a struct with private field names. The
+// TOML deserializer recognizes `NAME` and supplies the span along with the value.
+pub(crate) const NAME: &str = "$__toml_private_Spanned";
+pub(crate) const START: &str = "$__toml_private_start";
+pub(crate) const END: &str = "$__toml_private_end";
+pub(crate) const VALUE: &str = "$__toml_private_value";
+pub(crate) const FIELDS: &[&str] = &[START, END, VALUE];
+
+/// A deserialized value along with the span of source text it came from.
+///
+/// Works for any value and for the keys of maps. Tables defined by a `[header]` are spanned by
+/// the last key of the header, and the document itself by the whole text. Only the value takes
+/// part in comparisons and hashing, so a `Spanned<String>` can be used as a map key.
+///
+/// Spanned values can only be deserialized from TOML; other deserializers will report an error.
+#[derive(Clone, Debug)]
+pub struct Spanned<T> {
+    span: Span,
+    value: T,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(span: Span, value: T) -> Self {
+        Self { span, value }
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Returns the 1-based line and column of the start of the value in `text`, which must be
+    /// the document it was deserialized from.
+    pub fn line_col(&self, text: &str) -> (usize, usize) {
+        self.span.line_col(text)
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.value
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Eq> Eq for Spanned<T> {}
+
+impl<T: PartialOrd> PartialOrd for Spanned<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl<T: Ord> Ord for Spanned<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl<T: Hash> Hash for Spanned<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state)
+    }
+}
+
+impl Borrow<str> for Spanned<String> {
+    fn borrow(&self) -> &str {
+        &self.value
+    }
+}
+
+impl<T> AsRef<T> for Spanned<T> {
+    fn as_ref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: ser::Serialize> ser::Serialize for Spanned<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        self.value.serialize(serializer)
+    }
+}
+
+impl<'de, T: de::Deserialize<'de>> de::Deserialize<'de> for Spanned<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct SpannedVisitor<T>(std::marker::PhantomData<T>);
+
+        impl<'de, T: de::Deserialize<'de>> de::Visitor<'de> for SpannedVisitor<T> {
+            type Value = Spanned<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a spanned TOML value")
+            }
+
+            fn visit_map<V>(self, mut visitor: V) -> Result<Spanned<T>, V::Error>
+            where
+                V: de::MapAccess<'de>,
+            {
+                fn field<'de, V: de::MapAccess<'de>>(
+                    visitor: &mut V,
+                    name: &str,
+                ) -> Result<(), V::Error> {
+                    match visitor.next_key::<String>()? {
+                        Some(key) if key == name => Ok(()),
+                        _ => Err(de::Error::custom("expected a spanned value")),
+                    }
+                }
+
+                field(&mut visitor, START)?;
+                let lo: usize = visitor.next_value()?;
+                field(&mut visitor, END)?;
+                let hi: usize = visitor.next_value()?;
+                field(&mut visitor, VALUE)?;
+                let value = visitor.next_value()?;
+                Ok(Spanned::new(Span::new(lo, hi), value))
+            }
+        }
+
+        deserializer.deserialize_struct(NAME, FIELDS, SpannedVisitor(std::marker::PhantomData))
+    }
+}
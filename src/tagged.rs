@@ -0,0 +1,170 @@
+//! Conversion to and from the tagged JSON format used by the
+//! [toml-test](https://github.com/toml-lang/toml-test) suite.
+//!
+//! Scalars are written as `{"type": "integer", "value": "42"}`, arrays as JSON arrays and tables
+//! as JSON objects.
+
+use crate::datetime::{Datetime, DatetimeKind};
+use crate::error::{Error, Result};
+use crate::value::{Table, Value};
+use serde_json::{json, Map, Value as Json};
+
+fn tag(ty: &str, value: String) -> Json {
+    json!({ "type": ty, "value": value })
+}
+
+fn float_to_string(f: f64) -> String {
+    if f.is_nan() {
+        "nan".to_string()
+    } else if f.is_infinite() {
+        if f < 0.0 { "-inf" } else { "inf" }.to_string()
+    } else {
+        format!("{:?}", f)
+    }
+}
+
+/// Converts a value into tagged JSON.
+pub fn to_json(value: &Value) -> Json {
+    match value {
+        Value::String(s) => tag("string", s.clone()),
+        Value::Integer(i) => tag("integer", i.to_string()),
+        Value::Float(f) => tag("float", float_to_string(*f)),
+        Value::Boolean(b) => tag("bool", b.to_string()),
+        Value::Datetime(d) => {
+            let ty = match d.kind() {
+                DatetimeKind::OffsetDatetime => "datetime",
+                DatetimeKind::LocalDatetime => "datetime-local",
+                DatetimeKind::LocalDate => "date-local",
+                DatetimeKind::LocalTime => "time-local",
+            };
+            tag(ty, d.to_string())
+        }
+        Value::Array(items) => Json::Array(items.iter().map(to_json).collect()),
+        Value::Table(table) => table_to_json(table),
+    }
+}
+
+/// Converts a table into a tagged JSON object.
+pub fn table_to_json(table: &Table) -> Json {
+    Json::Object(
+        table
+            .iter()
+            .map(|(k, v)| (k.clone(), to_json(v)))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+#[cold]
+fn invalid(msg: &str, json: &Json) -> Error {
+    Error::Message(format!("{}: {}", msg, json))
+}
+
+/// Returns the type and value of a tagged scalar, if `map` is one.
+fn as_tagged(map: &Map<String, Json>) -> Option<(&str, &str)> {
+    if map.len() != 2 {
+        return None;
+    }
+    match (map.get("type"), map.get("value")) {
+        (Some(Json::String(ty)), Some(Json::String(value))) => Some((ty, value)),
+        _ => None,
+    }
+}
+
+/// Converts tagged JSON back into a value.
+pub fn from_json(json: &Json) -> Result<Value> {
+    match json {
+        Json::Array(items) => items
+            .iter()
+            .map(from_json)
+            .collect::<Result<_>>()
+            .map(Value::Array),
+        Json::Object(map) => match as_tagged(map) {
+            Some((ty, value)) => scalar_from_json(ty, value, json),
+            None => table_from_json(json).map(Value::Table),
+        },
+        _ => Err(invalid("expected a tagged value, array or table", json)),
+    }
+}
+
+/// Converts a tagged JSON object back into a table.
+pub fn table_from_json(json: &Json) -> Result<Table> {
+    match json {
+        Json::Object(map) => map
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), from_json(v)?)))
+            .collect(),
+        _ => Err(invalid("expected a table", json)),
+    }
+}
+
+fn scalar_from_json(ty: &str, value: &str, json: &Json) -> Result<Value> {
+    let bad_value = || invalid("invalid value", json);
+    match ty {
+        "string" => Ok(Value::String(value.to_string())),
+        "integer" => value.parse().map(Value::Integer).map_err(|_| bad_value()),
+        "float" => match value {
+            "inf" | "+inf" => Ok(Value::Float(f64::INFINITY)),
+            "-inf" => Ok(Value::Float(f64::NEG_INFINITY)),
+            "nan" | "+nan" | "-nan" => Ok(Value::Float(f64::NAN)),
+            _ => value.parse().map(Value::Float).map_err(|_| bad_value()),
+        },
+        "bool" => match value {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => Err(bad_value()),
+        },
+        "datetime" | "datetime-local" | "date-local" | "time-local" => {
+            let datetime: Datetime = value.parse().map_err(|_| bad_value())?;
+            let kind = match ty {
+                "datetime" => DatetimeKind::OffsetDatetime,
+                "datetime-local" => DatetimeKind::LocalDatetime,
+                "date-local" => DatetimeKind::LocalDate,
+                _ => DatetimeKind::LocalTime,
+            };
+            if datetime.kind() != kind {
+                return Err(bad_value());
+            }
+            Ok(Value::Datetime(datetime))
+        }
+        _ => Err(invalid("unknown type", json)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn roundtrip() {
+        let table = parse(
+            r#"
+            s = "hi"
+            i = 42
+            f = 1.5
+            b = true
+            d = 1979-05-27
+            t = 07:32:00
+            ldt = 1979-05-27T07:32:00
+            odt = 1979-05-27T07:32:00+01:00
+            a = [1, "two"]
+            [tbl]
+            x = { type = "not", value = "tagged", extra = 1 }
+            "#,
+        )
+        .unwrap();
+        let json = table_to_json(&table);
+        assert_eq!(json["i"], json!({"type": "integer", "value": "42"}));
+        assert_eq!(json["ldt"]["type"], "datetime-local");
+        assert_eq!(json["t"]["type"], "time-local");
+        assert_eq!(table_from_json(&json).unwrap(), table);
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert!(from_json(&json!(1)).is_err());
+        assert!(from_json(&json!({"type": "integer", "value": "x"})).is_err());
+        assert!(from_json(&json!({"type": "date-local", "value": "07:32:00"})).is_err());
+        assert!(from_json(&json!({"type": "color", "value": "red"})).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+//! Helpers shared by the tests of several modules.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// A directory of files that is removed when dropped.
+pub(crate) struct TempDir(pub PathBuf);
+
+impl TempDir {
+    /// Creates the directory `toml-NAME-PID` in the temporary directory, holding `files` as
+    /// pairs of relative paths and contents.
+    pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("toml-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, text) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
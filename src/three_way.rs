@@ -0,0 +1,584 @@
+//! Three-way merging of documents, as done by version control when two branches edit the same
+//! file.
+//!
+//! The merge works on values rather than lines: a key edited on one side only takes that side's
+//! value, wherever it's written, and only keys both sides changed differently conflict. The
+//! result is "ours" with "theirs" changes applied to it, so its formatting is kept and theirs
+//! values are written in the usual style of `DocumentMut`.
+//!
+//! Tables merge key by key. Arrays are replaced as a whole, except that items appended on both
+//! sides are all kept, and arrays identified by a key with `ThreeWay::identify` merge item by
+//! item.
+//!
+//! ```
+//! use toml_rust::three_way::ThreeWay;
+//!
+//! let base = "[package]\nname = \"app\"\nversion = \"1.0.0\"\n\n[dependencies]\nserde = \"1\"\n";
+//! let ours = "[package]\nname = \"app\"\nversion = \"1.1.0\"\n\n[dependencies]\nserde = \"1\"\n";
+//! let theirs = "[package]\nname = \"app\"\nversion = \"1.0.0\"\n\n[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n";
+//! let merged = ThreeWay::new().run(base, ours, theirs).unwrap();
+//! assert!(merged.is_clean());
+//! assert_eq!(
+//!     merged.document().as_str(),
+//!     "[package]\nname = \"app\"\nversion = \"1.1.0\"\n\n[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n"
+//! );
+//! ```
+
+use crate::document::{with_newline, DocumentMut};
+use crate::error::Result;
+use crate::patch::{Op, Patch};
+use crate::path::{ByKeys, Path, Segment};
+use crate::ser;
+use crate::value::{Table, Value};
+use std::collections::BTreeSet;
+
+/// A value both sides changed in different ways.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    path: Path,
+    base: Option<Value>,
+    ours: Option<Value>,
+    theirs: Option<Value>,
+}
+
+impl Conflict {
+    /// Returns the path of the value in ours.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the value in the base, unless both sides added it.
+    pub fn base(&self) -> Option<&Value> {
+        self.base.as_ref()
+    }
+
+    /// Returns our value, unless we removed it.
+    pub fn ours(&self) -> Option<&Value> {
+        self.ours.as_ref()
+    }
+
+    /// Returns their value, unless they removed it.
+    pub fn theirs(&self) -> Option<&Value> {
+        self.theirs.as_ref()
+    }
+}
+
+/// The result of a three-way merge.
+#[derive(Clone, Debug)]
+pub struct Merged {
+    document: DocumentMut,
+    theirs: DocumentMut,
+    conflicts: Vec<Conflict>,
+}
+
+impl Merged {
+    /// Returns the merged document. Conflicting values are left as they are in ours.
+    pub fn document(&self) -> &DocumentMut {
+        &self.document
+    }
+
+    pub fn into_document(self) -> DocumentMut {
+        self.document
+    }
+
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    /// Returns the merged text with git-style conflict markers around each conflict, which makes
+    /// it invalid TOML until they're resolved. Conflicts on a key/value pair of ours are marked
+    /// where it's written, with theirs written as in their document. Others are written as
+    /// dotted key/value pairs at the start of the text, or, inside arrays of tables, at the end,
+    /// relative to the item they're in, after a comment naming it. Lines end as ours' do.
+    pub fn with_markers(&self) -> String {
+        let text = self.document.as_str();
+        let newline = self.document.newline();
+        let theirs_text = self.theirs.as_str();
+        let mut in_place = Vec::new();
+        let mut at_start = String::new();
+        let mut at_end = String::new();
+        for conflict in &self.conflicts {
+            match self.document.entry_lines(&conflict.path) {
+                Some((lines, value)) => {
+                    let theirs = match &conflict.theirs {
+                        Some(v) => {
+                            // Ours' key, with theirs' value and whatever follows it.
+                            let mut line = text[lines.lo()..value.lo()].to_string();
+                            match self.theirs.entry_lines(&conflict.path) {
+                                Some((their_lines, their_value)) => line.push_str(
+                                    theirs_text[their_value.lo()..their_lines.hi()]
+                                        .trim_end_matches(['\r', '\n']),
+                                ),
+                                None => ser::write_value(&mut line, v),
+                            }
+                            line.push('\n');
+                            line
+                        }
+                        None => String::new(),
+                    };
+                    in_place.push((lines, theirs));
+                }
+                None => {
+                    let segments = conflict.path.segments();
+                    let out = match segments.iter().any(|s| matches!(s, Segment::Index(_))) {
+                        true => &mut at_end,
+                        false => &mut at_start,
+                    };
+                    let ours = conflict.ours.as_ref().map(|v| pair(&conflict.path, v));
+                    let theirs = conflict.theirs.as_ref().map(|v| pair(&conflict.path, v));
+                    out.push_str(&markers(
+                        ours.as_deref().unwrap_or(""),
+                        theirs.as_deref().unwrap_or(""),
+                        newline,
+                    ));
+                }
+            }
+        }
+
+        in_place.sort_by_key(|(lines, _)| lines.lo());
+        let mut out = at_start;
+        let mut pos = 0;
+        for (lines, theirs) in in_place {
+            if lines.lo() < pos {
+                continue;
+            }
+            out.push_str(&text[pos..lines.lo()]);
+            let mut ours = text[lines.lo()..lines.hi()].to_string();
+            if !ours.ends_with('\n') {
+                ours.push_str(newline);
+            }
+            out.push_str(&markers(&ours, &theirs, newline));
+            pos = lines.hi();
+        }
+        out.push_str(&text[pos..]);
+        if !at_end.is_empty() {
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push_str(newline);
+            }
+            out.push_str(&at_end);
+        }
+        out
+    }
+}
+
+/// Writes `value` at `path` as a key/value pair: dotted from the root if `path` has no indices,
+/// and otherwise relative to the last array item in it, after a comment naming the item. A
+/// whole item of an array of tables is written as a section of its own.
+fn pair(path: &Path, value: &Value) -> String {
+    let segments = path.segments();
+    let mut out = String::new();
+    match segments
+        .iter()
+        .rposition(|s| matches!(s, Segment::Index(_)))
+    {
+        None => out.push_str(&format!("{} = ", path)),
+        Some(i) if i + 1 < segments.len() => {
+            let item: Path = segments[..=i].iter().cloned().collect();
+            let rest: Path = segments[i + 1..].iter().cloned().collect();
+            out.push_str(&format!("# in {}\n{} = ", item, rest));
+        }
+        Some(i) => {
+            out.push_str(&format!("# {}\n", path));
+            let keys: Option<Vec<&str>> = segments[..i]
+                .iter()
+                .map(|s| match s {
+                    Segment::Key(key) => Some(key.as_str()),
+                    Segment::Index(_) => None,
+                })
+                .collect();
+            if let (Some(mut keys), Value::Table(_)) = (keys, value) {
+                ser::write_section(&mut out, &mut keys, &Value::Array(vec![value.clone()]));
+                return out;
+            }
+        }
+    }
+    ser::write_value(&mut out, value);
+    out.push('\n');
+    out
+}
+
+fn markers(ours: &str, theirs: &str, newline: &str) -> String {
+    let text = format!("<<<<<<< ours\n{}=======\n{}>>>>>>> theirs\n", ours, theirs);
+    with_newline(&text, newline)
+}
+
+/// Merges documents. Without any identities, arrays of tables merge like other arrays.
+#[derive(Clone, Debug, Default)]
+pub struct ThreeWay {
+    identities: ByKeys<String>,
+}
+
+/// What a merge does to ours.
+#[derive(Default)]
+struct Plan {
+    patch: Patch,
+    conflicts: Vec<Conflict>,
+}
+
+impl ThreeWay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches the items of the arrays at `path` by the value of their key `key`, so that items
+    /// are merged with the item of the same identity on the other side, whatever the indices in
+    /// `path`.
+    pub fn identify(&mut self, path: Path, key: impl Into<String>) {
+        self.identities.insert(&path, key.into());
+    }
+
+    /// Merges the changes from `base` to `theirs` into `ours`. Errors in the documents are
+    /// reported as in the source `base`, `ours` or `theirs`.
+    pub fn run(&self, base: &str, ours: &str, theirs: &str) -> Result<Merged> {
+        let base = crate::parse(base).map_err(|e| e.in_source("base"))?;
+        let mut document = DocumentMut::parse(ours).map_err(|e| e.in_source("ours"))?;
+        let theirs = DocumentMut::parse(theirs).map_err(|e| e.in_source("theirs"))?;
+
+        let mut plan = Plan::default();
+        let mut keys = Path::new();
+        self.tables(
+            &mut Path::new(),
+            &mut keys,
+            Some(&base),
+            document.as_table(),
+            theirs.as_table(),
+            &mut plan,
+        );
+        plan.patch.apply(&mut document)?;
+        Ok(Merged {
+            document,
+            theirs,
+            conflicts: plan.conflicts,
+        })
+    }
+
+    fn tables(
+        &self,
+        path: &mut Path,
+        keys: &mut Path,
+        base: Option<&Table>,
+        ours: &Table,
+        theirs: &Table,
+        plan: &mut Plan,
+    ) {
+        let all: BTreeSet<&String> = ours.keys().chain(theirs.keys()).collect();
+        for key in all {
+            path.push(key.as_str());
+            keys.push(key.as_str());
+            let base = base.and_then(|t| t.get(key));
+            self.value(path, keys, base, ours.get(key), theirs.get(key), plan);
+            path.pop();
+            keys.pop();
+        }
+    }
+
+    fn value(
+        &self,
+        path: &mut Path,
+        keys: &mut Path,
+        base: Option<&Value>,
+        ours: Option<&Value>,
+        theirs: Option<&Value>,
+        plan: &mut Plan,
+    ) {
+        if same(ours, theirs) || same(base, theirs) {
+            return;
+        }
+        match (ours, theirs) {
+            (Some(Value::Table(o)), Some(Value::Table(t))) => {
+                let b = base.and_then(Value::as_table);
+                return self.tables(path, keys, b, o, t, plan);
+            }
+            (Some(Value::Array(o)), Some(Value::Array(t))) => {
+                let b = base.and_then(Value::as_array).map(Vec::as_slice);
+                if let Some(id) = self.identities.get(keys) {
+                    if let Some(()) = self.items_by_identity(path, keys, id, b, o, t, plan) {
+                        return;
+                    }
+                }
+                // Only they changed an array of tables, which is edited item by item so that its
+                // sections stay where they are.
+                let tables =
+                    |items: &[Value]| !items.is_empty() && items.iter().all(Value::is_table);
+                if same(base, ours) && tables(o) && tables(t) {
+                    for (i, (o, t)) in o.iter().zip(t).enumerate() {
+                        path.push(i);
+                        self.value(path, keys, Some(o), Some(o), Some(t), plan);
+                        path.pop();
+                    }
+                    for item in t.iter().skip(o.len()) {
+                        plan.patch.push(Op::Append {
+                            path: path.clone(),
+                            value: item.clone(),
+                        });
+                    }
+                    for i in (t.len()..o.len()).rev() {
+                        plan.patch.push(Op::Remove { path: path.join(i) });
+                    }
+                    return;
+                }
+                if let Some(b) = b {
+                    // Items appended on both sides are all kept.
+                    if !same(base, ours) && o.starts_with(b) && t.starts_with(b) {
+                        for item in &t[b.len()..] {
+                            plan.patch.push(Op::Append {
+                                path: path.clone(),
+                                value: item.clone(),
+                            });
+                        }
+                        return;
+                    }
+                }
+            }
+            _ => {}
+        }
+        if !same(base, ours) {
+            plan.conflicts.push(Conflict {
+                path: path.clone(),
+                base: base.cloned(),
+                ours: ours.cloned(),
+                theirs: theirs.cloned(),
+            });
+            return;
+        }
+        plan.patch.push(match theirs {
+            Some(value) => Op::Set {
+                path: path.clone(),
+                value: value.clone(),
+            },
+            None => Op::Remove { path: path.clone() },
+        });
+    }
+
+    /// Merges arrays whose items are tables identified by their key `id`, or returns `None` if
+    /// some items aren't.
+    #[allow(clippy::too_many_arguments)]
+    fn items_by_identity(
+        &self,
+        path: &mut Path,
+        keys: &mut Path,
+        id: &str,
+        base: Option<&[Value]>,
+        ours: &[Value],
+        theirs: &[Value],
+        plan: &mut Plan,
+    ) -> Option<()> {
+        let identity = |item: &Value| match item.get(id)? {
+            Value::Table(_) | Value::Array(_) => None,
+            value => Some(value.clone()),
+        };
+        let base = base.unwrap_or_default();
+        let all = base.iter().chain(ours).chain(theirs);
+        if all.clone().any(|item| identity(item).is_none()) {
+            return None;
+        }
+        let find = |items: &'_ [Value], wanted: &Value| {
+            let found = items
+                .iter()
+                .find(|item| identity(item).as_ref() == Some(wanted));
+            found.cloned()
+        };
+
+        let mut removed = Vec::new();
+        for (i, o) in ours.iter().enumerate() {
+            let wanted = identity(o)?;
+            let (b, t) = (find(base, &wanted), find(theirs, &wanted));
+            path.push(i);
+            match (&b, &t) {
+                // Removed by them, so it goes unless we changed it.
+                (Some(b), None) if same(Some(b), Some(o)) => removed.push(i),
+                (_, Some(_)) | (None, None) => {
+                    self.value(path, keys, b.as_ref(), Some(o), t.as_ref(), plan)
+                }
+                (Some(_), None) => plan.conflicts.push(Conflict {
+                    path: path.clone(),
+                    base: b.clone(),
+                    ours: Some(o.clone()),
+                    theirs: None,
+                }),
+            }
+            path.pop();
+        }
+        for t in theirs {
+            let wanted = identity(t)?;
+            if find(ours, &wanted).is_some() {
+                continue;
+            }
+            match find(base, &wanted) {
+                // Removed by us, and unchanged by them.
+                Some(b) if same(Some(&b), Some(t)) => {}
+                Some(b) => plan.conflicts.push(Conflict {
+                    path: path.join(ours.len()),
+                    base: Some(b),
+                    ours: None,
+                    theirs: Some(t.clone()),
+                }),
+                None => plan.patch.push(Op::Append {
+                    path: path.clone(),
+                    value: t.clone(),
+                }),
+            }
+        }
+        // Removals go last and from the end, so the indices of the other edits stay valid.
+        for i in removed.into_iter().rev() {
+            plan.patch.push(Op::Remove { path: path.join(i) });
+        }
+        Some(())
+    }
+}
+
+/// Compares values, treating every NaN as the same value.
+fn same(a: Option<&Value>, b: Option<&Value>) -> bool {
+    match (a, b) {
+        (Some(Value::Float(a)), Some(Value::Float(b))) => a == b || (a.is_nan() && b.is_nan()),
+        (Some(Value::Array(a)), Some(Value::Array(b))) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(Some(a), Some(b)))
+        }
+        (Some(Value::Table(a)), Some(Value::Table(b))) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| same(Some(v), b.get(k)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, ours: &str, theirs: &str) -> Merged {
+        ThreeWay::new().run(base, ours, theirs).unwrap()
+    }
+
+    #[test]
+    fn three_way_success() {
+        // Edits on nearby lines that a line-based merge would see as a conflict.
+        let base = "# deps\n[dependencies]\na = \"1\"\nb = \"1\"\nc = \"1\"\n";
+        let ours = "# deps\n[dependencies]\na = \"2\"  # bumped\nb = \"1\"\nc = \"1\"\n";
+        let theirs = "# deps\n[dependencies]\na = \"1\"\nb = \"3\"\n";
+        let merged = merge(base, ours, theirs);
+        assert!(merged.is_clean());
+        assert_eq!(
+            merged.document().as_str(),
+            "# deps\n[dependencies]\na = \"2\"  # bumped\nb = \"3\"\n"
+        );
+
+        // The same change on both sides, and reordering, aren't conflicts.
+        let merged = merge("x = 1\ny = 2\n", "y = 2\nx = 3\n", "x = 3\ny = 2\n");
+        assert!(merged.is_clean());
+        assert_eq!(merged.document().as_str(), "y = 2\nx = 3\n");
+
+        // Tables added or removed by them.
+        let base = "[a]\nk = 1\n\n[c]\nm = 1\n";
+        let merged = merge(
+            base,
+            "[a]\nk = 1\n\n[c]\nm = 2\n",
+            "[c]\nm = 1\n\n[b]\nk = 1\n",
+        );
+        assert!(merged.is_clean());
+        assert_eq!(merged.document().as_str(), "[c]\nm = 2\n\n[b]\nk = 1\n");
+
+        // Appends on both sides.
+        let merged = merge("v = [1]\n", "v = [1, 2]\n", "v = [1, 3]\n");
+        assert_eq!(merged.document().as_str(), "v = [1, 2, 3]\n");
+
+        // Arrays of tables merged by identity.
+        let base = "[[bin]]\nname = \"a\"\npath = \"a.rs\"\n\n[[bin]]\nname = \"b\"\n";
+        let ours = "[[bin]]\nname = \"b\"\n\n[[bin]]\nname = \"a\"\npath = \"src/a.rs\"\n";
+        let theirs =
+            "[[bin]]\nname = \"a\"\npath = \"a.rs\"\ntest = false\n\n[[bin]]\nname = \"c\"\n";
+        let mut three_way = ThreeWay::new();
+        three_way.identify("bin".parse().unwrap(), "name");
+        let merged = three_way.run(base, ours, theirs).unwrap();
+        assert!(merged.is_clean(), "{:?}", merged.conflicts());
+        assert_eq!(
+            merged.document().as_str(),
+            "[[bin]]\nname = \"a\"\npath = \"src/a.rs\"\ntest = false\n\n[[bin]]\nname = \"c\"\n"
+        );
+
+        // Arrays of tables only they changed keep their sections where they are.
+        let base = "[[p]]\nn = 1\n\n[[p]]\nn = 2\n\n[x]\nk = 1\n";
+        let ours = "[[p]]\nn = 1 # one\n\n[[p]]\nn = 2\n\n[x]\nk = 2\n";
+        let theirs = "[[p]]\nn = 1\n\n[[p]]\nn = 3\nm = 1\n\n[x]\nk = 1\n";
+        let merged = merge(base, ours, theirs);
+        assert!(merged.is_clean(), "{:?}", merged.conflicts());
+        assert_eq!(
+            merged.document().as_str(),
+            "[[p]]\nn = 1 # one\n\n[[p]]\nn = 3\nm = 1\n\n[x]\nk = 2\n"
+        );
+        let merged = merge(base, ours, "[[p]]\nn = 1\n\n[x]\nk = 1\n");
+        assert_eq!(
+            merged.document().as_str(),
+            "[[p]]\nn = 1 # one\n\n[x]\nk = 2\n"
+        );
+    }
+
+    #[test]
+    fn three_way_conflicts() {
+        let base = "[server]\nport = 80\nhost = \"a\"\n";
+        let ours = "[server]\nport = 81 # ours\nhost = \"b\"\n";
+        let theirs = "[server]\nport = 82 # theirs\nhost = \"b\"\nt = 1\n";
+        let merged = merge(base, ours, theirs);
+        assert_eq!(merged.conflicts().len(), 1);
+        let conflict = &merged.conflicts()[0];
+        assert_eq!(conflict.path().to_string(), "server.port");
+        assert_eq!(conflict.base(), Some(&Value::Integer(80)));
+        assert_eq!(conflict.ours(), Some(&Value::Integer(81)));
+        assert_eq!(conflict.theirs(), Some(&Value::Integer(82)));
+        assert_eq!(
+            merged.document().as_str(),
+            "[server]\nport = 81 # ours\nhost = \"b\"\nt = 1\n"
+        );
+        assert_eq!(
+            merged.with_markers(),
+            "[server]\n<<<<<<< ours\nport = 81 # ours\n=======\nport = 82 # theirs\n\
+             >>>>>>> theirs\nhost = \"b\"\nt = 1\n"
+        );
+
+        // Markers end their lines as ours do.
+        let crlf = |text: &str| text.replace('\n', "\r\n");
+        let merged = merge(base, &crlf(ours), theirs);
+        assert_eq!(
+            merged.with_markers(),
+            crlf(
+                "[server]\n<<<<<<< ours\nport = 81 # ours\n=======\nport = 82 # theirs\n\
+                 >>>>>>> theirs\nhost = \"b\"\nt = 1\n"
+            )
+        );
+
+        // Removed on one side and changed on the other.
+        let merged = merge("a = 1\nb = { c = 1 }\n", "a = 2\n", "b = { c = 2 }\n");
+        assert_eq!(merged.conflicts().len(), 2);
+        assert_eq!(
+            merged.with_markers(),
+            "<<<<<<< ours\n=======\nb = { c = 2 }\n>>>>>>> theirs\n\
+             <<<<<<< ours\na = 2\n=======\n>>>>>>> theirs\n"
+        );
+
+        // Inside arrays of tables, pairs are written relative to their item.
+        let mut three_way = ThreeWay::new();
+        three_way.identify("bin".parse().unwrap(), "name");
+        let merged = three_way
+            .run(
+                "[[bin]]\nname = 'a'\npath = 'a.rs'\n",
+                "[[bin]]\nname = 'a'\n",
+                "[[bin]]\nname = 'a'\npath = 'b.rs'\n",
+            )
+            .unwrap();
+        assert_eq!(
+            merged.with_markers(),
+            "[[bin]]\nname = 'a'\n\
+             <<<<<<< ours\n=======\n# in bin[0]\npath = \"b.rs\"\n>>>>>>> theirs\n"
+        );
+
+        // Different appends to an array that was also edited.
+        let merged = merge("v = [1, 2]\n", "v = [2]\n", "v = [1, 2, 3]\n");
+        assert_eq!(merged.conflicts()[0].path().to_string(), "v");
+
+        let err = ThreeWay::new().run("a = 1", "a = ", "a = 1").unwrap_err();
+        assert_eq!(err.to_string(), "ours: Unexpected { pos: 4 }");
+    }
+}
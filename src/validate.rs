@@ -0,0 +1,230 @@
+//! Checks a document without keeping its symbols or building a tree of values.
+//!
+//! The `Validator` is the lexer's symbol sink, so it sees each symbol as soon as it's scanned.
+//! Scalars are decoded and dropped straight away, and arrays and inline tables are reduced to a
+//! placeholder once they're closed, so all that's kept is the skeleton of keys needed to spot
+//! duplicates.
+
+use crate::error::{Error, Result};
+use crate::lex::{self, Lex, Span, Sym, Symbol, SymbolSink};
+use crate::parse::{self, Node, NodeKind, TableKind, TableNode};
+use std::mem;
+
+type Keys = Vec<(String, Span)>;
+
+/// Key segments read so far in a table or inline table. Once the `=` has been seen they name
+/// the value that's coming.
+#[derive(Default)]
+struct Pending {
+    keys: Keys,
+    assigned: bool,
+}
+
+/// An array or inline table whose contents are still arriving.
+enum Frame {
+    Array,
+    InlineTable { table: TableNode, pending: Pending },
+}
+
+pub(crate) struct Validator<'a> {
+    text: &'a str,
+    root: TableNode,
+    /// Path of the table named by the last header.
+    current: Vec<String>,
+    /// Whether the header being read is an array of tables, while one is being read.
+    header: Option<bool>,
+    pending: Pending,
+    stack: Vec<Frame>,
+    error: Option<Error>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            root: TableNode::new(TableKind::Implicit),
+            current: Vec::new(),
+            header: None,
+            pending: Pending::default(),
+            stack: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Returns the first error found.
+    pub fn finish(self) -> Result<()> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// The keys of the innermost table or inline table.
+    fn pending(&mut self) -> Option<&mut Pending> {
+        match self.stack.last_mut() {
+            None => Some(&mut self.pending),
+            Some(Frame::InlineTable { pending, .. }) => Some(pending),
+            Some(Frame::Array) => None,
+        }
+    }
+
+    fn check(&mut self, symbol: Symbol) -> Result<()> {
+        let unexpected = || {
+            Error::Lex(lex::Error::Unexpected {
+                pos: symbol.span().lo(),
+            })
+        };
+
+        match symbol.sym() {
+            Sym::Table | Sym::ArrayOfTable => {
+                self.header = Some(symbol.sym() == Sym::ArrayOfTable);
+                self.pending = Pending::default();
+            }
+            Sym::TableEnd => {
+                let is_array = self.header.take().ok_or_else(unexpected)?;
+                let keys = mem::take(&mut self.pending.keys);
+                let (last, prefix) = keys.split_last().ok_or_else(unexpected)?;
+                parse::define_header(&mut self.root, prefix, last, is_array)?;
+                self.current = keys.into_iter().map(|(key, _)| key).collect();
+            }
+            Sym::Assign => {
+                let pending = self.pending().ok_or_else(unexpected)?;
+                if pending.keys.is_empty() || pending.assigned {
+                    return Err(unexpected());
+                }
+                pending.assigned = true;
+            }
+            Sym::Key | Sym::String if self.pending().is_some_and(|p| !p.assigned) => {
+                let key = parse::parse_key(self.text, symbol)?;
+                if let Some(pending) = self.pending() {
+                    pending.keys.push((key, symbol.span()));
+                }
+            }
+            Sym::Array => self.stack.push(Frame::Array),
+            Sym::InlineTable => self.stack.push(Frame::InlineTable {
+                table: TableNode::new(TableKind::Inline),
+                pending: Pending::default(),
+            }),
+            Sym::ArrayEnd => match self.stack.pop() {
+                Some(Frame::Array) => self.complete(symbol.span())?,
+                _ => return Err(unexpected()),
+            },
+            Sym::InlineTableEnd => match self.stack.pop() {
+                Some(Frame::InlineTable { pending, .. }) if !pending.assigned => {
+                    self.complete(symbol.span())?
+                }
+                _ => return Err(unexpected()),
+            },
+            Sym::Eof => {}
+            _ => {
+                parse::parse_scalar(self.text, symbol)?;
+                self.complete(symbol.span())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a value that has been read in full in the innermost table or inline table.
+    fn complete(&mut self, span: Span) -> Result<()> {
+        // Values that have been closed can't be extended, which is all that matters from now
+        // on, so an empty inline table stands in for all of them.
+        let node = Node {
+            span,
+            kind: NodeKind::Table(TableNode::new(TableKind::Inline)),
+        };
+
+        let (table, pending) = match self.stack.last_mut() {
+            Some(Frame::Array) => return Ok(()),
+            Some(Frame::InlineTable { table, pending }) => (table, pending),
+            None => match parse::lookup(&mut self.root, &self.current) {
+                Some(table) => (table, &mut self.pending),
+                None => return Err(Error::Lex(lex::Error::Unexpected { pos: span.lo() })),
+            },
+        };
+
+        let keys = mem::take(pending).keys;
+        match keys.split_last() {
+            Some((last, prefix)) => parse::insert_keyval(table, prefix, last, node),
+            None => Err(Error::Lex(lex::Error::Unexpected { pos: span.lo() })),
+        }
+    }
+}
+
+impl SymbolSink for Validator<'_> {
+    /// Checks the next symbol. After the first error everything else is ignored.
+    fn push(&mut self, symbol: Symbol) {
+        if self.error.is_none() {
+            if let Err(e) = self.check(symbol) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+/// Checks that `text` is a valid TOML document, returning the same error `parse` would.
+///
+/// Symbols are checked as they're scanned rather than collected, and values are checked and
+/// then dropped, so only the keys of the document are held in memory.
+pub fn validate(text: &str) -> Result<()> {
+    let mut lex = Lex::with_sink(text, Validator::new(text));
+    lex.scan()?;
+    lex.symbols.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `validate` agrees with `parse`, down to the error.
+    fn agrees(text: &str) -> Result<()> {
+        let expected = crate::parse(text).map(drop);
+        let result = validate(text);
+        assert_eq!(result, expected, "{:?}", text);
+        result
+    }
+
+    #[test]
+    fn validate_success() {
+        for text in [
+            "",
+            "a = 1\nb = 'two'\nc.d = [1, [2, {e = 3}], 1979-05-27]",
+            "[a]\nb = 1\n[a.c]\nd = { e.f = \"\\u00e9\" }",
+            "[[a]]\nb = 1\n[[a]]\nb = 2\n[a.c]\n[[a.d]]",
+            "[a.b.c]\n[a]\nd = 1",
+            "a = { b = [ { c = 1 }, { c = 2 } ] }\nd = []\ne = {}",
+            "'quoted key'.\"and another\" = \"\"\"\nmulti\\\n  line\"\"\"",
+        ] {
+            agrees(text).unwrap();
+        }
+    }
+
+    #[test]
+    fn validate_fail() {
+        for text in [
+            "a = 1\na = 2",
+            "a = 1\n'a' = 2",
+            "a.b = 1\na = 2",
+            "a = {}\na.b = 1",
+            "a = { b = 1, b = 2 }",
+            "a = [{ b = 1, b = 2 }]",
+            "a = [1, { b = 1, c.d = 1, c = 2 }]",
+            "[a]\n[a]",
+            "a = 1\n[a]",
+            "a = []\n[[a]]",
+            "[[a]]\n[a]",
+            "[a]\nb.c = 1\n[a.b]",
+            "a = { b = 1 }\n[a.c]",
+            "a = \"\\q\"",
+            "a = 'ctrl \u{1}'",
+            "a = 9223372036854775808",
+            "a = 1979-02-29",
+            "a = [1, \"\\x\"]",
+            "\"\\q\" = 1",
+            "[\"\\q\"]",
+            "a = { b = 1 } x",
+            "a = 1\nb = 0x_1\na = 2",
+        ] {
+            assert!(agrees(text).is_err(), "{:?}", text);
+        }
+    }
+}
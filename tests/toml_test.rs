@@ -0,0 +1,166 @@
+//! Runs the decoder and encoder against a corpus laid out like the toml-test suite.
+//!
+//! By default the small corpus in `tests/toml-test` is used. Point `TOML_TEST_DIR` at a checkout
+//! of https://github.com/toml-lang/toml-test/tree/main/tests to run the full suite.
+
+use serde_json::Value as Json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn corpus_dir() -> PathBuf {
+    match std::env::var_os("TOML_TEST_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/toml-test"),
+    }
+}
+
+fn collect(dir: &Path, ext: &str, out: &mut Vec<PathBuf>) {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("reading {}: {}", dir.display(), e))
+        .map(|e| e.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect(&path, ext, out);
+        } else if path.extension().is_some_and(|e| e == ext) {
+            out.push(path);
+        }
+    }
+}
+
+/// Compares tagged JSON, treating floats and datetimes by value rather than spelling.
+fn tagged_eq(a: &Json, b: &Json) -> bool {
+    match (a, b) {
+        (Json::Array(a), Json::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| tagged_eq(a, b))
+        }
+        (Json::Object(a), Json::Object(b)) => {
+            if let (Some(ty), Some(Json::String(x)), Some(Json::String(y))) =
+                (a.get("type"), a.get("value"), b.get("value"))
+            {
+                if a.len() == 2 && b.get("type") == Some(ty) {
+                    return match ty.as_str() {
+                        Some("float") => {
+                            let (x, y) = (parse_float(x), parse_float(y));
+                            x == y || (x.is_nan() && y.is_nan())
+                        }
+                        Some("datetime" | "datetime-local" | "date-local" | "time-local") => {
+                            x.parse::<toml_rust::Datetime>().ok()
+                                == y.parse::<toml_rust::Datetime>().ok()
+                        }
+                        _ => x == y,
+                    };
+                }
+            }
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(k, v)| b.get(k).is_some_and(|w| tagged_eq(v, w)))
+        }
+        _ => a == b,
+    }
+}
+
+fn parse_float(s: &str) -> f64 {
+    match s {
+        "inf" | "+inf" => f64::INFINITY,
+        "-inf" => f64::NEG_INFINITY,
+        "nan" | "+nan" | "-nan" => f64::NAN,
+        _ => s.parse().unwrap(),
+    }
+}
+
+#[test]
+fn valid() {
+    let mut files = Vec::new();
+    collect(&corpus_dir().join("valid"), "toml", &mut files);
+    assert!(!files.is_empty());
+
+    let mut failures = Vec::new();
+    for path in &files {
+        let text = fs::read_to_string(path).unwrap();
+        let expected: Json =
+            serde_json::from_str(&fs::read_to_string(path.with_extension("json")).unwrap())
+                .unwrap();
+
+        // Decode.
+        let table = match toml_rust::parse(&text) {
+            Ok(table) => table,
+            Err(e) => {
+                failures.push(format!("{}: decode failed: {}", path.display(), e));
+                continue;
+            }
+        };
+        if let Err(e) = toml_rust::validate(&text) {
+            failures.push(format!("{}: validate failed: {}", path.display(), e));
+            continue;
+        }
+        match toml_rust::cst::parse(&text) {
+            Ok(tree) if tree.root().tokens().map(|t| t.text()).collect::<String>() == text => {}
+            Ok(_) => failures.push(format!("{}: syntax tree lost text", path.display())),
+            Err(e) => failures.push(format!("{}: syntax tree failed: {}", path.display(), e)),
+        }
+        let decoded = toml_rust::tagged::table_to_json(&table);
+        if !tagged_eq(&decoded, &expected) {
+            failures.push(format!(
+                "{}: decoded\n  {}\nexpected\n  {}",
+                path.display(),
+                decoded,
+                expected
+            ));
+            continue;
+        }
+
+        // Encode the expected JSON and check it decodes back to the same thing.
+        let encoded = toml_rust::tagged::table_from_json(&expected)
+            .and_then(|table| toml_rust::to_string(&table));
+        match encoded.and_then(|text| toml_rust::parse(&text)) {
+            Ok(table) if tagged_eq(&toml_rust::tagged::table_to_json(&table), &expected) => {}
+            Ok(table) => failures.push(format!(
+                "{}: encoder round trip produced {:?}",
+                path.display(),
+                table
+            )),
+            Err(e) => failures.push(format!("{}: encode failed: {}", path.display(), e)),
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} valid tests failed:\n{}",
+            failures.len(),
+            files.len(),
+            failures.join("\n")
+        );
+    }
+}
+
+#[test]
+fn invalid() {
+    let mut files = Vec::new();
+    collect(&corpus_dir().join("invalid"), "toml", &mut files);
+    assert!(!files.is_empty());
+
+    let failures: Vec<_> = files
+        .iter()
+        .filter(|path| {
+            // Invalid tests may contain invalid UTF-8, which is rejected before parsing.
+            match fs::read(path).map(String::from_utf8) {
+                Ok(Ok(text)) => {
+                    toml_rust::parse(&text).is_ok() || toml_rust::validate(&text).is_ok()
+                }
+                _ => false,
+            }
+        })
+        .map(|path| path.display().to_string())
+        .collect();
+
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} invalid tests were accepted:\n{}",
+            failures.len(),
+            files.len(),
+            failures.join("\n")
+        );
+    }
+}